@@ -1,3 +1,5 @@
+use std::path::Path;
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let out_dir = std::env::var("OUT_DIR")?;
     
@@ -9,5 +11,33 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         )?;
     
     println!("cargo:rerun-if-changed=proto/");
+
+    embed_migrations(&out_dir)?;
     Ok(())
-}
\ No newline at end of file
+}
+
+/// List the `.surql` files in `migrations/` for `MigrationManager::load_embedded`.
+fn embed_migrations(out_dir: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = Path::new(&std::env::var("CARGO_MANIFEST_DIR")?).join("migrations");
+    let mut files = Vec::new();
+    if dir.is_dir() {
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "surql") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+
+    let mut code = String::from("&[\n");
+    for path in &files {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        code.push_str(&format!("    ({:?}, include_str!({:?})),\n", name, path.display().to_string()));
+    }
+    code.push_str("]\n");
+    std::fs::write(Path::new(out_dir).join("embedded_migrations.rs"), code)?;
+
+    println!("cargo:rerun-if-changed=migrations/");
+    Ok(())
+}
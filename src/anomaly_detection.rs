@@ -9,6 +9,25 @@ pub struct QueryMetrics {
     pub timestamp: SystemTime,
 }
 
+/// Anomaly detection settings loaded from the application config
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnomalyDetectionConfig {
+    /// Number of recent queries the baseline is computed over
+    pub window_size: usize,
+    /// A query is anomalous when it exceeds the baseline by this factor
+    pub threshold_multiplier: f64,
+}
+
+impl Default for AnomalyDetectionConfig {
+    fn default() -> Self {
+        Self {
+            window_size: 100,
+            threshold_multiplier: 2.0,
+        }
+    }
+}
+
 pub struct AnomalyDetector {
     window_size: usize,
     metrics_history: VecDeque<QueryMetrics>,
@@ -24,6 +43,20 @@ impl AnomalyDetector {
         }
     }
 
+    pub fn from_config(config: &AnomalyDetectionConfig) -> Self {
+        Self::new(config.window_size, config.threshold_multiplier)
+    }
+
+    /// Apply new thresholds while keeping the recorded history. A smaller
+    /// window drops the oldest samples.
+    pub fn apply_config(&mut self, config: &AnomalyDetectionConfig) {
+        self.window_size = config.window_size;
+        self.threshold_multiplier = config.threshold_multiplier;
+        while self.metrics_history.len() > self.window_size {
+            self.metrics_history.pop_front();
+        }
+    }
+
     pub fn record_metrics(&mut self, metrics: QueryMetrics) {
         if self.metrics_history.len() >= self.window_size {
             self.metrics_history.pop_front();
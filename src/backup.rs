@@ -0,0 +1,1141 @@
+// Path: src/backup.rs
+
+use crate::db::{escape_ident, info_section, DatabaseError, DatabaseManager};
+use crate::pool::PooledConnection;
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use surrealdb::sql::{Array, Thing, Value};
+use thiserror::Error;
+use tracing::info;
+
+/// Layout version written to the manifest. Archives with a newer version
+/// are rejected on restore.
+pub const FORMAT_VERSION: u32 = 2;
+
+const MANIFEST: &str = "manifest.json";
+const SCHEMA: &str = "schema.surql";
+
+#[derive(Debug, Error)]
+pub enum BackupError {
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid backup archive: {0}")]
+    InvalidArchive(String),
+
+    #[error("Checksum mismatch for {0}")]
+    ChecksumMismatch(String),
+
+    #[error("Invalid backup chain: {0}")]
+    InvalidChain(String),
+
+    #[error("Invalid restore target: {0}")]
+    InvalidTarget(String),
+}
+
+impl From<surrealdb::Error> for BackupError {
+    fn from(error: surrealdb::Error) -> Self {
+        BackupError::Database(DatabaseError::from(error))
+    }
+}
+
+pub type BackupResult<T> = Result<T, BackupError>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupKind {
+    #[default]
+    Full,
+    /// Only the changes since the `parent` backup
+    Incremental,
+}
+
+/// How far a table was captured, so the next incremental backup can pick
+/// up from there.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Watermark {
+    /// The last change feed versionstamp included; 0 if the feed was empty
+    ChangeFeed { versionstamp: u64 },
+    /// Records whose `field` is at or after `since` changed after the backup
+    UpdatedAt { field: String, since: DateTime<Utc> },
+}
+
+/// Contents of `manifest.json` at the root of every archive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub format_version: u32,
+    /// Empty for archives written before format version 2
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub kind: BackupKind,
+    /// Id of the backup an incremental builds on
+    #[serde(default)]
+    pub parent: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub namespace: String,
+    pub database: String,
+    pub tables: Vec<TableBackup>,
+    /// Tables of the parent backup that no longer exist
+    #[serde(default)]
+    pub removed_tables: Vec<String>,
+    /// SHA-256 of every other file in the archive, keyed by path
+    pub checksums: BTreeMap<String, String>,
+}
+
+impl BackupManifest {
+    pub fn total_records(&self) -> u64 {
+        self.tables.iter().map(|t| t.records).sum()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableBackup {
+    pub name: String,
+    /// Records, or for incrementals changes, in the data files
+    pub records: u64,
+    /// Data files in the order they are restored
+    pub files: Vec<DataFile>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watermark: Option<Watermark>,
+}
+
+/// SurrealQL writing up to the chunk size records.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DataFile {
+    pub path: String,
+    pub records: u64,
+}
+
+/// A written archive and what it holds.
+#[derive(Debug, Clone)]
+pub struct BackupArchive {
+    pub path: PathBuf,
+    pub manifest: BackupManifest,
+}
+
+/// Where to restore an archive. `database` is required and must not be the
+/// database the manager is serving, so a restore that fails halfway never
+/// leaves live data dropped; an unset `namespace` keeps the archive's.
+#[derive(Debug, Clone, Default)]
+pub struct RestoreOptions {
+    pub namespace: Option<String>,
+    pub database: Option<String>,
+}
+
+/// Reported after each data file is restored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RestoreProgress {
+    pub table: String,
+    pub records_restored: u64,
+    pub records_total: u64,
+}
+
+/// A table as found in `INFO FOR DB`
+struct TableInfo {
+    name: String,
+    relation: bool,
+    /// How long the table's change feed keeps changes, if it has one
+    changefeed: Option<std::time::Duration>,
+    fields: BTreeMap<String, String>,
+}
+
+/// Logical backups of the selected database.
+///
+/// An archive is a gzip-compressed tar file holding `schema.surql` with the
+/// analyzer, function, param, table, field, index and event definitions,
+/// SurrealQL files writing the records of every table (including
+/// `migration` and the ML data tables) one chunk at a time, and a manifest
+/// with the checksum of each file. Tables are read page by page while the
+/// database stays online, so the archive is not a point-in-time snapshot of
+/// writes made during the backup.
+///
+/// Incremental backups only hold what changed since their parent: the
+/// change feed of tables defined with `CHANGEFEED`, including deletes, and
+/// records of other tables whose `updated_at` field moved past the parent's
+/// start. Deletes in tables without a change feed are not seen this way, and
+/// tables with neither are copied whole every time.
+pub struct BackupManager {
+    db: Arc<DatabaseManager>,
+    chunk_size: usize,
+    watermark_field: String,
+}
+
+impl BackupManager {
+    pub fn new(db: Arc<DatabaseManager>) -> Self {
+        Self {
+            db,
+            chunk_size: 1000,
+            watermark_field: "updated_at".to_string(),
+        }
+    }
+
+    /// Records per data file. Lower it for tables with large records, e.g.
+    /// model weights, to bound the memory used per file.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// The datetime field incremental backups compare for tables without a
+    /// change feed, instead of `updated_at`.
+    pub fn with_watermark_field(mut self, field: impl Into<String>) -> Self {
+        self.watermark_field = field.into();
+        self
+    }
+
+    /// Write a full archive to `path`. If `path` is an existing directory,
+    /// the archive is created inside it under a name with the namespace,
+    /// database and time of the backup. The archive only appears once it
+    /// is complete.
+    pub async fn backup(&self, path: impl AsRef<Path>) -> BackupResult<BackupArchive> {
+        self.write_backup(path.as_ref(), None).await
+    }
+
+    /// Write an archive of the changes since the backup at `base`, which
+    /// may itself be incremental. Tables whose change feed no longer
+    /// retains changes from the time of `base` are copied whole instead.
+    pub async fn backup_incremental(
+        &self,
+        path: impl AsRef<Path>,
+        base: impl AsRef<Path>,
+    ) -> BackupResult<BackupArchive> {
+        let base = self.verify(base).await?;
+        if base.id.is_empty() {
+            return Err(BackupError::InvalidChain(
+                "archives older than format version 2 cannot be the base of an incremental".to_string(),
+            ));
+        }
+        self.write_backup(path.as_ref(), Some(base)).await
+    }
+
+    async fn write_backup(&self, path: &Path, base: Option<BackupManifest>) -> BackupResult<BackupArchive> {
+        let conn = self.db.get_connection().await?;
+        let (namespace, database) = session(&conn).await?;
+        if let Some(base) = &base {
+            if (&base.namespace, &base.database) != (&namespace, &database) {
+                return Err(BackupError::InvalidChain(format!(
+                    "base backup is of {}/{}, not {}/{}",
+                    base.namespace, base.database, namespace, database
+                )));
+            }
+        }
+
+        // Taken before anything is read, so changes made during the backup
+        // are captured again by the next incremental rather than missed
+        let started_at: Option<DateTime<Utc>> = conn.query("RETURN time::now()").await?.take(0)?;
+        let started_at = started_at.unwrap_or_else(Utc::now);
+
+        let path = archive_path(path, &namespace, &database, started_at).await;
+        let staging = Staging::create(PathBuf::from(format!("{}.partial", path.display()))).await?;
+
+        let mut manifest = BackupManifest {
+            format_version: FORMAT_VERSION,
+            id: uuid::Uuid::new_v4().to_string(),
+            kind: if base.is_some() { BackupKind::Incremental } else { BackupKind::Full },
+            parent: base.as_ref().map(|b| b.id.clone()),
+            created_at: started_at,
+            namespace,
+            database,
+            tables: Vec::new(),
+            removed_tables: Vec::new(),
+            checksums: BTreeMap::new(),
+        };
+
+        let mut schema = String::new();
+        for section in ["analyzers", "functions", "params"] {
+            for definition in info_section(&conn, "INFO FOR DB", section).await?.values() {
+                schema.push_str(&format!("{};\n", overwrite(definition)));
+            }
+        }
+        let mut tables = Vec::new();
+        for (name, definition) in info_section(&conn, "INFO FOR DB", "tables").await? {
+            schema.push_str(&format!("{};\n", overwrite(&definition)));
+            let info_query = format!("INFO FOR TABLE {}", escape_ident(&name));
+            let fields = info_section(&conn, &info_query, "fields").await?;
+            for definition in fields.values() {
+                schema.push_str(&format!("{};\n", overwrite(definition)));
+            }
+            for section in ["indexes", "events"] {
+                for definition in info_section(&conn, &info_query, section).await?.values() {
+                    schema.push_str(&format!("{};\n", overwrite(definition)));
+                }
+            }
+            tables.push(TableInfo {
+                relation: definition.contains(" TYPE RELATION"),
+                changefeed: changefeed_retention(&definition),
+                name,
+                fields,
+            });
+        }
+        staging.write(SCHEMA, schema.as_bytes(), &mut manifest.checksums).await?;
+
+        for (position, table) in tables.iter().enumerate() {
+            let previous = base
+                .as_ref()
+                .and_then(|b| b.tables.iter().find(|t| t.name == table.name))
+                .and_then(|t| t.watermark.clone());
+            let mut writer = TableWriter {
+                staging: &staging,
+                checksums: &mut manifest.checksums,
+                position,
+                table,
+                backup: TableBackup {
+                    name: table.name.clone(),
+                    records: 0,
+                    files: Vec::new(),
+                    watermark: None,
+                },
+            };
+
+            // Changes older than the feed's retention are gone, so a feed
+            // that does not reach back to the base cannot be replayed
+            let feed_covers_base = match (table.changefeed, &base) {
+                (Some(retention), Some(base)) => {
+                    let covers = (started_at - base.created_at).to_std().is_ok_and(|age| age <= retention);
+                    if !covers {
+                        info!(
+                            "Change feed of {} no longer reaches back to the base backup, copying the table whole",
+                            table.name
+                        );
+                    }
+                    covers
+                }
+                _ => false,
+            };
+            match (previous, base.is_some()) {
+                (Some(Watermark::ChangeFeed { versionstamp }), _) if feed_covers_base => {
+                    let last = self.copy_changes(&conn, &mut writer, versionstamp).await?;
+                    writer.backup.watermark = Some(Watermark::ChangeFeed { versionstamp: last });
+                }
+                (Some(Watermark::UpdatedAt { field, since }), _)
+                    if table.changefeed.is_none() && table.fields.contains_key(&field) =>
+                {
+                    self.copy_records(&conn, &mut writer, Some((&field, since)), true).await?;
+                    writer.backup.watermark = Some(Watermark::UpdatedAt { field, since: started_at });
+                }
+                (_, incremental) => {
+                    // Anything that cannot be caught up on is copied whole,
+                    // replacing the table's records on restore
+                    writer.backup.watermark = self.watermark(&conn, table, started_at).await?;
+                    if incremental {
+                        writer.push(format!("DELETE {};\n", escape_ident(&table.name)), 0).await?;
+                    }
+                    self.copy_records(&conn, &mut writer, None, incremental).await?;
+                }
+            }
+            manifest.tables.push(writer.backup);
+        }
+
+        if let Some(base) = &base {
+            manifest.removed_tables = base
+                .tables
+                .iter()
+                .filter(|t| !tables.iter().any(|table| table.name == t.name))
+                .map(|t| t.name.clone())
+                .collect();
+        }
+
+        let contents = serde_json::to_vec_pretty(&manifest)
+            .map_err(|e| BackupError::InvalidArchive(e.to_string()))?;
+        staging.write(MANIFEST, &contents, &mut BTreeMap::new()).await?;
+        staging.pack(&path, &manifest).await?;
+
+        info!(
+            "Wrote {:?} backup of {} records in {} tables to {}",
+            manifest.kind,
+            manifest.total_records(),
+            manifest.tables.len(),
+            path.display()
+        );
+        Ok(BackupArchive { path, manifest })
+    }
+
+    /// Where the next incremental backup should start for `table`.
+    async fn watermark(
+        &self,
+        conn: &PooledConnection,
+        table: &TableInfo,
+        started_at: DateTime<Utc>,
+    ) -> BackupResult<Option<Watermark>> {
+        if table.changefeed.is_some() {
+            let mut versionstamp = 0;
+            loop {
+                let (sets, _) = changes_since(conn, &table.name, versionstamp, self.chunk_size).await?;
+                match sets.last() {
+                    Some(&last) => versionstamp = last,
+                    None => break,
+                }
+                if sets.len() < self.chunk_size {
+                    break;
+                }
+            }
+            return Ok(Some(Watermark::ChangeFeed { versionstamp }));
+        }
+
+        if table.fields.contains_key(&self.watermark_field) {
+            return Ok(Some(Watermark::UpdatedAt {
+                field: self.watermark_field.clone(),
+                since: started_at,
+            }));
+        }
+        Ok(None)
+    }
+
+    /// Page through the table in id order, writing each page to its own
+    /// file. `changed` limits it to records whose field is at or after the
+    /// given time.
+    async fn copy_records(
+        &self,
+        conn: &PooledConnection,
+        writer: &mut TableWriter<'_>,
+        changed: Option<(&str, DateTime<Utc>)>,
+        upsert: bool,
+    ) -> BackupResult<()> {
+        let mut conditions = Vec::new();
+        if let Some((field, _)) = changed {
+            conditions.push(format!("{} >= <datetime>$since", escape_ident(field)));
+        }
+
+        let mut after: Option<Thing> = None;
+        loop {
+            let mut conditions = conditions.clone();
+            if after.is_some() {
+                conditions.push("id > $after".to_string());
+            }
+            let where_clause = match conditions.is_empty() {
+                true => String::new(),
+                false => format!(" WHERE {}", conditions.join(" AND ")),
+            };
+            let query = format!("SELECT * FROM type::table($table){} ORDER BY id LIMIT $limit", where_clause);
+
+            let page: surrealdb::Value = conn
+                .query(query)
+                .bind(("table", writer.table.name.clone()))
+                .bind(("after", after.clone()))
+                .bind(("since", changed.map(|(_, since)| since)))
+                .bind(("limit", self.chunk_size))
+                .await?
+                .take(0)?;
+
+            let records = match page.into_inner() {
+                Value::Array(records) if !records.is_empty() => records,
+                _ => break,
+            };
+            after = match records.last().and_then(|record| field(record, "id")) {
+                Some(Value::Thing(id)) => Some(id.clone()),
+                _ => None,
+            };
+
+            let count = records.len();
+            let contents = match upsert {
+                true => records.iter().map(|record| writer.upsert(record)).collect(),
+                false => writer.insert(records),
+            };
+            writer.push(contents, count as u64).await?;
+            if count < self.chunk_size || after.is_none() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Replay the change feed after `versionstamp` as upserts and deletes,
+    /// returning the last versionstamp written.
+    async fn copy_changes(
+        &self,
+        conn: &PooledConnection,
+        writer: &mut TableWriter<'_>,
+        mut versionstamp: u64,
+    ) -> BackupResult<u64> {
+        loop {
+            let (sets, changes) = changes_since(conn, &writer.table.name, versionstamp, self.chunk_size).await?;
+            let Some(&last) = sets.last() else {
+                return Ok(versionstamp);
+            };
+            versionstamp = last;
+
+            let mut contents = String::new();
+            let mut count = 0;
+            for change in &changes {
+                if let Some(record) = field(change, "current").or_else(|| field(change, "update")) {
+                    contents.push_str(&writer.upsert(record));
+                } else if let Some(id) = field(change, "delete").and_then(|deleted| field(deleted, "id")) {
+                    contents.push_str(&format!("DELETE {};\n", id));
+                } else {
+                    continue;
+                }
+                count += 1;
+            }
+            if count > 0 {
+                writer.push(contents, count).await?;
+            }
+            if sets.len() < self.chunk_size {
+                return Ok(versionstamp);
+            }
+        }
+    }
+
+    /// Check an archive's manifest and checksums without restoring it.
+    pub async fn verify(&self, path: impl AsRef<Path>) -> BackupResult<BackupManifest> {
+        Staging::unpack(path.as_ref()).await?.verified_manifest().await
+    }
+
+    pub async fn restore(&self, path: impl AsRef<Path>, options: &RestoreOptions) -> BackupResult<BackupManifest> {
+        self.restore_with_progress(path, options, |_| {}).await
+    }
+
+    /// Restore a full backup, calling `progress` after each data file.
+    /// Nothing is written unless every checksum matches. Tables in the
+    /// archive replace tables of the same name in the target; other tables
+    /// are left alone. A restore that fails partway leaves the target
+    /// partly restored, which is why it cannot be the live database.
+    pub async fn restore_with_progress<F>(
+        &self,
+        path: impl AsRef<Path>,
+        options: &RestoreOptions,
+        progress: F,
+    ) -> BackupResult<BackupManifest>
+    where
+        F: FnMut(&RestoreProgress),
+    {
+        let mut applied = self.restore_chain(&[path], None, options, progress).await?;
+        Ok(applied.remove(0))
+    }
+
+    /// Restore a full backup and replay the incrementals built on it, in
+    /// order, up to the last one taken at or before `until`. `archives` may
+    /// be given in any order and hold more than needed: the chain starts at
+    /// the latest full backup not after `until`. Every archive in the chain
+    /// is verified before anything is written. Returns the manifests applied.
+    pub async fn restore_chain<P, F>(
+        &self,
+        archives: &[P],
+        until: Option<DateTime<Utc>>,
+        options: &RestoreOptions,
+        mut progress: F,
+    ) -> BackupResult<Vec<BackupManifest>>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&RestoreProgress),
+    {
+        let conn = self.db.get_connection().await?;
+        let live = session(&conn).await?;
+        let database = options.database.clone().ok_or_else(|| {
+            BackupError::InvalidTarget("a target database is required; restores never write to the live one".to_string())
+        })?;
+
+        let mut chain = Vec::with_capacity(archives.len());
+        for path in archives {
+            let staging = Staging::unpack(path.as_ref()).await?;
+            let manifest = staging.verified_manifest().await?;
+            if until.is_none_or(|until| manifest.created_at <= until) {
+                chain.push((staging, manifest));
+            }
+        }
+        chain.sort_by_key(|(_, manifest)| manifest.created_at);
+
+        let start = chain
+            .iter()
+            .rposition(|(_, manifest)| manifest.kind == BackupKind::Full)
+            .ok_or_else(|| BackupError::InvalidChain("no full backup to start from".to_string()))?;
+        chain.drain(..start);
+        for pair in chain.windows(2) {
+            let (parent, child) = (&pair[0].1, &pair[1].1);
+            if child.kind != BackupKind::Incremental || child.parent.as_deref() != Some(parent.id.as_str()) {
+                return Err(BackupError::InvalidChain(format!(
+                    "backup from {} does not build on the one from {}",
+                    child.created_at, parent.created_at
+                )));
+            }
+        }
+
+        let (_, first) = &chain[0];
+        let namespace = options.namespace.clone().unwrap_or_else(|| first.namespace.clone());
+        if (&namespace, &database) == (&live.0, &live.1) {
+            return Err(BackupError::InvalidTarget(format!(
+                "{}/{} is the live database; restore into another one and switch to it once it is complete",
+                namespace, database
+            )));
+        }
+        // USE only applies to the query it is part of, so the pooled
+        // connection keeps its own namespace and database
+        let target = format!("USE NS {} DB {};\n", escape_ident(&namespace), escape_ident(&database));
+
+        let mut status = RestoreProgress {
+            table: String::new(),
+            records_restored: 0,
+            records_total: chain.iter().map(|(_, manifest)| manifest.total_records()).sum(),
+        };
+        let applied: BackupResult<()> = async {
+            for (staging, manifest) in &chain {
+                let removed = match manifest.kind {
+                    BackupKind::Full => manifest.tables.iter().map(|t| &t.name).collect::<Vec<_>>(),
+                    BackupKind::Incremental => manifest.removed_tables.iter().collect(),
+                };
+                let mut script = target.clone();
+                for table in removed {
+                    script.push_str(&format!("REMOVE TABLE IF EXISTS {};\n", escape_ident(table)));
+                }
+                script.push_str(&staging.read(SCHEMA).await?);
+                conn.query(script).await?.check()?;
+
+                for table in &manifest.tables {
+                    status.table = table.name.clone();
+                    for file in &table.files {
+                        let script = format!("{}OPTION IMPORT;\n{}", target, staging.read(&file.path).await?);
+                        conn.query(script).await?.check()?;
+
+                        status.records_restored += file.records;
+                        progress(&status);
+                    }
+                }
+            }
+            Ok(())
+        }
+        .await;
+        // Even a partly applied restore leaves cached results stale
+        self.db.clear_cache();
+        applied?;
+
+        info!(
+            "Restored {} records from a chain of {} backups",
+            status.records_restored,
+            chain.len()
+        );
+        Ok(chain.into_iter().map(|(_, manifest)| manifest).collect())
+    }
+}
+
+/// Collects the data files of one table.
+struct TableWriter<'a> {
+    staging: &'a Staging,
+    checksums: &'a mut BTreeMap<String, String>,
+    position: usize,
+    table: &'a TableInfo,
+    backup: TableBackup,
+}
+
+impl TableWriter<'_> {
+    async fn push(&mut self, contents: String, records: u64) -> BackupResult<()> {
+        let file = DataFile {
+            path: format!("data/{:04}/{:06}.surql", self.position, self.backup.files.len()),
+            records,
+        };
+        self.staging.write(&file.path, contents.as_bytes(), self.checksums).await?;
+        self.backup.records += records;
+        self.backup.files.push(file);
+        Ok(())
+    }
+
+    /// One statement creating all `records`, for a table known to be empty.
+    fn insert(&self, records: Array) -> String {
+        let insert = if self.table.relation { "INSERT RELATION INTO" } else { "INSERT INTO" };
+        format!("{} {} {};\n", insert, escape_ident(&self.table.name), Value::Array(records))
+    }
+
+    /// A statement creating or replacing `record`.
+    fn upsert(&self, record: &Value) -> String {
+        let id = field(record, "id").cloned().unwrap_or_default();
+        if self.table.relation {
+            // Edges cannot be upserted, so the old one is replaced
+            format!("DELETE {}; INSERT RELATION INTO {} {};\n", id, escape_ident(&self.table.name), record)
+        } else {
+            format!("UPSERT {} CONTENT {};\n", id, record)
+        }
+    }
+}
+
+/// Read up to `limit` versionstamps after `versionstamp`, or from the start
+/// when it is 0. Returns the versionstamps read and their changes.
+async fn changes_since(
+    conn: &PooledConnection,
+    table: &str,
+    versionstamp: u64,
+    limit: usize,
+) -> BackupResult<(Vec<u64>, Vec<Value>)> {
+    // SHOW CHANGES reports versionstamps with the batch counter in the
+    // upper bits, but SINCE takes the counter alone
+    let since = match versionstamp {
+        0 => 0,
+        versionstamp => (versionstamp >> 16) + 1,
+    };
+    let query = format!("SHOW CHANGES FOR TABLE {} SINCE {} LIMIT {}", escape_ident(table), since, limit);
+    let result: surrealdb::Value = conn.query(query).await?.take(0)?;
+
+    let mut sets = Vec::new();
+    let mut changes = Vec::new();
+    if let Value::Array(entries) = result.into_inner() {
+        for entry in entries {
+            let versionstamp = match field(&entry, "versionstamp") {
+                Some(Value::Number(n)) => n.as_int() as u64,
+                _ => continue,
+            };
+            if let Some(Value::Array(entry_changes)) = field(&entry, "changes") {
+                changes.extend(entry_changes.iter().cloned());
+            }
+            sets.push(versionstamp);
+        }
+    }
+    Ok((sets, changes))
+}
+
+fn field<'a>(value: &'a Value, name: &str) -> Option<&'a Value> {
+    match value {
+        Value::Object(object) => object.get(name),
+        _ => None,
+    }
+}
+
+async fn session(conn: &PooledConnection) -> BackupResult<(String, String)> {
+    let session: Vec<String> = conn.query("RETURN [session::ns(), session::db()]").await?.take(0)?;
+    match <[String; 2]>::try_from(session) {
+        Ok([namespace, database]) => Ok((namespace, database)),
+        Err(_) => Err(DatabaseError::InvalidInput("no namespace or database selected".to_string()).into()),
+    }
+}
+
+async fn archive_path(path: &Path, namespace: &str, database: &str, created_at: DateTime<Utc>) -> PathBuf {
+    if !tokio::fs::metadata(path).await.map(|m| m.is_dir()).unwrap_or(false) {
+        return path.to_path_buf();
+    }
+
+    let safe = |name: &str| -> String {
+        name.chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-')
+            .collect()
+    };
+    path.join(format!(
+        "omnipro-{}-{}-{}.tar.gz",
+        safe(namespace),
+        safe(database),
+        created_at.format("%Y%m%dT%H%M%S%.3fZ")
+    ))
+}
+
+/// The retention of the `CHANGEFEED` clause in a table definition.
+fn changefeed_retention(definition: &str) -> Option<std::time::Duration> {
+    let (_, rest) = definition.split_once(" CHANGEFEED ")?;
+    let retention = rest.split_whitespace().next()?;
+    surrealdb::sql::Duration::try_from(retention).ok().map(|duration| duration.0)
+}
+
+/// `DEFINE TABLE x ...` as `DEFINE TABLE OVERWRITE x ...`, so restoring over
+/// existing definitions replaces them.
+fn overwrite(definition: &str) -> String {
+    match definition.strip_prefix("DEFINE ").and_then(|rest| rest.split_once(' ')) {
+        Some((kind, rest)) => format!("DEFINE {} OVERWRITE {}", kind, rest),
+        None => definition.to_string(),
+    }
+}
+
+fn sha256(contents: &[u8]) -> String {
+    hex::encode(Sha256::digest(contents))
+}
+
+/// A scratch directory holding the files of an archive; removed on drop.
+struct Staging {
+    dir: PathBuf,
+}
+
+impl Staging {
+    async fn create(dir: PathBuf) -> BackupResult<Self> {
+        // Leftovers of an interrupted backup to the same path
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        tokio::fs::create_dir_all(&dir).await?;
+        Ok(Self { dir })
+    }
+
+    async fn unpack(archive: &Path) -> BackupResult<Self> {
+        tokio::fs::metadata(archive).await?;
+        let staging = Self::create(std::env::temp_dir().join(format!("omnipro-restore-{}", uuid::Uuid::new_v4()))).await?;
+
+        let (archive, dir) = (archive.to_path_buf(), staging.dir.clone());
+        tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+            let file = std::fs::File::open(&archive)?;
+            tar::Archive::new(GzDecoder::new(file)).unpack(&dir)
+        })
+        .await
+        .map_err(|e| BackupError::InvalidArchive(e.to_string()))?
+        .map_err(|e| BackupError::InvalidArchive(format!("cannot unpack: {}", e)))?;
+
+        Ok(staging)
+    }
+
+    async fn write(&self, name: &str, contents: &[u8], checksums: &mut BTreeMap<String, String>) -> BackupResult<()> {
+        let path = self.dir.join(name);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&path, contents).await?;
+        checksums.insert(name.to_string(), sha256(contents));
+        Ok(())
+    }
+
+    async fn read(&self, name: &str) -> BackupResult<String> {
+        Ok(tokio::fs::read_to_string(self.path(name)?).await?)
+    }
+
+    /// Resolve a path taken from the manifest, which must stay inside the
+    /// staging directory.
+    fn path(&self, name: &str) -> BackupResult<PathBuf> {
+        let relative = Path::new(name);
+        if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(BackupError::InvalidArchive(format!("unexpected path {}", name)));
+        }
+        Ok(self.dir.join(relative))
+    }
+
+    async fn verified_manifest(&self) -> BackupResult<BackupManifest> {
+        let contents = tokio::fs::read(self.dir.join(MANIFEST))
+            .await
+            .map_err(|_| BackupError::InvalidArchive(format!("missing {}", MANIFEST)))?;
+        let manifest: BackupManifest = serde_json::from_slice(&contents)
+            .map_err(|e| BackupError::InvalidArchive(format!("{}: {}", MANIFEST, e)))?;
+
+        if manifest.format_version > FORMAT_VERSION {
+            return Err(BackupError::InvalidArchive(format!(
+                "format version {} is newer than the supported {}",
+                manifest.format_version, FORMAT_VERSION
+            )));
+        }
+
+        let referenced = std::iter::once(SCHEMA)
+            .chain(manifest.tables.iter().flat_map(|t| t.files.iter().map(|f| f.path.as_str())));
+        for name in referenced {
+            if !manifest.checksums.contains_key(name) {
+                return Err(BackupError::InvalidArchive(format!("no checksum for {}", name)));
+            }
+        }
+
+        for (name, expected) in &manifest.checksums {
+            let contents = tokio::fs::read(self.path(name)?)
+                .await
+                .map_err(|_| BackupError::InvalidArchive(format!("missing {}", name)))?;
+            if sha256(&contents) != *expected {
+                return Err(BackupError::ChecksumMismatch(name.clone()));
+            }
+        }
+
+        Ok(manifest)
+    }
+
+    /// Compress the staged files into `archive`, manifest first.
+    async fn pack(&self, archive: &Path, manifest: &BackupManifest) -> BackupResult<()> {
+        let names: Vec<String> = std::iter::once(MANIFEST.to_string())
+            .chain(manifest.checksums.keys().cloned())
+            .collect();
+        let (dir, archive) = (self.dir.clone(), archive.to_path_buf());
+
+        tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+            let partial = PathBuf::from(format!("{}.tmp", archive.display()));
+            let file = std::fs::File::create(&partial)?;
+            let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+            for name in &names {
+                builder.append_path_with_name(dir.join(name), name)?;
+            }
+            builder.into_inner()?.finish()?.sync_all()?;
+            std::fs::rename(&partial, &archive)
+        })
+        .await
+        .map_err(std::io::Error::other)??;
+
+        Ok(())
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::surrealml::{Dataset, SurrealMLStorage};
+    use crate::tests::test_db;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("omnipro-backup-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    async fn populated_db() -> Arc<DatabaseManager> {
+        let db = Arc::new(test_db("test", "backup").await);
+        db.execute_query(
+            "DEFINE TABLE item SCHEMAFULL;
+             DEFINE FIELD n ON item TYPE int;
+             DEFINE FIELD at ON item TYPE datetime;
+             DEFINE INDEX item_n ON item FIELDS n UNIQUE;
+             FOR $n IN 1..=5 { CREATE type::thing('item', $n) SET n = $n, at = time::now() };
+             CREATE migration SET version = 1, name = 'init';",
+            Default::default(),
+        )
+        .await
+        .unwrap();
+
+        let dataset = Dataset {
+            id: "d1".to_string(),
+            name: "weights".to_string(),
+            description: String::new(),
+            created_at: Utc::now(),
+        };
+        SurrealMLStorage::new(db.clone())
+            .store_dataset("d1".to_string(), dataset, vec![0, 1, 2, 255])
+            .await
+            .unwrap();
+        db
+    }
+
+    #[test]
+    fn test_changefeed_retention() {
+        let definition = "DEFINE TABLE item TYPE ANY SCHEMALESS CHANGEFEED 1h30m INCLUDE ORIGINAL PERMISSIONS NONE";
+        assert_eq!(changefeed_retention(definition), Some(std::time::Duration::from_secs(5400)));
+        assert_eq!(changefeed_retention("DEFINE TABLE item TYPE ANY SCHEMALESS PERMISSIONS NONE"), None);
+    }
+
+    #[test]
+    fn test_overwrite() {
+        assert_eq!(overwrite("DEFINE TABLE item TYPE ANY SCHEMAFULL"), "DEFINE TABLE OVERWRITE item TYPE ANY SCHEMAFULL");
+        assert_eq!(overwrite("DEFINE FIELD n ON item TYPE int"), "DEFINE FIELD OVERWRITE n ON item TYPE int");
+    }
+
+    #[tokio::test]
+    async fn test_backup_and_restore_into_other_database() {
+        let db = populated_db().await;
+        let dir = temp_dir();
+        let archive = BackupManager::new(db.clone()).with_chunk_size(2).backup(&dir).await.unwrap();
+
+        assert!(archive.path.starts_with(&dir));
+        let items = archive.manifest.tables.iter().find(|t| t.name == "item").unwrap();
+        assert_eq!(items.records, 5);
+        assert_eq!(items.files.len(), 3);
+        for table in ["migration", "dataset", "dataset_data"] {
+            assert!(archive.manifest.tables.iter().any(|t| t.name == table));
+        }
+
+        let mut reported = Vec::new();
+        let options = RestoreOptions {
+            database: Some("restored".to_string()),
+            ..Default::default()
+        };
+        BackupManager::new(db.clone())
+            .restore_with_progress(&archive.path, &options, |p| reported.push(p.records_restored))
+            .await
+            .unwrap();
+        assert_eq!(reported.last(), Some(&archive.manifest.total_records()));
+
+        let restored = db
+            .execute_query(
+                "USE DB restored;
+                 SELECT VALUE n FROM item ORDER BY n;
+                 SELECT VALUE data FROM dataset_data:d1;
+                 SELECT VALUE version FROM migration;
+                 INFO FOR TABLE item;",
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(restored[1], serde_json::json!([1, 2, 3, 4, 5]));
+        assert_eq!(restored[2], serde_json::json!([[0, 1, 2, 255]]));
+        assert_eq!(restored[3], serde_json::json!([1]));
+        assert!(restored[4]["indexes"].get("item_n").is_some());
+
+        // Restoring again replaces the tables instead of failing on existing records
+        BackupManager::new(db.clone()).restore(&archive.path, &options).await.unwrap();
+
+        // The live database is never a restore target
+        for options in [
+            RestoreOptions::default(),
+            RestoreOptions { database: Some("backup".to_string()), ..Default::default() },
+        ] {
+            assert!(matches!(
+                BackupManager::new(db.clone()).restore(&archive.path, &options).await,
+                Err(BackupError::InvalidTarget(_))
+            ));
+        }
+        let live = db.execute_query("SELECT VALUE n FROM item ORDER BY n", Default::default()).await.unwrap();
+        assert_eq!(live[0], serde_json::json!([1, 2, 3, 4, 5]));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_incremental_chain_restores_to_point_in_time() {
+        let db = Arc::new(test_db("test", "incremental").await);
+        db.execute_query(
+            "DEFINE TABLE item CHANGEFEED 1h;
+             DEFINE TABLE doc SCHEMAFULL;
+             DEFINE FIELD n ON doc TYPE int;
+             DEFINE FIELD updated_at ON doc TYPE datetime DEFAULT time::now();
+             FOR $n IN 1..=3 {
+                 CREATE type::thing('item', $n) SET n = $n;
+                 CREATE type::thing('doc', $n) SET n = $n;
+                 CREATE type::thing('plain', $n) SET n = $n;
+             };",
+            Default::default(),
+        )
+        .await
+        .unwrap();
+
+        let dir = temp_dir();
+        let manager = BackupManager::new(db.clone());
+        let full = manager.backup(dir.join("full.tar.gz")).await.unwrap();
+
+        db.execute_query(
+            "UPDATE item:1 SET n = 10; DELETE item:2; CREATE item:4 SET n = 4;
+             UPDATE doc:1 SET n = 10, updated_at = time::now();
+             DELETE plain:3;",
+            Default::default(),
+        )
+        .await
+        .unwrap();
+        let first = manager.backup_incremental(dir.join("inc1.tar.gz"), &full.path).await.unwrap();
+
+        let changed = |archive: &BackupArchive, table: &str| {
+            archive.manifest.tables.iter().find(|t| t.name == table).unwrap().records
+        };
+        assert_eq!(first.manifest.kind, BackupKind::Incremental);
+        assert_eq!(first.manifest.parent.as_deref(), Some(full.manifest.id.as_str()));
+        assert_eq!(changed(&first, "item"), 3);
+        assert_eq!(changed(&first, "doc"), 1);
+        assert_eq!(changed(&first, "plain"), 2);
+
+        db.execute_query("CREATE item:5 SET n = 5", Default::default()).await.unwrap();
+        let second = manager.backup_incremental(dir.join("inc2.tar.gz"), &first.path).await.unwrap();
+        assert_eq!(changed(&second, "item"), 1);
+        assert_eq!(changed(&second, "doc"), 0);
+
+        let contents = |database: &str| {
+            let db = db.clone();
+            let query = format!(
+                "USE DB {}; SELECT VALUE n FROM item ORDER BY n; SELECT VALUE n FROM doc ORDER BY n; SELECT VALUE n FROM plain ORDER BY n;",
+                database
+            );
+            async move { db.execute_query(&query, Default::default()).await.unwrap() }
+        };
+
+        // Stop after the first incremental
+        let archives = [&second.path, &full.path, &first.path];
+        let options = RestoreOptions {
+            database: Some("at_first".to_string()),
+            ..Default::default()
+        };
+        let applied = manager
+            .restore_chain(&archives, Some(first.manifest.created_at), &options, |_| {})
+            .await
+            .unwrap();
+        assert_eq!(applied.len(), 2);
+        let restored = contents("at_first").await;
+        assert_eq!(restored[1], serde_json::json!([3, 4, 10]));
+        assert_eq!(restored[2], serde_json::json!([2, 3, 10]));
+        assert_eq!(restored[3], serde_json::json!([1, 2]));
+
+        let options = RestoreOptions {
+            database: Some("latest".to_string()),
+            ..Default::default()
+        };
+        manager.restore_chain(&archives, None, &options, |_| {}).await.unwrap();
+        assert_eq!(contents("latest").await[1], serde_json::json!([3, 4, 5, 10]));
+
+        // An incremental cannot be restored without the backups it builds on
+        assert!(matches!(
+            manager.restore_chain(&[&first.path], None, &options, |_| {}).await,
+            Err(BackupError::InvalidChain(_))
+        ));
+        assert!(matches!(
+            manager.restore_chain(&[&full.path, &second.path], None, &options, |_| {}).await,
+            Err(BackupError::InvalidChain(_))
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_incremental_copies_tables_whose_feed_expired() {
+        let db = Arc::new(test_db("test", "expired_feed").await);
+        db.execute_query(
+            "DEFINE TABLE item CHANGEFEED 1h; FOR $n IN 1..=3 { CREATE type::thing('item', $n) SET n = $n };",
+            Default::default(),
+        )
+        .await
+        .unwrap();
+
+        let dir = temp_dir();
+        let manager = BackupManager::new(db.clone());
+        let mut full = manager.backup(dir.join("full.tar.gz")).await.unwrap();
+        // Pretend the base is older than the feed's retention
+        full.manifest.created_at -= chrono::Duration::hours(2);
+        let staging = Staging::unpack(&full.path).await.unwrap();
+        let contents = serde_json::to_vec_pretty(&full.manifest).unwrap();
+        std::fs::write(staging.dir.join(MANIFEST), contents).unwrap();
+        staging.pack(&full.path, &full.manifest).await.unwrap();
+
+        db.execute_query("UPDATE item:1 SET n = 10", Default::default()).await.unwrap();
+        let incremental = manager.backup_incremental(dir.join("inc.tar.gz"), &full.path).await.unwrap();
+        let items = incremental.manifest.tables.iter().find(|t| t.name == "item").unwrap();
+        assert_eq!(items.records, 3);
+        assert!(matches!(items.watermark, Some(Watermark::ChangeFeed { .. })));
+
+        let options = RestoreOptions {
+            database: Some("restored".to_string()),
+            ..Default::default()
+        };
+        manager
+            .restore_chain(&[&full.path, &incremental.path], None, &options, |_| {})
+            .await
+            .unwrap();
+        let restored = db
+            .execute_query("USE DB restored; SELECT VALUE n FROM item ORDER BY n", Default::default())
+            .await
+            .unwrap();
+        assert_eq!(restored[1], serde_json::json!([2, 3, 10]));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_restore_rejects_tampered_archive() {
+        let db = populated_db().await;
+        let dir = temp_dir();
+        let archive = BackupManager::new(db.clone()).backup(dir.join("backup.tar.gz")).await.unwrap();
+
+        // Rebuild the archive with one data file changed but the original manifest
+        let staging = Staging::unpack(&archive.path).await.unwrap();
+        let tampered = &archive.manifest.tables[0].files[0].path;
+        std::fs::write(staging.dir.join(tampered), "REMOVE TABLE item;").unwrap();
+        staging.pack(&archive.path, &archive.manifest).await.unwrap();
+
+        let manager = BackupManager::new(db.clone());
+        assert!(matches!(
+            manager.verify(&archive.path).await,
+            Err(BackupError::ChecksumMismatch(name)) if name == *tampered
+        ));
+        let options = RestoreOptions {
+            database: Some("restored".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            manager.restore(&archive.path, &options).await,
+            Err(BackupError::ChecksumMismatch(_))
+        ));
+
+        std::fs::write(&archive.path, "not an archive").unwrap();
+        assert!(matches!(manager.verify(&archive.path).await, Err(BackupError::InvalidArchive(_))));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
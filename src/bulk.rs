@@ -0,0 +1,749 @@
+// Path: src/bulk.rs
+
+use crate::db::{escape_ident, info_section, DatabaseError, DatabaseManager, DatabaseResult};
+use crate::replica::ReadPreference;
+use crate::sanitizer::Sanitizer;
+use crate::schema::FieldDefinition;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use surrealdb::sql::{self, Thing};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+/// Layout of the rows read by [`DatabaseManager::import_table`] and written
+/// by [`DatabaseManager::export_table`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataFormat {
+    /// One JSON object per line
+    Ndjson,
+    /// A header row naming the columns, then one row per record
+    Csv,
+    /// A single JSON array of objects
+    Json,
+}
+
+impl std::str::FromStr for DataFormat {
+    type Err = DatabaseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ndjson" | "jsonl" => Ok(DataFormat::Ndjson),
+            "csv" => Ok(DataFormat::Csv),
+            "json" => Ok(DataFormat::Json),
+            other => Err(DatabaseError::InvalidInput(format!("unknown data format: {}", other))),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    format: DataFormat,
+    batch_size: usize,
+    columns: HashMap<String, String>,
+    fields: Option<Vec<FieldDefinition>>,
+}
+
+impl ImportOptions {
+    pub fn new(format: DataFormat) -> Self {
+        Self {
+            format,
+            batch_size: 500,
+            columns: HashMap::new(),
+            fields: None,
+        }
+    }
+
+    /// Rows sent per `INSERT`.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Store the CSV column `header` as `field`. Unmapped columns keep
+    /// their header as the field name.
+    pub fn map_column(mut self, header: &str, field: &str) -> Self {
+        self.columns.insert(header.to_string(), field.to_string());
+        self
+    }
+
+    /// Check rows against `fields` instead of the fields defined on the table.
+    pub fn fields(mut self, fields: Vec<FieldDefinition>) -> Self {
+        self.fields = Some(fields);
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    format: DataFormat,
+    batch_size: usize,
+    columns: Vec<String>,
+}
+
+impl ExportOptions {
+    pub fn new(format: DataFormat) -> Self {
+        Self {
+            format,
+            batch_size: 500,
+            columns: Vec::new(),
+        }
+    }
+
+    /// Records read per query.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// CSV columns, in order. Defaults to `id` and the table's defined
+    /// fields, or the fields of the first record for schemaless tables.
+    pub fn columns(mut self, columns: &[&str]) -> Self {
+        self.columns = columns.iter().map(|c| c.to_string()).collect();
+        self
+    }
+}
+
+/// A row that was not imported.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RowError {
+    /// 1-based position of the row in the input, not counting a CSV header
+    pub row: u64,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub imported: u64,
+    pub failed: Vec<RowError>,
+}
+
+pub(crate) async fn import<R>(
+    db: &DatabaseManager,
+    table: &str,
+    reader: R,
+    options: &ImportOptions,
+) -> DatabaseResult<ImportReport>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    let table = Sanitizer::new().sanitize_name(table).map_err(DatabaseError::InvalidInput)?;
+    let fields = match &options.fields {
+        Some(fields) => fields.clone(),
+        None => table_fields(db, &table).await?,
+    };
+
+    let mut rows = RowReader::new(reader, options, &fields);
+    let mut report = ImportReport::default();
+    let mut batch = Vec::with_capacity(options.batch_size);
+    while let Some((row, parsed)) = rows.next().await? {
+        match parsed.and_then(|data| prepare(&table, &fields, data)) {
+            Ok(record) => batch.push((row, record)),
+            Err(error) => report.failed.push(RowError { row, error }),
+        }
+        if batch.len() >= options.batch_size {
+            insert_batch(db, &table, std::mem::take(&mut batch), &mut report).await?;
+        }
+    }
+    if !batch.is_empty() {
+        insert_batch(db, &table, batch, &mut report).await?;
+    }
+    report.failed.sort_by_key(|failure| failure.row);
+
+    tracing::info!(
+        "Imported {} rows into {}, {} rejected",
+        report.imported,
+        table,
+        report.failed.len()
+    );
+    Ok(report)
+}
+
+/// Insert a batch in one statement. If the database rejects it, e.g. for a
+/// duplicate key, the rows are retried one by one so only the offending
+/// rows are reported. Connection failures and timeouts abort the import.
+async fn insert_batch(
+    db: &DatabaseManager,
+    table: &str,
+    batch: Vec<(u64, sql::Value)>,
+    report: &mut ImportReport,
+) -> DatabaseResult<()> {
+    let query = format!("INSERT INTO {} $records", escape_ident(table));
+    let insert = |records: sql::Value| {
+        let query = query.clone();
+        async move {
+            db.with_timeout(None, async {
+                let conn = db.get_connection().await?;
+                conn.query(query).bind(("records", records)).await?.check()?;
+                Ok::<_, DatabaseError>(())
+            })
+            .await
+        }
+    };
+
+    let records = sql::Value::from(batch.iter().map(|(_, record)| record.clone()).collect::<Vec<_>>());
+    match insert(records).await {
+        Ok(()) => {
+            report.imported += batch.len() as u64;
+            return Ok(());
+        }
+        Err(DatabaseError::DatabaseError(_)) if batch.len() > 1 => {}
+        Err(DatabaseError::DatabaseError(e)) => {
+            report.failed.push(RowError { row: batch[0].0, error: e.to_string() });
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    }
+
+    for (row, record) in batch {
+        match insert(sql::Value::from(vec![record])).await {
+            Ok(()) => report.imported += 1,
+            Err(DatabaseError::DatabaseError(e)) => report.failed.push(RowError { row, error: e.to_string() }),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Check a row against the field definitions and convert it for insertion:
+/// strings in datetime, uuid and record fields become typed values, and an
+/// `id` becomes a record id in `table`.
+fn prepare(
+    table: &str,
+    fields: &[FieldDefinition],
+    mut data: serde_json::Map<String, serde_json::Value>,
+) -> Result<sql::Value, String> {
+    for field in fields {
+        field.check(data.get(&field.name))?;
+    }
+    let id = data.remove("id");
+
+    let mut record = match sql::json(&serde_json::Value::Object(data).to_string()) {
+        Ok(sql::Value::Object(record)) => record,
+        Ok(_) => return Err("row is not an object".to_string()),
+        Err(e) => return Err(e.to_string()),
+    };
+
+    for field in fields {
+        let Some(sql::Value::Strand(text)) = record.get(&field.name) else {
+            continue;
+        };
+        let typed = match field.field_type.as_str() {
+            "datetime" => sql::Datetime::try_from(text.as_str()).map(sql::Value::from).ok(),
+            "uuid" => sql::Uuid::try_from(text.as_str()).map(sql::Value::from).ok(),
+            kind if kind.starts_with("record") => sql::thing(text.as_str()).map(sql::Value::from).ok(),
+            _ => None,
+        };
+        if let Some(typed) = typed {
+            record.insert(field.name.clone(), typed);
+        }
+    }
+
+    match id {
+        None | Some(serde_json::Value::Null) => {}
+        Some(id) => {
+            let key = match id {
+                serde_json::Value::String(key) => key,
+                other => other.to_string(),
+            };
+            // Exported ids look like `table:key`
+            let thing = match sql::thing(&key) {
+                Ok(thing) if thing.tb == table => thing,
+                _ => Thing::from((table, key.as_str())),
+            };
+            record.insert("id".to_string(), sql::Value::from(thing));
+        }
+    }
+    Ok(sql::Value::from(record))
+}
+
+/// The top-level fields defined on `table`; nested fields such as
+/// `address.city` are not checked.
+async fn table_fields(db: &DatabaseManager, table: &str) -> DatabaseResult<Vec<FieldDefinition>> {
+    let conn = db.get_connection().await?;
+    let definitions = info_section(&conn, &format!("INFO FOR TABLE {}", escape_ident(table)), "fields").await?;
+    Ok(definitions
+        .values()
+        .filter_map(|definition| FieldDefinition::from_surql(definition))
+        .filter(|field| !field.name.contains(['.', '[']))
+        .collect())
+}
+
+type Row = Result<serde_json::Map<String, serde_json::Value>, String>;
+
+/// Reads rows one at a time, whatever the format.
+struct RowReader<'a, R> {
+    input: BufReader<R>,
+    format: DataFormat,
+    options: &'a ImportOptions,
+    fields: &'a [FieldDefinition],
+    row: u64,
+    /// Field names of the CSV columns, once the header has been read
+    headers: Option<Vec<String>>,
+    json: JsonArrayState,
+}
+
+#[derive(Default)]
+struct JsonArrayState {
+    started: bool,
+    finished: bool,
+    /// A byte read past the end of a scalar element
+    pending: Option<u8>,
+}
+
+impl<'a, R: AsyncRead + Unpin> RowReader<'a, R> {
+    fn new(reader: R, options: &'a ImportOptions, fields: &'a [FieldDefinition]) -> Self {
+        Self {
+            input: BufReader::new(reader),
+            format: options.format,
+            options,
+            fields,
+            row: 0,
+            headers: None,
+            json: JsonArrayState::default(),
+        }
+    }
+
+    /// The next row and its position. Malformed rows are returned as
+    /// errors so they can be reported; only an unreadable input or a
+    /// broken JSON array fails the import.
+    async fn next(&mut self) -> DatabaseResult<Option<(u64, Row)>> {
+        let row = match self.format {
+            DataFormat::Ndjson => self.next_line().await?,
+            DataFormat::Csv => self.next_csv().await?,
+            DataFormat::Json => self.next_element().await?,
+        };
+        Ok(row.map(|row| {
+            self.row += 1;
+            (self.row, row)
+        }))
+    }
+
+    async fn next_line(&mut self) -> DatabaseResult<Option<Row>> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.input.read_line(&mut line).await? == 0 {
+                return Ok(None);
+            }
+            if !line.trim().is_empty() {
+                return Ok(Some(parse_object(line.as_bytes())));
+            }
+        }
+    }
+
+    async fn next_csv(&mut self) -> DatabaseResult<Option<Row>> {
+        if self.headers.is_none() {
+            let Some(header) = self.next_csv_record().await? else {
+                return Ok(None);
+            };
+            let header = header.map_err(|e| DatabaseError::InvalidInput(format!("invalid CSV header: {}", e)))?;
+            self.headers = Some(
+                header
+                    .into_iter()
+                    .map(|h| self.options.columns.get(&h).cloned().unwrap_or(h))
+                    .collect(),
+            );
+        }
+
+        let Some(cells) = self.next_csv_record().await? else {
+            return Ok(None);
+        };
+        let headers = self.headers.as_deref().unwrap_or_default();
+        Ok(Some(cells.and_then(|cells| {
+            if cells.len() != headers.len() {
+                return Err(format!("expected {} columns, found {}", headers.len(), cells.len()));
+            }
+            Ok(headers
+                .iter()
+                .zip(cells)
+                .filter(|(_, cell)| !cell.is_empty())
+                .map(|(name, cell)| {
+                    let field = self.fields.iter().find(|f| f.name == *name);
+                    (name.clone(), csv_value(field, cell))
+                })
+                .collect())
+        })))
+    }
+
+    /// Read one CSV record, which may span lines inside quoted cells.
+    async fn next_csv_record(&mut self) -> DatabaseResult<Option<Result<Vec<String>, String>>> {
+        let mut text = String::new();
+        loop {
+            let read = self.input.read_line(&mut text).await?;
+            let balanced = text.matches('"').count().is_multiple_of(2);
+            if read == 0 || (balanced && !text.trim().is_empty()) {
+                break;
+            }
+            if balanced {
+                text.clear();
+            }
+        }
+        if text.trim().is_empty() {
+            return Ok(None);
+        }
+
+        let mut reader = csv::ReaderBuilder::new().has_headers(false).from_reader(text.as_bytes());
+        let record = match reader.records().next() {
+            Some(Ok(record)) => Ok(record.iter().map(str::to_string).collect()),
+            Some(Err(e)) => Err(e.to_string()),
+            None => Ok(Vec::new()),
+        };
+        Ok(Some(record))
+    }
+
+    /// The next element of a top-level JSON array, read without loading
+    /// the whole array.
+    async fn next_element(&mut self) -> DatabaseResult<Option<Row>> {
+        if self.json.finished {
+            return Ok(None);
+        }
+
+        let mut first = self.next_significant().await?;
+        if !self.json.started {
+            if first != Some(b'[') {
+                return Err(DatabaseError::InvalidInput("JSON input must be an array".to_string()));
+            }
+            self.json.started = true;
+            first = self.next_significant().await?;
+            if first == Some(b']') {
+                self.json.finished = true;
+                return Ok(None);
+            }
+        } else {
+            match first {
+                Some(b',') => first = self.next_significant().await?,
+                Some(b']') => {
+                    self.json.finished = true;
+                    return Ok(None);
+                }
+                _ => return Err(DatabaseError::InvalidInput("expected , or ] in JSON array".to_string())),
+            }
+        }
+        let Some(first) = first else {
+            return Err(DatabaseError::InvalidInput("unterminated JSON array".to_string()));
+        };
+
+        let mut element = vec![first];
+        let (mut depth, mut in_string, mut escaped) = (0usize, first == b'"', false);
+        if matches!(first, b'{' | b'[') {
+            depth = 1;
+        }
+        while depth > 0 || in_string || !matches!(first, b'{' | b'[' | b'"') {
+            let Some(byte) = self.next_byte().await? else {
+                return Err(DatabaseError::InvalidInput("unterminated JSON array".to_string()));
+            };
+            if in_string {
+                element.push(byte);
+                match (escaped, byte) {
+                    (true, _) => escaped = false,
+                    (false, b'\\') => escaped = true,
+                    (false, b'"') => in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            if depth == 0 && (matches!(byte, b',' | b']') || byte.is_ascii_whitespace()) {
+                // End of a scalar; leave the delimiter for the next call
+                self.json.pending = Some(byte);
+                break;
+            }
+            element.push(byte);
+            match byte {
+                b'"' => in_string = true,
+                b'{' | b'[' => depth += 1,
+                b'}' | b']' => depth -= 1,
+                _ => {}
+            }
+        }
+        Ok(Some(parse_object(&element)))
+    }
+
+    async fn next_byte(&mut self) -> DatabaseResult<Option<u8>> {
+        if let Some(byte) = self.json.pending.take() {
+            return Ok(Some(byte));
+        }
+        match self.input.read_u8().await {
+            Ok(byte) => Ok(Some(byte)),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn next_significant(&mut self) -> DatabaseResult<Option<u8>> {
+        loop {
+            match self.next_byte().await? {
+                Some(byte) if byte.is_ascii_whitespace() => continue,
+                other => return Ok(other),
+            }
+        }
+    }
+}
+
+fn parse_object(text: &[u8]) -> Row {
+    match serde_json::from_slice(text) {
+        Ok(serde_json::Value::Object(row)) => Ok(row),
+        Ok(_) => Err("row is not an object".to_string()),
+        Err(e) => Err(format!("invalid JSON: {}", e)),
+    }
+}
+
+/// Interpret a CSV cell by the type of the field it is stored in. Cells
+/// that do not parse stay strings and fail the field check.
+fn csv_value(field: Option<&FieldDefinition>, cell: String) -> serde_json::Value {
+    let kind = field.map_or("string", |f| f.field_type.as_str());
+    let parsed = match kind {
+        "int" => cell.parse::<i64>().ok().map(serde_json::Value::from),
+        "float" | "number" | "decimal" => cell.parse::<f64>().ok().map(serde_json::Value::from),
+        "bool" => cell.parse::<bool>().ok().map(serde_json::Value::from),
+        kind if kind == "object" || kind.starts_with("array") || kind.starts_with("set") => {
+            serde_json::from_str(&cell).ok()
+        }
+        _ => None,
+    };
+    parsed.unwrap_or(serde_json::Value::String(cell))
+}
+
+pub(crate) async fn export<W>(
+    db: &DatabaseManager,
+    table: &str,
+    writer: W,
+    options: &ExportOptions,
+) -> DatabaseResult<u64>
+where
+    W: AsyncWrite + Unpin + Send,
+{
+    let table = Sanitizer::new().sanitize_name(table).map_err(DatabaseError::InvalidInput)?;
+    let mut writer = tokio::io::BufWriter::new(writer);
+    let mut columns = options.columns.clone();
+    if options.format == DataFormat::Csv && columns.is_empty() {
+        let fields = table_fields(db, &table).await?;
+        if !fields.is_empty() {
+            columns = std::iter::once("id".to_string()).chain(fields.into_iter().map(|f| f.name)).collect();
+        }
+    }
+
+    if options.format == DataFormat::Json {
+        writer.write_all(b"[").await?;
+    }
+
+    let mut exported = 0u64;
+    let mut after: Option<Thing> = None;
+    loop {
+        let query = match after {
+            Some(_) => "SELECT * FROM type::table($table) WHERE id > $after ORDER BY id LIMIT $limit",
+            None => "SELECT * FROM type::table($table) ORDER BY id LIMIT $limit",
+        };
+        let page: surrealdb::Value = db
+            .with_timeout(None, async {
+                let conn = db.get_read_connection(ReadPreference::Replica).await?;
+                Ok::<_, DatabaseError>(
+                    conn.query(query)
+                        .bind(("table", table.clone()))
+                        .bind(("after", after.clone()))
+                        .bind(("limit", options.batch_size))
+                        .await?
+                        .take(0)?,
+                )
+            })
+            .await?;
+
+        let records = match page.into_inner() {
+            sql::Value::Array(records) if !records.is_empty() => records,
+            _ => break,
+        };
+        after = match records.last() {
+            Some(sql::Value::Object(record)) => match record.get("id") {
+                Some(sql::Value::Thing(id)) => Some(id.clone()),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        let count = records.len();
+        let rows: Vec<serde_json::Value> = records.into_iter().map(|record| record.into_json()).collect();
+        if options.format == DataFormat::Csv && exported == 0 {
+            if columns.is_empty() {
+                columns = csv_columns(&rows[0]);
+            }
+            writer.write_all(&csv_line(columns.iter().cloned())?).await?;
+        }
+
+        for row in rows {
+            match options.format {
+                DataFormat::Ndjson => {
+                    writer.write_all(row.to_string().as_bytes()).await?;
+                    writer.write_all(b"\n").await?;
+                }
+                DataFormat::Json => {
+                    let separator = if exported == 0 { "\n" } else { ",\n" };
+                    writer.write_all(format!("{}{}", separator, row).as_bytes()).await?;
+                }
+                DataFormat::Csv => {
+                    let cells = columns.iter().map(|column| match row.get(column) {
+                        None | Some(serde_json::Value::Null) => String::new(),
+                        Some(serde_json::Value::String(text)) => text.clone(),
+                        Some(other) => other.to_string(),
+                    });
+                    writer.write_all(&csv_line(cells)?).await?;
+                }
+            }
+            exported += 1;
+        }
+
+        if count < options.batch_size || after.is_none() {
+            break;
+        }
+    }
+
+    if options.format == DataFormat::Json {
+        writer.write_all(if exported == 0 { b"]\n" as &[u8] } else { b"\n]\n" }).await?;
+    }
+    writer.flush().await?;
+    Ok(exported)
+}
+
+/// `id` followed by the other fields of `row` in name order.
+fn csv_columns(row: &serde_json::Value) -> Vec<String> {
+    let mut columns = vec!["id".to_string()];
+    if let Some(row) = row.as_object() {
+        let mut names: Vec<_> = row.keys().filter(|k| *k != "id").cloned().collect();
+        names.sort();
+        columns.extend(names);
+    }
+    columns
+}
+
+fn csv_line(cells: impl IntoIterator<Item = String>) -> DatabaseResult<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record(cells)
+        .map_err(|e| DatabaseError::InvalidInput(format!("cannot write CSV: {}", e)))?;
+    writer
+        .into_inner()
+        .map_err(|e| DatabaseError::InvalidInput(format!("cannot write CSV: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_db;
+    use serde_json::json;
+
+    async fn people_db() -> DatabaseManager {
+        let db = test_db("test", "bulk").await;
+        db.execute_query(
+            "DEFINE TABLE person SCHEMAFULL;
+             DEFINE FIELD email ON person TYPE string;
+             DEFINE FIELD age ON person TYPE option<int>;
+             DEFINE FIELD born ON person TYPE option<datetime>;
+             DEFINE INDEX person_email ON person FIELDS email UNIQUE;",
+            Default::default(),
+        )
+        .await
+        .unwrap();
+        db
+    }
+
+    #[test]
+    fn test_field_definition_from_surql() {
+        let field = FieldDefinition::from_surql("DEFINE FIELD age ON person TYPE option<int> PERMISSIONS FULL").unwrap();
+        assert_eq!((field.name.as_str(), field.field_type.as_str(), field.required), ("age", "int", false));
+        assert!(field.check(None).is_ok());
+        assert!(field.check(Some(&json!("ten"))).is_err());
+
+        // Defaulted and computed fields may be left out of a row
+        let field = FieldDefinition::from_surql("DEFINE FIELD tags ON person TYPE array<string> DEFAULT []").unwrap();
+        assert!(!field.required);
+        assert!(field.check(None).is_ok());
+        assert!(field.check(Some(&json!(["a", "b"]))).is_ok());
+        assert!(field.check(Some(&json!(["a", 1]))).is_err());
+
+        let computed = "DEFINE FIELD updated_at ON person TYPE datetime READONLY VALUE time::now() PERMISSIONS FULL";
+        assert!(!FieldDefinition::from_surql(computed).unwrap().required);
+
+        let field = FieldDefinition::from_surql("DEFINE FIELD name ON person TYPE string ASSERT $value != 'a VALUE b' PERMISSIONS FULL").unwrap();
+        assert!(field.required);
+        assert!(field.check(None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_csv_import_reports_bad_rows() {
+        let db = people_db().await;
+        let csv = "Email,Age,born\n\
+                   a@example.com,30,1990-01-01T00:00:00Z\n\
+                   b@example.com,not a number,\n\
+                   \"c@example.com\",,\n\
+                   a@example.com,40,\n\
+                   ,50,\n";
+        let options = ImportOptions::new(DataFormat::Csv)
+            .map_column("Email", "email")
+            .map_column("Age", "age")
+            .batch_size(2);
+        let report = db.import_table("person", csv.as_bytes(), &options).await.unwrap();
+
+        assert_eq!(report.imported, 2);
+        let failed: Vec<_> = report.failed.iter().map(|f| f.row).collect();
+        assert_eq!(failed, [2, 4, 5]);
+        assert!(report.failed[0].error.contains("age"));
+
+        let born = db
+            .execute_query("SELECT VALUE type::is::datetime(born) FROM person WHERE email = 'a@example.com'", Default::default())
+            .await
+            .unwrap();
+        assert_eq!(born, json!([[true]]));
+    }
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        let db = people_db().await;
+        let ndjson = "{\"id\": \"person:one\", \"email\": \"one@example.com\", \"age\": 1}\n\
+                      \n\
+                      {\"email\": \"two@example.com\"}\n\
+                      not json\n";
+        let report = db
+            .import_table("person", ndjson.as_bytes(), &ImportOptions::new(DataFormat::Ndjson))
+            .await
+            .unwrap();
+        assert_eq!(report.imported, 2);
+        assert_eq!(report.failed.len(), 1);
+
+        for format in [DataFormat::Ndjson, DataFormat::Csv, DataFormat::Json] {
+            let mut output = Vec::new();
+            let options = ExportOptions::new(format).batch_size(1);
+            assert_eq!(db.export_table("person", &mut output, &options).await.unwrap(), 2);
+
+            let copy = test_db("test", "bulk_copy").await;
+            copy.execute_query("DEFINE TABLE person SCHEMAFULL; DEFINE FIELD email ON person TYPE string; DEFINE FIELD age ON person TYPE option<int>;", Default::default())
+                .await
+                .unwrap();
+            let report = copy
+                .import_table("person", output.as_slice(), &ImportOptions::new(format))
+                .await
+                .unwrap();
+            assert_eq!(report, ImportReport { imported: 2, failed: vec![] }, "{:?}", format);
+
+            let one = copy
+                .execute_query("SELECT VALUE age FROM person:one", Default::default())
+                .await
+                .unwrap();
+            assert_eq!(one, json!([[1]]), "{:?}", format);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_json_array_elements() {
+        let db = people_db().await;
+        let json = r#" [ {"email": "x@example.com", "age": 1}, 42, {"email": "y]@example.com"} ] "#;
+        let report = db
+            .import_table("person", json.as_bytes(), &ImportOptions::new(DataFormat::Json))
+            .await
+            .unwrap();
+        assert_eq!(report.imported, 2);
+        assert_eq!(report.failed, vec![RowError { row: 2, error: "row is not an object".to_string() }]);
+
+        let result = db
+            .import_table("person", "{\"email\": 1}".as_bytes(), &ImportOptions::new(DataFormat::Json))
+            .await;
+        assert!(matches!(result, Err(DatabaseError::InvalidInput(_))));
+    }
+}
@@ -0,0 +1,500 @@
+// Path: src/cache.rs
+
+use crate::telemetry::TelemetryManager;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::task::AbortHandle;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    /// Most results kept at once; the least recently used go first
+    pub max_entries: usize,
+    /// Limit on the total size of cached results, measured as JSON text
+    pub max_bytes: usize,
+    /// How long a result stays fresh when the read does not say
+    pub default_ttl_ms: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_entries: 10_000,
+            max_bytes: 64 * 1024 * 1024,
+            default_ttl_ms: 60_000,
+        }
+    }
+}
+
+impl CacheConfig {
+    pub fn default_ttl(&self) -> Duration {
+        Duration::from_millis(self.default_ttl_ms)
+    }
+}
+
+/// Counters since the cache was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+impl CacheStats {
+    /// Fraction of lookups answered from the cache, 0.0 before any lookup.
+    pub fn hit_ratio(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+struct Entry {
+    value: serde_json::Value,
+    tables: Vec<String>,
+    expires_at: Instant,
+    size: usize,
+    /// Position in the recency order
+    used: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<String, Entry>,
+    /// Keys by last use, oldest first
+    recency: BTreeMap<u64, String>,
+    by_table: HashMap<String, HashSet<String>>,
+    /// Tables with a live query invalidating them
+    watched: HashSet<String>,
+    bytes: usize,
+    clock: u64,
+    /// Bumped by every invalidation, so results read before one are not stored after it
+    generation: u64,
+    /// Generation of each table's last invalidation
+    invalidated_at: HashMap<String, u64>,
+    /// Generation of the last [`QueryCache::clear`]
+    cleared_at: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl Inner {
+    fn remove(&mut self, key: &str) -> Option<Entry> {
+        let entry = self.entries.remove(key)?;
+        self.recency.remove(&entry.used);
+        self.bytes -= entry.size;
+        for table in &entry.tables {
+            if let Some(keys) = self.by_table.get_mut(table) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    self.by_table.remove(table);
+                }
+            }
+        }
+        Some(entry)
+    }
+
+    /// Whether a result read from `tables` at `generation` has been
+    /// invalidated since. Invalidations of other tables do not count.
+    fn is_stale(&self, tables: &[String], generation: u64) -> bool {
+        self.cleared_at > generation
+            || tables
+                .iter()
+                .any(|table| self.invalidated_at.get(table).is_some_and(|&at| at > generation))
+    }
+
+    fn touch(&mut self, key: &str) {
+        self.clock += 1;
+        let clock = self.clock;
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.recency.remove(&entry.used);
+            entry.used = clock;
+            self.recency.insert(clock, key.to_string());
+        }
+    }
+}
+
+/// In-process cache of read results, keyed by query and parameters.
+///
+/// Each entry records the tables it was read from. A write to one of those
+/// tables, through this process or seen by a live query, drops the entry;
+/// otherwise it lives until its TTL passes or it is evicted for space.
+pub struct QueryCache {
+    config: CacheConfig,
+    inner: Mutex<Inner>,
+    telemetry: Option<Arc<TelemetryManager>>,
+    /// Tasks following the live queries, stopped with the cache
+    watchers: Mutex<Vec<AbortHandle>>,
+}
+
+impl Drop for QueryCache {
+    fn drop(&mut self) {
+        self.stop_watchers();
+    }
+}
+
+impl QueryCache {
+    pub fn new(config: CacheConfig, telemetry: Option<Arc<TelemetryManager>>) -> Self {
+        Self {
+            config,
+            inner: Mutex::new(Inner::default()),
+            telemetry,
+            watchers: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn config(&self) -> &CacheConfig {
+        &self.config
+    }
+
+    /// The cached result for `key`, if it is still fresh.
+    pub fn get(&self, key: &str) -> Option<serde_json::Value> {
+        let mut inner = self.lock();
+        let fresh = match inner.entries.get(key) {
+            Some(entry) if entry.expires_at > Instant::now() => true,
+            Some(_) => {
+                inner.remove(key);
+                self.record_metric("db_cache_evictions", "reason", "expired");
+                false
+            }
+            None => false,
+        };
+
+        if fresh {
+            inner.hits += 1;
+            inner.touch(key);
+            self.record_metric("db_cache_requests", "result", "hit");
+            inner.entries.get(key).map(|entry| entry.value.clone())
+        } else {
+            inner.misses += 1;
+            self.record_metric("db_cache_requests", "result", "miss");
+            None
+        }
+    }
+
+    /// Marker to pass to [`QueryCache::insert`]; take it before reading the
+    /// value so a write that lands during the read is not missed.
+    pub fn generation(&self) -> u64 {
+        self.lock().generation
+    }
+
+    /// Store `value`, read from `tables`, for `ttl`. Nothing is stored when one
+    /// of `tables` was invalidated since `generation` or the value alone is over
+    /// the size limit.
+    pub fn insert(&self, key: &str, tables: &[String], value: serde_json::Value, ttl: Duration, generation: u64) {
+        let size = key.len() + value.to_string().len();
+        if size > self.config.max_bytes || self.config.max_entries == 0 {
+            return;
+        }
+
+        let mut inner = self.lock();
+        if inner.is_stale(tables, generation) {
+            return;
+        }
+        inner.remove(key);
+        inner.clock += 1;
+        let used = inner.clock;
+        inner.entries.insert(
+            key.to_string(),
+            Entry {
+                value,
+                tables: tables.to_vec(),
+                expires_at: Instant::now() + ttl,
+                size,
+                used,
+            },
+        );
+        inner.recency.insert(used, key.to_string());
+        inner.bytes += size;
+        for table in tables {
+            inner.by_table.entry(table.clone()).or_default().insert(key.to_string());
+        }
+
+        while inner.entries.len() > self.config.max_entries || inner.bytes > self.config.max_bytes {
+            let Some(oldest) = inner.recency.values().next().cloned() else {
+                break;
+            };
+            inner.remove(&oldest);
+            self.record_metric("db_cache_evictions", "reason", "lru");
+        }
+    }
+
+    /// Drop every result read from `table`.
+    pub fn invalidate_table(&self, table: &str) {
+        let mut inner = self.lock();
+        inner.generation += 1;
+        let generation = inner.generation;
+        inner.invalidated_at.insert(table.to_string(), generation);
+        let keys: Vec<String> = inner.by_table.get(table).into_iter().flatten().cloned().collect();
+        for key in keys {
+            inner.remove(&key);
+            self.record_metric("db_cache_evictions", "reason", "invalidated");
+        }
+    }
+
+    /// Drop every result. The live queries are dropped too, since a schema
+    /// change such as `REMOVE TABLE` may have ended them; the next cached
+    /// read subscribes again.
+    pub fn clear(&self) {
+        let mut inner = self.lock();
+        let generation = inner.generation + 1;
+        let (hits, misses) = (inner.hits, inner.misses);
+        *inner = Inner {
+            generation,
+            cleared_at: generation,
+            hits,
+            misses,
+            ..Inner::default()
+        };
+        self.stop_watchers();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.lock();
+        CacheStats {
+            hits: inner.hits,
+            misses: inner.misses,
+            entries: inner.entries.len(),
+            bytes: inner.bytes,
+        }
+    }
+
+    /// Mark `table` as watched, returning whether it was not already.
+    pub(crate) fn watch(&self, table: &str) -> bool {
+        self.lock().watched.insert(table.to_string())
+    }
+
+    pub(crate) fn unwatch(&self, table: &str) {
+        self.lock().watched.remove(table);
+    }
+
+    pub(crate) fn add_watcher(&self, watcher: AbortHandle) {
+        let mut watchers = self.watchers.lock().unwrap_or_else(|e| e.into_inner());
+        watchers.retain(|w| !w.is_finished());
+        watchers.push(watcher);
+    }
+
+    fn stop_watchers(&self) {
+        let mut watchers = self.watchers.lock().unwrap_or_else(|e| e.into_inner());
+        for watcher in watchers.drain(..) {
+            watcher.abort();
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn record_metric(&self, name: &str, key: &str, value: &str) {
+        if let Some(telemetry) = &self.telemetry {
+            telemetry.record_metric(name.to_string(), 1.0, vec![(key.to_string(), value.to_string())]);
+        }
+    }
+}
+
+/// The tables a query names, or `None` when they cannot all be told from
+/// the text, e.g. `type::table($t)`, a `$record` target or a schema change.
+pub(crate) fn referenced_tables(query: &str) -> Option<Vec<String>> {
+    static TARGETS: OnceLock<Regex> = OnceLock::new();
+    static SCHEMA: OnceLock<Regex> = OnceLock::new();
+    let targets = TARGETS.get_or_init(|| {
+        Regex::new(
+            r"(?i)\b(?:FROM|UPDATE|UPSERT|CREATE|DELETE|INTO|RELATE)\s+(?:ONLY\s+)?((?:[^\s;,()]+\s*,\s*)*[^\s;,()]+)",
+        )
+        .unwrap()
+    });
+    let schema = SCHEMA.get_or_init(|| Regex::new(r"(?i)\b(?:DEFINE|REMOVE|ALTER|USE)\s").unwrap());
+    if schema.is_match(query) {
+        return None;
+    }
+
+    // type::thing('user', $id) and type::table('user') name the table literally
+    static LITERAL: OnceLock<Regex> = OnceLock::new();
+    let literal = LITERAL.get_or_init(|| {
+        Regex::new(r#"type::(?:thing|table)\(\s*['"]([A-Za-z_][A-Za-z0-9_]*)['"][^()]*\)"#).unwrap()
+    });
+    let query = literal.replace_all(query, "$1");
+    let query = query.as_ref();
+
+    let mut tables = Vec::new();
+    for captures in targets.captures_iter(query) {
+        for target in captures[1].split(',') {
+            for part in target.split("->").flat_map(|part| part.split("<-")) {
+                let part = part.trim();
+                if part.is_empty() {
+                    continue;
+                }
+                // A function such as type::table(...) picks the table at runtime
+                if part.contains("::") {
+                    return None;
+                }
+                let table = part.split(':').next().unwrap_or_default().trim_matches('`');
+                let valid = table.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                    && table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                if !valid {
+                    return None;
+                }
+                if !tables.iter().any(|t| t == table) {
+                    tables.push(table.to_string());
+                }
+            }
+        }
+    }
+
+    // Graph traversals in projections and conditions read edge tables
+    static EDGES: OnceLock<Regex> = OnceLock::new();
+    let edges = EDGES.get_or_init(|| Regex::new(r"(?:->|<-)\s*`?([A-Za-z_][A-Za-z0-9_]*)").unwrap());
+    for captures in edges.captures_iter(query) {
+        let table = captures[1].to_string();
+        if !tables.contains(&table) {
+            tables.push(table);
+        }
+    }
+
+    (!tables.is_empty()).then_some(tables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{DatabaseConfig, DatabaseManager, QueryOptions};
+    use serde_json::json;
+
+    fn cache(max_entries: usize, max_bytes: usize) -> QueryCache {
+        QueryCache::new(
+            CacheConfig {
+                enabled: true,
+                max_entries,
+                max_bytes,
+                default_ttl_ms: 60_000,
+            },
+            None,
+        )
+    }
+
+    fn tables(names: &[&str]) -> Vec<String> {
+        names.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn test_lru_and_size_limits() {
+        let cache = cache(2, 1024);
+        let ttl = Duration::from_secs(60);
+        cache.insert("a", &tables(&["user"]), json!(1), ttl, 0);
+        cache.insert("b", &tables(&["user"]), json!(2), ttl, 0);
+        assert_eq!(cache.get("a"), Some(json!(1)));
+
+        // "b" is now the least recently used
+        cache.insert("c", &tables(&["model"]), json!(3), ttl, 0);
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(json!(1)));
+        assert_eq!(cache.get("c"), Some(json!(3)));
+
+        cache.insert("big", &tables(&["user"]), json!("x".repeat(2048)), ttl, 0);
+        assert_eq!(cache.get("big"), None);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (3, 2, 2));
+        assert_eq!(stats.hit_ratio(), 0.6);
+    }
+
+    #[test]
+    fn test_ttl_and_invalidation() {
+        let cache = cache(10, 1024);
+        cache.insert("short", &tables(&["user"]), json!(1), Duration::ZERO, 0);
+        assert_eq!(cache.get("short"), None);
+
+        let generation = cache.generation();
+        cache.insert("users", &tables(&["user"]), json!(1), Duration::from_secs(60), generation);
+        cache.insert("join", &tables(&["user", "model"]), json!(2), Duration::from_secs(60), generation);
+        cache.insert("models", &tables(&["model"]), json!(3), Duration::from_secs(60), generation);
+
+        cache.invalidate_table("user");
+        assert_eq!(cache.get("users"), None);
+        assert_eq!(cache.get("join"), None);
+        assert_eq!(cache.get("models"), Some(json!(3)));
+
+        // Read before the invalidation, so not stored
+        cache.insert("stale", &tables(&["user"]), json!(4), Duration::from_secs(60), generation);
+        assert_eq!(cache.get("stale"), None);
+
+        // Invalidating another table does not hold back a result
+        cache.insert("other", &tables(&["model"]), json!(5), Duration::from_secs(60), generation);
+        assert_eq!(cache.get("other"), Some(json!(5)));
+
+        // Nothing read before a clear is stored
+        let generation = cache.generation();
+        cache.clear();
+        cache.insert("cleared", &tables(&["model"]), json!(6), Duration::from_secs(60), generation);
+        assert_eq!(cache.get("cleared"), None);
+    }
+
+    #[test]
+    fn test_referenced_tables() {
+        assert_eq!(referenced_tables("SELECT * FROM user WHERE age > $age"), Some(tables(&["user"])));
+        assert_eq!(
+            referenced_tables("SELECT *, ->likes->post AS liked FROM `user`, model:abc; UPDATE product SET x = 1"),
+            Some(tables(&["user", "model", "product", "likes", "post"]))
+        );
+        assert_eq!(referenced_tables("RELATE user:a->likes->post:b"), Some(tables(&["user", "likes", "post"])));
+        assert_eq!(
+            referenced_tables("UPDATE type::thing('user', $id) MERGE $data"),
+            Some(tables(&["user"]))
+        );
+        assert_eq!(referenced_tables("SELECT * FROM type::table($table)"), None);
+        assert_eq!(referenced_tables("DELETE $id"), None);
+        assert_eq!(referenced_tables("DEFINE TABLE user SCHEMALESS"), None);
+        assert_eq!(referenced_tables("RETURN 1"), None);
+    }
+
+    async fn cached_db() -> DatabaseManager {
+        let mut config = DatabaseConfig::in_memory("test", "cache");
+        config.cache.enabled = true;
+        DatabaseManager::new(config).await.unwrap()
+    }
+
+    async fn count(db: &DatabaseManager) -> serde_json::Value {
+        let options = QueryOptions {
+            cache_ttl: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        let result = db
+            .execute_query_with("SELECT count() FROM item GROUP ALL", Default::default(), options)
+            .await
+            .unwrap();
+        result[0][0]["count"].clone()
+    }
+
+    #[tokio::test]
+    async fn test_cached_queries_are_invalidated() {
+        let db = cached_db().await;
+        db.execute_query("CREATE item:1", Default::default()).await.unwrap();
+
+        assert_eq!(count(&db).await, json!(1));
+        assert_eq!(count(&db).await, json!(1));
+        assert_eq!(db.cache_stats().unwrap().hits, 1);
+
+        // A write through the manager drops the result straight away
+        db.execute_query("CREATE item:2", Default::default()).await.unwrap();
+        assert_eq!(count(&db).await, json!(2));
+
+        // One that bypasses it is seen by the live query
+        db.get_connection().await.unwrap().query("CREATE item:3").await.unwrap();
+        let mut seen = count(&db).await;
+        for _ in 0..50 {
+            if seen == json!(3) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            seen = count(&db).await;
+        }
+        assert_eq!(seen, json!(3));
+    }
+}
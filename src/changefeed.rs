@@ -0,0 +1,427 @@
+// Path: src/changefeed.rs
+
+use crate::db::{escape_ident, DatabaseManager};
+use crate::error::{Error, Result};
+use crate::sanitizer::Sanitizer;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+/// Table holding the last delivered versionstamp of each consumer
+pub const CHECKPOINT_TABLE: &str = "changefeed_checkpoint";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeAction {
+    /// The record was created or updated
+    Update,
+    /// `record` only holds the id of the deleted record
+    Delete,
+}
+
+/// A record change read from a table's change feed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangeEvent {
+    pub table: String,
+    /// Position in the feed; changes committed together share it
+    pub versionstamp: u64,
+    pub action: ChangeAction,
+    pub record: serde_json::Value,
+    /// For tables defined with `INCLUDE ORIGINAL`, the JSON patch that turns
+    /// `record` back into its previous version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original: Option<serde_json::Value>,
+}
+
+/// Destination for the events read by a [`ChangeFeedConsumer`].
+#[async_trait]
+pub trait ChangeSink: Send + Sync {
+    /// Deliver a batch in feed order. The checkpoint only moves once this
+    /// returns `Ok`, so a failed batch is delivered again and sinks must
+    /// tolerate duplicates.
+    async fn deliver(&self, events: &[ChangeEvent]) -> Result<()>;
+}
+
+/// Appends each event as a JSON line to a file, syncing it to disk before
+/// the batch counts as delivered.
+pub struct NdjsonSink {
+    file: Mutex<tokio::fs::File>,
+}
+
+impl NdjsonSink {
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path.as_ref())
+            .await
+            .map_err(|e| Error::Internal(format!("cannot open {}: {}", path.as_ref().display(), e)))?;
+
+        Ok(Self { file: Mutex::new(file) })
+    }
+}
+
+#[async_trait]
+impl ChangeSink for NdjsonSink {
+    async fn deliver(&self, events: &[ChangeEvent]) -> Result<()> {
+        let mut lines = Vec::new();
+        for event in events {
+            serde_json::to_writer(&mut lines, event)?;
+            lines.push(b'\n');
+        }
+
+        let mut file = self.file.lock().await;
+        let write = async {
+            file.write_all(&lines).await?;
+            file.sync_data().await
+        };
+        write
+            .await
+            .map_err(|e| Error::Internal(format!("cannot write change events: {}", e)))
+    }
+}
+
+/// Hands events to a task in the same process. Delivery waits while the
+/// channel is full, so a slow receiver holds the consumer back.
+pub struct ChannelSink {
+    sender: mpsc::Sender<ChangeEvent>,
+}
+
+impl ChannelSink {
+    pub fn new(sender: mpsc::Sender<ChangeEvent>) -> Self {
+        Self { sender }
+    }
+
+    /// A sink and the receiving end of a channel holding up to `capacity` events.
+    pub fn channel(capacity: usize) -> (Self, mpsc::Receiver<ChangeEvent>) {
+        let (sender, receiver) = mpsc::channel(capacity);
+        (Self::new(sender), receiver)
+    }
+}
+
+#[async_trait]
+impl ChangeSink for ChannelSink {
+    async fn deliver(&self, events: &[ChangeEvent]) -> Result<()> {
+        for event in events {
+            self.sender
+                .send(event.clone())
+                .await
+                .map_err(|_| Error::Internal("change event receiver was dropped".to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+/// A batch of changes as returned by `SHOW CHANGES`
+#[derive(Deserialize)]
+struct ChangeSet {
+    versionstamp: u64,
+    changes: Vec<serde_json::Value>,
+}
+
+/// Reads a table's change feed in batches and hands the changes to a
+/// [`ChangeSink`].
+///
+/// After each delivered batch the consumer stores its position in
+/// [`CHECKPOINT_TABLE`] under its name, and resumes from there after a
+/// restart. Delivery is at least once: a crash between delivering a batch
+/// and saving the checkpoint delivers that batch again. The table must have
+/// been defined with a `CHANGEFEED` whose retention covers the time the
+/// consumer may be down.
+pub struct ChangeFeedConsumer {
+    db: Arc<DatabaseManager>,
+    name: String,
+    table: String,
+    sink: Arc<dyn ChangeSink>,
+    batch_size: usize,
+    poll_interval: Duration,
+}
+
+impl ChangeFeedConsumer {
+    /// A consumer for `table`, checkpointed under the table's name.
+    pub fn new(db: Arc<DatabaseManager>, table: &str, sink: Arc<dyn ChangeSink>) -> Result<Self> {
+        let table = Sanitizer::new().sanitize_name(table).map_err(Error::InvalidInput)?;
+
+        Ok(Self {
+            db,
+            name: table.clone(),
+            table,
+            sink,
+            batch_size: 100,
+            poll_interval: Duration::from_secs(1),
+        })
+    }
+
+    /// Checkpoint under `name` instead, e.g. to feed one table to several sinks.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Read at most `batch_size` versionstamps per query.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// How long [`ChangeFeedConsumer::spawn`] waits once it has caught up
+    /// or after a failed batch.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The last versionstamp delivered, if any.
+    pub async fn checkpoint(&self) -> Result<Option<u64>> {
+        self.db
+            .with_timeout(None, async {
+                let conn = self.db.get_connection().await?;
+                let versionstamp: Option<u64> = conn
+                    .query("SELECT VALUE versionstamp FROM type::thing($checkpoints, $name)")
+                    .bind(("checkpoints", CHECKPOINT_TABLE))
+                    .bind(("name", self.name.clone()))
+                    .await?
+                    .take(0)?;
+                Ok(versionstamp)
+            })
+            .await
+    }
+
+    /// Deliver the next batch after the checkpoint and move the checkpoint
+    /// past it. Returns the number of events delivered.
+    pub async fn poll_once(&self) -> Result<usize> {
+        Ok(self.next_batch().await?.1)
+    }
+
+    /// The number of versionstamps read and of events delivered. Feed
+    /// entries other than record changes, e.g. table definitions, are skipped
+    /// but still move the checkpoint.
+    async fn next_batch(&self) -> Result<(usize, usize)> {
+        // SHOW CHANGES reports versionstamps with the batch counter in the
+        // upper bits, but SINCE takes the counter alone
+        let since = match self.checkpoint().await? {
+            Some(versionstamp) => (versionstamp >> 16) + 1,
+            None => 0,
+        };
+
+        // SHOW CHANGES takes neither a bound table nor a bound SINCE
+        let query = format!(
+            "SHOW CHANGES FOR TABLE {} SINCE {} LIMIT {}",
+            escape_ident(&self.table),
+            since,
+            self.batch_size
+        );
+        let sets: Vec<ChangeSet> = self
+            .db
+            .with_timeout(None, async {
+                let conn = self.db.get_connection().await?;
+                let sets: surrealdb::Value = conn.query(query).await?.take(0)?;
+                Ok::<_, Error>(serde_json::from_value(sets.into_inner().into_json())?)
+            })
+            .await?;
+
+        let Some(last) = sets.last().map(|set| set.versionstamp) else {
+            return Ok((0, 0));
+        };
+
+        let events: Vec<ChangeEvent> = sets
+            .iter()
+            .flat_map(|set| set.changes.iter().filter_map(|change| self.event(set.versionstamp, change)))
+            .collect();
+        if !events.is_empty() {
+            self.sink.deliver(&events).await?;
+        }
+        self.save_checkpoint(last).await?;
+
+        debug!(
+            "Change feed consumer {} delivered {} events up to versionstamp {}",
+            self.name,
+            events.len(),
+            last
+        );
+        Ok((sets.len(), events.len()))
+    }
+
+    fn event(&self, versionstamp: u64, change: &serde_json::Value) -> Option<ChangeEvent> {
+        let event = |action, record: &serde_json::Value, original| ChangeEvent {
+            table: self.table.clone(),
+            versionstamp,
+            action,
+            record: record.clone(),
+            original,
+        };
+
+        // With INCLUDE ORIGINAL an update of an existing record arrives as
+        // the current record plus a patch back to the original
+        if let Some(current) = change.get("current") {
+            return Some(event(ChangeAction::Update, current, change.get("update").cloned()));
+        }
+        if let Some(record) = change.get("update") {
+            return Some(event(ChangeAction::Update, record, None));
+        }
+        if let Some(record) = change.get("delete") {
+            return Some(event(ChangeAction::Delete, record, None));
+        }
+        None
+    }
+
+    async fn save_checkpoint(&self, versionstamp: u64) -> Result<()> {
+        self.db
+            .with_timeout(None, async {
+                let conn = self.db.get_connection().await?;
+                conn.query(
+                    "UPSERT type::thing($checkpoints, $name) SET source = $table, versionstamp = $versionstamp, updated_at = time::now()",
+                )
+                .bind(("checkpoints", CHECKPOINT_TABLE))
+                .bind(("name", self.name.clone()))
+                .bind(("table", self.table.clone()))
+                .bind(("versionstamp", versionstamp))
+                .await?
+                .check()?;
+                Ok(())
+            })
+            .await
+    }
+
+    /// Consume the feed until the task is aborted, polling whenever it has
+    /// caught up. Failed batches are logged and retried.
+    pub fn spawn(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                match self.next_batch().await {
+                    Ok((read, _)) if read >= self.batch_size => continue,
+                    Ok(_) => {}
+                    Err(e) => warn!("Change feed consumer {} failed, retrying: {}", self.name, e),
+                }
+                tokio::time::sleep(self.poll_interval).await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{self, ChangeFeedDefinition, FieldDefinition, TableDefinition};
+    use crate::tests::test_db;
+    use serde_json::json;
+
+    async fn feed_db() -> Arc<DatabaseManager> {
+        let db = Arc::new(test_db("test", "changefeed").await);
+        let table = TableDefinition {
+            name: "item".to_string(),
+            fields: vec![FieldDefinition {
+                name: "n".to_string(),
+                field_type: "int".to_string(),
+                required: true,
+            }],
+            indexes: vec![],
+            changefeed: Some(ChangeFeedDefinition {
+                retention_secs: 3600,
+                include_original: false,
+            }),
+        };
+        schema::create_table(&db, &table).await.unwrap();
+        db
+    }
+
+    #[test]
+    fn test_changefeed_definition() {
+        let mut table = TableDefinition {
+            name: "item".to_string(),
+            fields: vec![],
+            indexes: vec![],
+            changefeed: Some(ChangeFeedDefinition {
+                retention_secs: 60,
+                include_original: true,
+            }),
+        };
+        let surql = table.to_surql(&Sanitizer::new()).unwrap();
+        assert_eq!(surql, "DEFINE TABLE `item` SCHEMAFULL CHANGEFEED 60s INCLUDE ORIGINAL;");
+
+        table.changefeed = Some(ChangeFeedDefinition {
+            retention_secs: 0,
+            include_original: false,
+        });
+        assert!(table.to_surql(&Sanitizer::new()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_delivers_changes_and_resumes_from_checkpoint() {
+        let db = feed_db().await;
+        db.execute_query("CREATE item:a SET n = 1; UPDATE item:a SET n = 2; DELETE item:a;", Default::default())
+            .await
+            .unwrap();
+
+        let (sink, mut events) = ChannelSink::channel(16);
+        let consumer = ChangeFeedConsumer::new(db.clone(), "item", Arc::new(sink)).unwrap();
+        assert_eq!(consumer.poll_once().await.unwrap(), 3);
+
+        let received: Vec<_> = std::iter::from_fn(|| events.try_recv().ok()).collect();
+        let actions: Vec<_> = received.iter().map(|e| e.action).collect();
+        assert_eq!(actions, [ChangeAction::Update, ChangeAction::Update, ChangeAction::Delete]);
+        assert_eq!(received[1].record, json!({ "id": "item:a", "n": 2 }));
+        assert_eq!(consumer.checkpoint().await.unwrap(), Some(received[2].versionstamp));
+
+        // A new consumer with the same name picks up where the first stopped
+        db.execute_query("CREATE item:b SET n = 3", Default::default()).await.unwrap();
+        let (sink, mut events) = ChannelSink::channel(16);
+        let consumer = ChangeFeedConsumer::new(db, "item", Arc::new(sink)).unwrap();
+        assert_eq!(consumer.poll_once().await.unwrap(), 1);
+        assert_eq!(events.try_recv().unwrap().record, json!({ "id": "item:b", "n": 3 }));
+        assert_eq!(consumer.poll_once().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_failed_delivery_keeps_checkpoint() {
+        struct Failing;
+
+        #[async_trait]
+        impl ChangeSink for Failing {
+            async fn deliver(&self, _events: &[ChangeEvent]) -> Result<()> {
+                Err(Error::Internal("sink down".to_string()))
+            }
+        }
+
+        let db = feed_db().await;
+        db.execute_query("CREATE item:a SET n = 1", Default::default()).await.unwrap();
+
+        let consumer = ChangeFeedConsumer::new(db.clone(), "item", Arc::new(Failing)).unwrap();
+        assert!(consumer.poll_once().await.is_err());
+        assert_eq!(consumer.checkpoint().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_sink() {
+        let db = feed_db().await;
+        db.execute_query("CREATE item:a SET n = 1; CREATE item:b SET n = 2;", Default::default())
+            .await
+            .unwrap();
+
+        let path = std::env::temp_dir().join(format!("{}-changes.ndjson", uuid::Uuid::new_v4()));
+        let sink = NdjsonSink::open(&path).await.unwrap();
+        let consumer = ChangeFeedConsumer::new(db, "item", Arc::new(sink))
+            .unwrap()
+            .with_batch_size(1);
+        // One versionstamp per poll: the table definition, then each record
+        for _ in 0..3 {
+            consumer.poll_once().await.unwrap();
+        }
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let events: Vec<ChangeEvent> = contents.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].record["n"], 2);
+        std::fs::remove_file(path).unwrap();
+    }
+}
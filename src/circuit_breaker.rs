@@ -0,0 +1,376 @@
+// Path: src/circuit_breaker.rs
+
+use crate::telemetry::TelemetryManager;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CircuitBreakerConfig {
+    pub enabled: bool,
+    /// Number of recent calls the failure and slow-call rates are taken over
+    pub window_size: usize,
+    /// The circuit never trips before this many calls are in the window
+    pub minimum_calls: usize,
+    /// Fraction of failed calls, between 0.0 and 1.0, that opens the circuit
+    pub failure_rate_threshold: f64,
+    /// Calls taking longer than this count as slow
+    pub slow_call_ms: u64,
+    /// Fraction of slow calls, between 0.0 and 1.0, that opens the circuit
+    pub slow_call_rate_threshold: f64,
+    /// How long the circuit stays open before a health check probes the server
+    pub open_duration_ms: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            window_size: 20,
+            minimum_calls: 10,
+            failure_rate_threshold: 0.5,
+            slow_call_ms: 5_000,
+            slow_call_rate_threshold: 0.8,
+            open_duration_ms: 10_000,
+        }
+    }
+}
+
+impl CircuitBreakerConfig {
+    pub fn slow_call(&self) -> Duration {
+        Duration::from_millis(self.slow_call_ms)
+    }
+
+    pub fn open_duration(&self) -> Duration {
+        Duration::from_millis(self.open_duration_ms)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Calls go through and their outcomes are tracked
+    Closed,
+    /// Calls fail fast until the open duration has passed
+    Open,
+    /// A health check is probing whether the server has recovered
+    HalfOpen,
+}
+
+impl CircuitState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CircuitState::Closed => "closed",
+            CircuitState::Open => "open",
+            CircuitState::HalfOpen => "half_open",
+        }
+    }
+}
+
+/// What the breaker decided for a call about to be made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Admission {
+    Allow,
+    /// Run the health check probe; its result closes or reopens the circuit
+    Probe,
+    Reject,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Outcome {
+    failed: bool,
+    slow: bool,
+}
+
+struct Inner {
+    state: CircuitState,
+    window: VecDeque<Outcome>,
+    opened_at: Instant,
+}
+
+/// Tracks recent database call outcomes and stops sending calls to a server
+/// that is failing or too slow, so callers get an error right away instead
+/// of each waiting out its own timeout.
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    inner: Mutex<Inner>,
+    telemetry: Option<Arc<TelemetryManager>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig, telemetry: Option<Arc<TelemetryManager>>) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                state: CircuitState::Closed,
+                window: VecDeque::with_capacity(config.window_size),
+                opened_at: Instant::now(),
+            }),
+            config,
+            telemetry,
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.lock().state
+    }
+
+    pub(crate) fn admit(&self) -> Admission {
+        if !self.config.enabled {
+            return Admission::Allow;
+        }
+
+        let mut inner = self.lock();
+        match inner.state {
+            CircuitState::Closed => Admission::Allow,
+            CircuitState::Open if inner.opened_at.elapsed() >= self.config.open_duration() => {
+                self.transition(&mut inner, CircuitState::HalfOpen);
+                Admission::Probe
+            }
+            // Only one probe runs at a time; everything else keeps failing fast
+            CircuitState::Open | CircuitState::HalfOpen => {
+                drop(inner);
+                self.record_metric("db_circuit_rejected", vec![]);
+                Admission::Reject
+            }
+        }
+    }
+
+    /// Record a call that reached the server (or failed to). Outcomes are
+    /// only tracked while the circuit is closed.
+    pub(crate) fn record(&self, failed: bool, elapsed: Duration) {
+        if !self.config.enabled {
+            return;
+        }
+
+        let mut inner = self.lock();
+        if inner.state != CircuitState::Closed {
+            return;
+        }
+
+        if inner.window.len() == self.config.window_size.max(1) {
+            inner.window.pop_front();
+        }
+        inner.window.push_back(Outcome {
+            failed,
+            slow: elapsed >= self.config.slow_call(),
+        });
+
+        let calls = inner.window.len();
+        if calls < self.config.minimum_calls.max(1) {
+            return;
+        }
+        let failures = inner.window.iter().filter(|o| o.failed).count();
+        let slow = inner.window.iter().filter(|o| o.slow).count();
+        let failure_rate = failures as f64 / calls as f64;
+        let slow_rate = slow as f64 / calls as f64;
+
+        if failure_rate >= self.config.failure_rate_threshold || slow_rate >= self.config.slow_call_rate_threshold {
+            warn!(
+                "Opening database circuit breaker: {:.0}% failed, {:.0}% slow over {} calls",
+                failure_rate * 100.0,
+                slow_rate * 100.0,
+                calls
+            );
+            self.transition(&mut inner, CircuitState::Open);
+        }
+    }
+
+    /// Track the probe a [`Admission::Probe`] asked for. Finish it with the
+    /// health check's result; dropping it unfinished, e.g. when the caller's
+    /// future is cancelled mid-probe, reopens the circuit so a later call
+    /// can probe again.
+    pub(crate) fn probe(&self) -> Probe<'_> {
+        Probe {
+            breaker: self,
+            finished: false,
+        }
+    }
+
+    /// Close the circuit if the half-open probe succeeded, reopen it otherwise.
+    pub(crate) fn record_probe(&self, healthy: bool) {
+        let mut inner = self.lock();
+        if inner.state != CircuitState::HalfOpen {
+            return;
+        }
+
+        if healthy {
+            info!("Database health check passed, closing circuit breaker");
+            self.transition(&mut inner, CircuitState::Closed);
+        } else {
+            self.transition(&mut inner, CircuitState::Open);
+        }
+    }
+
+    fn transition(&self, inner: &mut Inner, state: CircuitState) {
+        let previous = std::mem::replace(&mut inner.state, state);
+        match state {
+            CircuitState::Closed => inner.window.clear(),
+            CircuitState::Open => inner.opened_at = Instant::now(),
+            CircuitState::HalfOpen => {}
+        }
+
+        self.record_metric(
+            "db_circuit_state_transition",
+            vec![("from", previous.as_str()), ("to", state.as_str())],
+        );
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn record_metric(&self, name: &str, attributes: Vec<(&str, &str)>) {
+        if let Some(telemetry) = &self.telemetry {
+            telemetry.record_metric(
+                name.to_string(),
+                1.0,
+                attributes
+                    .into_iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            );
+        }
+    }
+}
+
+/// A health check probe in progress; see [`CircuitBreaker::probe`].
+pub(crate) struct Probe<'a> {
+    breaker: &'a CircuitBreaker,
+    finished: bool,
+}
+
+impl Probe<'_> {
+    pub(crate) fn finish(mut self, healthy: bool) {
+        self.finished = true;
+        self.breaker.record_probe(healthy);
+    }
+}
+
+impl Drop for Probe<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            warn!("Database health check probe was cancelled, reopening circuit breaker");
+            self.breaker.record_probe(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{DatabaseConfig, DatabaseError, DatabaseManager};
+
+    fn config() -> CircuitBreakerConfig {
+        CircuitBreakerConfig {
+            enabled: true,
+            window_size: 4,
+            minimum_calls: 4,
+            failure_rate_threshold: 0.5,
+            slow_call_ms: 100,
+            slow_call_rate_threshold: 0.75,
+            open_duration_ms: 0,
+        }
+    }
+
+    #[test]
+    fn test_trips_on_error_rate() {
+        let breaker = CircuitBreaker::new(config(), None);
+        breaker.record(true, Duration::ZERO);
+        breaker.record(true, Duration::ZERO);
+        breaker.record(false, Duration::ZERO);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        breaker.record(false, Duration::ZERO);
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_trips_on_latency() {
+        let breaker = CircuitBreaker::new(config(), None);
+        for _ in 0..3 {
+            breaker.record(false, Duration::from_millis(500));
+        }
+        breaker.record(false, Duration::ZERO);
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_half_open_probe() {
+        let breaker = CircuitBreaker::new(config(), None);
+        for _ in 0..4 {
+            breaker.record(true, Duration::ZERO);
+        }
+
+        // With a zero open duration the next call becomes the probe
+        assert_eq!(breaker.admit(), Admission::Probe);
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert_eq!(breaker.admit(), Admission::Reject);
+
+        breaker.record_probe(false);
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        assert_eq!(breaker.admit(), Admission::Probe);
+        breaker.record_probe(true);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert_eq!(breaker.admit(), Admission::Allow);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_probe_reopens_circuit() {
+        let breaker = CircuitBreaker::new(config(), None);
+        for _ in 0..4 {
+            breaker.record(true, Duration::ZERO);
+        }
+
+        // The caller gives up while the health check is still running
+        assert_eq!(breaker.admit(), Admission::Probe);
+        let probing = async {
+            let probe = breaker.probe();
+            std::future::pending::<()>().await;
+            probe.finish(true);
+        };
+        assert!(tokio::time::timeout(Duration::from_millis(10), probing).await.is_err());
+
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert_eq!(breaker.admit(), Admission::Probe);
+        breaker.probe().finish(true);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_open_circuit_fails_fast() {
+        let mut db_config = DatabaseConfig::in_memory("test", "circuit");
+        db_config.circuit_breaker = CircuitBreakerConfig {
+            minimum_calls: 1,
+            window_size: 1,
+            open_duration_ms: 60_000,
+            ..CircuitBreakerConfig::default()
+        };
+        db_config.query_timeout_ms = 50;
+        let db = DatabaseManager::new(db_config).await.unwrap();
+        let sleep = || async {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            Ok::<_, DatabaseError>(())
+        };
+
+        // A shorter limit from the caller, e.g. a client deadline, is not
+        // the server's fault
+        let result = db.with_timeout(Some(Duration::from_millis(10)), sleep()).await;
+        assert!(matches!(result, Err(DatabaseError::Timeout(_))));
+        assert_eq!(db.circuit_state(), CircuitState::Closed);
+
+        // The configured limit counts as a failure and trips the one-call window
+        let result = db.with_timeout(None, sleep()).await;
+        assert!(matches!(result, Err(DatabaseError::Timeout(_))));
+        assert_eq!(db.circuit_state(), CircuitState::Open);
+
+        let error = db.execute_query("RETURN 1", Default::default()).await.unwrap_err();
+        assert!(matches!(error, DatabaseError::CircuitOpen));
+        assert!(error.is_retryable());
+
+        // The health check bypasses the breaker so it can serve as the probe
+        assert!(db.health_check().await.is_ok());
+    }
+}
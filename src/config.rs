@@ -0,0 +1,450 @@
+// Path: src/config.rs
+
+use crate::anomaly_detection::AnomalyDetectionConfig;
+use crate::db::DatabaseConfig;
+use crate::migrations::MigrationConfig;
+use crate::reload::ReloadConfig;
+use crate::security::SecurityConfig;
+use crate::telemetry::TelemetryConfig;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tracing_subscriber::EnvFilter;
+
+/// Prefix for environment overrides, e.g. `OMNIPRO_DATABASE__POOL__MAX_SIZE=20`.
+/// Nested keys are separated by a double underscore.
+pub const ENV_PREFIX: &str = "OMNIPRO_";
+
+/// Environment variables understood before the config file existed. They
+/// still win over the file so existing deployments keep working.
+const LEGACY_ENV_VARS: &[(&str, &[&str])] = &[
+    ("DB_ENGINE", &["database", "engine"]),
+    ("DB_URL", &["database", "url"]),
+    ("DB_NAMESPACE", &["database", "namespace"]),
+    ("DB_NAME", &["database", "database"]),
+    ("DB_USER", &["database", "username"]),
+    ("DB_PASS", &["database", "password"]),
+];
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Failed to read config file {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Failed to parse config file {path}: {message}")]
+    Parse { path: PathBuf, message: String },
+
+    #[error("Unsupported config format for {0} (expected .yaml, .yml or .toml)")]
+    UnsupportedFormat(PathBuf),
+
+    #[error("Invalid value in environment variable {var}: {message}")]
+    Env { var: String, message: String },
+
+    #[error("Invalid configuration:\n  - {}", .0.join("\n  - "))]
+    Invalid(Vec<String>),
+}
+
+pub type ConfigResult<T> = Result<T, ConfigError>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Address the gRPC server listens on
+    pub bind_address: String,
+    /// Endpoints a ConnectDb call may switch to besides `database.url`.
+    /// Any other url in the request is refused.
+    pub connect_urls: Vec<String>,
+    /// Directory the Backup and Restore RPCs read and write archives in.
+    /// Paths in requests are relative to it and may not leave it.
+    pub backup_dir: PathBuf,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: "[::1]:50051".to_string(),
+            connect_urls: Vec::new(),
+            backup_dir: PathBuf::from("backups"),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn socket_addr(&self) -> Result<SocketAddr, String> {
+        self.bind_address
+            .parse()
+            .map_err(|e| format!("server.bind_address `{}` is not a socket address: {}", self.bind_address, e))
+    }
+}
+
+/// Complete application configuration: defaults, overlaid by a YAML or TOML
+/// file, overlaid by environment variables.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    pub database: DatabaseConfig,
+    pub server: ServerConfig,
+    pub telemetry: TelemetryConfig,
+    pub security: SecurityConfig,
+    pub anomaly_detection: AnomalyDetectionConfig,
+    pub reload: ReloadConfig,
+    pub migrations: MigrationConfig,
+}
+
+impl AppConfig {
+    /// Load the configuration from `path` (if any) and the process
+    /// environment, then validate it.
+    pub fn load(path: Option<&Path>) -> ConfigResult<Self> {
+        let base = match path {
+            Some(path) => Self::read_file(path)?,
+            None => serde_json::to_value(Self::default()).expect("default config serializes"),
+        };
+
+        // The file was already checked when it was read and every override
+        // is checked as it is applied, so errors name their actual source
+        let merged = apply_env_overrides(base, std::env::vars())?;
+        let config: Self = serde_json::from_value(merged)
+            .map_err(|e| ConfigError::Invalid(vec![e.to_string()]))?;
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Parse a config file without applying environment overrides or validating.
+    pub fn from_file(path: &Path) -> ConfigResult<Self> {
+        let value = Self::read_file(path)?;
+        serde_json::from_value(value).map_err(|e| ConfigError::Parse {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })
+    }
+
+    fn read_file(path: &Path) -> ConfigResult<serde_json::Value> {
+        let contents = std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        let parse_error = |message: String| ConfigError::Parse {
+            path: path.to_path_buf(),
+            message,
+        };
+
+        // Round-trip through the typed config so missing keys pick up defaults
+        let config: Self = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml") | Some("yml") => {
+                serde_yaml::from_str(&contents).map_err(|e| parse_error(e.to_string()))?
+            }
+            Some("toml") => toml::from_str(&contents).map_err(|e| parse_error(e.to_string()))?,
+            _ => return Err(ConfigError::UnsupportedFormat(path.to_path_buf())),
+        };
+
+        Ok(serde_json::to_value(config).expect("config serializes"))
+    }
+
+    /// Check every section and report all problems at once.
+    pub fn validate(&self) -> ConfigResult<()> {
+        let mut errors = Vec::new();
+
+        let db = &self.database;
+        if let Err(e) = db.endpoint() {
+            errors.push(format!("database: {}", e));
+        }
+        if db.namespace.trim().is_empty() {
+            errors.push("database.namespace must not be empty".to_string());
+        }
+        if db.database.trim().is_empty() {
+            errors.push("database.database must not be empty".to_string());
+        }
+        if !db.engine.is_embedded() && db.username.is_empty() {
+            errors.push("database.username is required for remote engines".to_string());
+        }
+        if db.pool.max_size == 0 {
+            errors.push("database.pool.max_size must be at least 1".to_string());
+        }
+        if db.pool.min_size > db.pool.max_size {
+            errors.push(format!(
+                "database.pool.min_size ({}) exceeds max_size ({})",
+                db.pool.min_size, db.pool.max_size
+            ));
+        }
+        if db.replicas.urls.iter().any(|url| url.trim().is_empty()) {
+            errors.push("database.replicas.urls must not contain empty urls".to_string());
+        }
+        let breaker = &db.circuit_breaker;
+        if breaker.window_size == 0 || breaker.minimum_calls == 0 {
+            errors.push("database.circuit_breaker.window_size and minimum_calls must be at least 1".to_string());
+        }
+        if breaker.minimum_calls > breaker.window_size {
+            errors.push(format!(
+                "database.circuit_breaker.minimum_calls ({}) exceeds window_size ({})",
+                breaker.minimum_calls, breaker.window_size
+            ));
+        }
+        for (name, rate) in [
+            ("failure_rate_threshold", breaker.failure_rate_threshold),
+            ("slow_call_rate_threshold", breaker.slow_call_rate_threshold),
+        ] {
+            if !(rate > 0.0 && rate <= 1.0) {
+                errors.push(format!("database.circuit_breaker.{} must be above 0.0 and at most 1.0", name));
+            }
+        }
+        if db.reconnect.multiplier < 1.0 {
+            errors.push("database.reconnect.multiplier must be at least 1.0".to_string());
+        }
+        if !(0.0..=1.0).contains(&db.reconnect.jitter) {
+            errors.push("database.reconnect.jitter must be between 0.0 and 1.0".to_string());
+        }
+        if db.reconnect.initial_backoff_ms > db.reconnect.max_backoff_ms {
+            errors.push("database.reconnect.initial_backoff_ms exceeds max_backoff_ms".to_string());
+        }
+
+        let lock = &self.migrations.lock;
+        if lock.enabled && (lock.lease_ms == 0 || lock.poll_interval_ms == 0) {
+            errors.push("migrations.lock.lease_ms and poll_interval_ms must be at least 1".to_string());
+        }
+
+        if let Err(e) = self.server.socket_addr() {
+            errors.push(e);
+        }
+        if self.server.backup_dir.as_os_str().is_empty() {
+            errors.push("server.backup_dir must not be empty".to_string());
+        }
+        if self.server.connect_urls.iter().any(|url| url.trim().is_empty()) {
+            errors.push("server.connect_urls must not contain empty urls".to_string());
+        }
+
+        if let Err(e) = EnvFilter::try_new(&self.telemetry.log_filter) {
+            errors.push(format!("telemetry.log_filter is invalid: {}", e));
+        }
+
+        errors.extend(validate_security(&self.security));
+        errors.extend(validate_anomaly_detection(&self.anomaly_detection));
+
+        if self.reload.poll_interval_ms == 0 {
+            errors.push("reload.poll_interval_ms must be at least 1".to_string());
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(errors))
+        }
+    }
+}
+
+fn validate_security(security: &SecurityConfig) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    if security.password_min_length == 0 {
+        errors.push("security.password_min_length must be at least 1".to_string());
+    }
+    if security.allowed_roles.is_empty() {
+        errors.push("security.allowed_roles must not be empty".to_string());
+    }
+    for role in &security.allowed_roles {
+        if role.is_empty() || !role.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            errors.push(format!("security.allowed_roles contains an invalid role `{}`", role));
+        }
+    }
+    if security.blocked_patterns.iter().any(|p| p.is_empty()) {
+        errors.push("security.blocked_patterns must not contain empty patterns".to_string());
+    }
+
+    errors
+}
+
+fn validate_anomaly_detection(anomaly: &AnomalyDetectionConfig) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    if anomaly.window_size < 2 {
+        errors.push("anomaly_detection.window_size must be at least 2".to_string());
+    }
+    if anomaly.threshold_multiplier <= 1.0 {
+        errors.push("anomaly_detection.threshold_multiplier must be greater than 1.0".to_string());
+    }
+
+    errors
+}
+
+/// Overlay environment variables onto a serialized config. Values are parsed
+/// as JSON when possible (numbers, booleans, arrays) and taken as strings
+/// otherwise. A value of the wrong type is reported against its variable.
+fn apply_env_overrides(
+    mut config: serde_json::Value,
+    vars: impl Iterator<Item = (String, String)>,
+) -> ConfigResult<serde_json::Value> {
+    let mut overrides = Vec::new();
+    let mut legacy = Vec::new();
+
+    for (var, raw) in vars {
+        if let Some(key) = var.strip_prefix(ENV_PREFIX) {
+            if key == "CONFIG" {
+                continue;
+            }
+            let path: Vec<String> = key.split("__").map(|part| part.to_lowercase()).collect();
+            overrides.push((var, path, raw));
+        } else if let Some((_, path)) = LEGACY_ENV_VARS.iter().find(|(name, _)| *name == var) {
+            legacy.push((var, path.iter().map(|p| p.to_string()).collect(), raw));
+        }
+    }
+
+    // Prefixed variables take precedence over the legacy names
+    for (var, path, raw) in legacy.into_iter().chain(overrides) {
+        let value = serde_json::from_str(&raw).unwrap_or(serde_json::Value::String(raw));
+        let mut updated = config.clone();
+        set_path(&mut updated, &path, value)
+            .and_then(|()| serde_json::from_value::<AppConfig>(updated.clone()).map_err(|e| e.to_string()))
+            .map_err(|message| ConfigError::Env { var, message })?;
+        config = updated;
+    }
+
+    Ok(config)
+}
+
+fn set_path(target: &mut serde_json::Value, path: &[String], value: serde_json::Value) -> Result<(), String> {
+    let (last, parents) = path.split_last().ok_or_else(|| "empty key".to_string())?;
+
+    let mut current = target;
+    for part in parents {
+        current = current
+            .get_mut(part)
+            .filter(|v| v.is_object())
+            .ok_or_else(|| format!("unknown config section `{}`", part))?;
+    }
+
+    let section = current.as_object_mut().ok_or_else(|| "not a config section".to_string())?;
+    match section.get(last) {
+        // Keep string settings as strings even if they look like numbers
+        Some(serde_json::Value::String(_)) => {
+            let value = match value {
+                serde_json::Value::String(s) => s,
+                other => other.to_string(),
+            };
+            section.insert(last.clone(), serde_json::Value::String(value));
+        }
+        Some(_) => {
+            section.insert(last.clone(), value);
+        }
+        None => return Err(format!("unknown config key `{}`", last)),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::DatabaseEngine;
+
+    fn vars(pairs: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn write_temp(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}", uuid::Uuid::new_v4(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_loads_yaml_with_defaults() {
+        let path = write_temp("config.yaml", "database:\n  engine: memory\n  namespace: app\nserver:\n  bind_address: 0.0.0.0:6000\n");
+        let config = AppConfig::from_file(&path).unwrap();
+
+        assert_eq!(config.database.engine, DatabaseEngine::Memory);
+        assert_eq!(config.database.namespace, "app");
+        assert_eq!(config.database.database, "test");
+        assert_eq!(config.server.bind_address, "0.0.0.0:6000");
+        assert_eq!(config.database.pool.max_size, 10);
+    }
+
+    #[test]
+    fn test_loads_toml() {
+        let path = write_temp("config.toml", "[database]\nurl = \"ws://db:8000\"\n\n[anomaly_detection]\nwindow_size = 50\n");
+        let config = AppConfig::from_file(&path).unwrap();
+
+        assert_eq!(config.database.url, "ws://db:8000");
+        assert_eq!(config.anomaly_detection.window_size, 50);
+    }
+
+    #[test]
+    fn test_rejects_unknown_extension() {
+        let path = write_temp("config.ini", "");
+        assert!(matches!(AppConfig::from_file(&path), Err(ConfigError::UnsupportedFormat(_))));
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let base = serde_json::to_value(AppConfig::default()).unwrap();
+        let merged = apply_env_overrides(base, vars(&[
+            ("DB_NAME", "legacy"),
+            ("OMNIPRO_DATABASE__DATABASE", "prefixed"),
+            ("OMNIPRO_DATABASE__POOL__MAX_SIZE", "32"),
+            ("OMNIPRO_DATABASE__PASSWORD", "12345"),
+            ("OMNIPRO_SECURITY__ALLOWED_ROLES", "[\"ops\"]"),
+            ("UNRELATED", "ignored"),
+        ])).unwrap();
+        let config: AppConfig = serde_json::from_value(merged).unwrap();
+
+        assert_eq!(config.database.database, "prefixed");
+        assert_eq!(config.database.pool.max_size, 32);
+        assert_eq!(config.database.password, "12345");
+        assert_eq!(config.security.allowed_roles, vec!["ops".to_string()]);
+    }
+
+    #[test]
+    fn test_env_override_unknown_key() {
+        let base = serde_json::to_value(AppConfig::default()).unwrap();
+        let result = apply_env_overrides(base, vars(&[("OMNIPRO_DATABASE__NOPE", "1")]));
+        assert!(matches!(result, Err(ConfigError::Env { .. })));
+    }
+
+    #[test]
+    fn test_env_override_wrong_type() {
+        let base = serde_json::to_value(AppConfig::default()).unwrap();
+        let result = apply_env_overrides(base, vars(&[
+            ("OMNIPRO_DATABASE__NAMESPACE", "app"),
+            ("OMNIPRO_DATABASE__POOL__MAX_SIZE", "lots"),
+        ]));
+        assert!(
+            matches!(&result, Err(ConfigError::Env { var, .. }) if var == "OMNIPRO_DATABASE__POOL__MAX_SIZE"),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_bad_file_value_names_the_file() {
+        let path = write_temp("config.yaml", "database:\n  pool:\n    max_size: lots\n");
+        match AppConfig::load(Some(&path)) {
+            Err(ConfigError::Parse { path: reported, message }) => {
+                assert_eq!(reported, path);
+                assert!(message.contains("max_size"), "{}", message);
+            }
+            other => panic!("expected a parse error for the file, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_validation_reports_every_problem() {
+        let mut config = AppConfig::default();
+        config.server.bind_address = "not an address".to_string();
+        config.database.pool.min_size = 20;
+        config.anomaly_detection.threshold_multiplier = 0.5;
+
+        match config.validate() {
+            Err(ConfigError::Invalid(errors)) => assert_eq!(errors.len(), 3, "{:?}", errors),
+            other => panic!("expected validation errors, got {:?}", other),
+        }
+        assert!(AppConfig::default().validate().is_ok());
+    }
+}
@@ -1,61 +1,788 @@
 // Path: src/db.rs
 
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
 use std::sync::Arc;
-use surrealdb::engine::remote::ws::{Client, Ws};
-use surrealdb::Surreal;
+use std::time::Duration;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use surrealdb::method::QueryStream;
+use surrealdb::sql::Thing;
 use thiserror::Error;
+use crate::bulk::{self, ExportOptions, ImportOptions, ImportReport};
+use crate::cache::{referenced_tables, CacheConfig, CacheStats, QueryCache};
+use crate::circuit_breaker::{Admission, CircuitBreaker, CircuitBreakerConfig, CircuitState};
+use crate::live::LiveStream;
+use crate::pagination::{CursorSigner, Page, PageRequest};
+use crate::pool::{ConnectionPool, PoolConfig, PoolStatus, PooledConnection};
+use crate::reconnect::{ConnectionState, ReconnectConfig};
+use crate::replica::{is_read_only, ReadPreference, ReplicaConfig, ReplicaSet};
+use crate::telemetry::TelemetryManager;
+use crate::transaction::{transaction_error, Transaction};
+use futures::StreamExt;
+use std::future::Future;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+pub use crate::user::{User, UserError, UserRepository, UserResult};
 
 #[derive(Debug, Error)]
 pub enum DatabaseError {
     #[error("Database error: {0}")]
-    DatabaseError(#[from] surrealdb::Error),
+    DatabaseError(Box<surrealdb::Error>),
     #[error("Invalid input: {0}")]
     InvalidInput(String),
+    #[error("IO error: {0}")]
+    IOError(#[from] std::io::Error),
+    #[error("No pooled connection available within {0:?}")]
+    PoolExhausted(Duration),
+    #[error("Database unavailable: {0}")]
+    Unavailable(String),
+    #[error("Query timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("Database circuit breaker is open")]
+    CircuitOpen,
+}
+
+impl DatabaseError {
+    /// Whether the operation may succeed if retried later, e.g. once the
+    /// pool has reconnected to the server.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            DatabaseError::PoolExhausted(_) | DatabaseError::Unavailable(_) | DatabaseError::CircuitOpen
+        )
+    }
+}
+
+impl From<surrealdb::Error> for DatabaseError {
+    fn from(error: surrealdb::Error) -> Self {
+        use surrealdb::error::Api;
+
+        match error {
+            surrealdb::Error::Api(Api::Ws(e) | Api::Http(e)) => DatabaseError::Unavailable(e),
+            surrealdb::Error::Api(Api::ConnectionUninitialised) => {
+                DatabaseError::Unavailable("connection uninitialised".to_string())
+            }
+            other => DatabaseError::DatabaseError(Box::new(other)),
+        }
+    }
 }
 
 pub type DatabaseResult<T> = Result<T, DatabaseError>;
 
+/// Storage engine used to reach SurrealDB.
+///
+/// Remote engines connect to a running server at `url`. Embedded engines run
+/// the datastore in-process; the on-disk ones treat `url` as a path and are
+/// only available when the matching cargo feature is enabled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DatabaseEngine {
+    #[default]
+    Ws,
+    Http,
+    Memory,
+    RocksDb,
+    SurrealKv,
+}
+
+impl DatabaseEngine {
+    fn scheme(self) -> &'static str {
+        match self {
+            DatabaseEngine::Ws => "ws",
+            DatabaseEngine::Http => "http",
+            DatabaseEngine::Memory => "mem",
+            DatabaseEngine::RocksDb => "rocksdb",
+            DatabaseEngine::SurrealKv => "surrealkv",
+        }
+    }
+
+    pub fn is_embedded(self) -> bool {
+        !matches!(self, DatabaseEngine::Ws | DatabaseEngine::Http)
+    }
+}
+
+impl std::str::FromStr for DatabaseEngine {
+    type Err = DatabaseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ws" => Ok(DatabaseEngine::Ws),
+            "http" => Ok(DatabaseEngine::Http),
+            "memory" | "mem" => Ok(DatabaseEngine::Memory),
+            "rocksdb" => Ok(DatabaseEngine::RocksDb),
+            "surrealkv" => Ok(DatabaseEngine::SurrealKv),
+            other => Err(DatabaseError::InvalidInput(format!("unknown database engine: {}", other))),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct DatabaseConfig {
+    pub engine: DatabaseEngine,
     pub url: String,
     pub namespace: String,
     pub database: String,
     pub username: String,
     pub password: String,
+    pub pool: PoolConfig,
+    pub reconnect: ReconnectConfig,
+    pub replicas: ReplicaConfig,
+    pub circuit_breaker: CircuitBreakerConfig,
+    /// Default limit for a single query or transaction; 0 disables it
+    pub query_timeout_ms: u64,
+    /// Key that signs page tokens. When empty a random key is used, so
+    /// tokens only work on the instance that issued them until it restarts.
+    pub cursor_secret: String,
+    pub cache: CacheConfig,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            engine: DatabaseEngine::Ws,
+            url: "ws://localhost:8000".to_string(),
+            namespace: "test".to_string(),
+            database: "test".to_string(),
+            username: "root".to_string(),
+            password: "root".to_string(),
+            pool: PoolConfig::default(),
+            reconnect: ReconnectConfig::default(),
+            replicas: ReplicaConfig::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            query_timeout_ms: 30_000,
+            cursor_secret: String::new(),
+            cache: CacheConfig::default(),
+        }
+    }
+}
+
+impl DatabaseConfig {
+    /// Configuration for a throwaway in-process datastore.
+    pub fn in_memory(namespace: &str, database: &str) -> Self {
+        Self {
+            engine: DatabaseEngine::Memory,
+            url: String::new(),
+            namespace: namespace.to_string(),
+            database: database.to_string(),
+            username: String::new(),
+            password: String::new(),
+            pool: PoolConfig::default(),
+            reconnect: ReconnectConfig::default(),
+            replicas: ReplicaConfig::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            query_timeout_ms: 30_000,
+            cursor_secret: String::new(),
+            cache: CacheConfig::default(),
+        }
+    }
+
+    pub fn query_timeout(&self) -> Option<Duration> {
+        (self.query_timeout_ms > 0).then(|| Duration::from_millis(self.query_timeout_ms))
+    }
+
+    /// The endpoint handed to `surrealdb::engine::any::connect`. A `url` that
+    /// already carries a scheme (e.g. `wss://`) is used as is.
+    pub fn endpoint(&self) -> Result<String, String> {
+        match self.engine {
+            DatabaseEngine::Memory => return Ok("mem://".to_string()),
+            #[cfg(not(feature = "kv-rocksdb"))]
+            DatabaseEngine::RocksDb => {
+                return Err("the rocksdb engine requires the `kv-rocksdb` feature".to_string())
+            }
+            #[cfg(not(feature = "kv-surrealkv"))]
+            DatabaseEngine::SurrealKv => {
+                return Err("the surrealkv engine requires the `kv-surrealkv` feature".to_string())
+            }
+            _ => {}
+        }
+
+        if self.url.is_empty() {
+            return Err(format!("a url is required for the {} engine", self.engine.scheme()));
+        }
+
+        if self.url.contains("://") {
+            Ok(self.url.clone())
+        } else {
+            Ok(format!("{}://{}", self.engine.scheme(), self.url))
+        }
+    }
 }
 
+/// Per-call overrides for [`DatabaseManager::execute_query_with`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QueryOptions {
+    /// Replaces the configured query timeout
+    pub timeout: Option<Duration>,
+    pub read_preference: ReadPreference,
+    /// Answer a read-only query from the result cache when it is enabled,
+    /// keeping a fresh result for this long; `None` always asks the database
+    pub cache_ttl: Option<Duration>,
+}
+
+/// Entry point to the database. Writes and transactions always go to the
+/// primary; read-only queries are spread over the configured replicas. A
+/// circuit breaker in front of both fails calls fast while the database is
+/// degraded.
 pub struct DatabaseManager {
-    client: Arc<Surreal<Client>>,
+    pool: Arc<ConnectionPool>,
+    replicas: Arc<ReplicaSet>,
+    breaker: CircuitBreaker,
+    query_timeout: Option<Duration>,
+    cursors: CursorSigner,
+    cache: Option<Arc<QueryCache>>,
 }
 
 impl DatabaseManager {
     pub async fn new(config: DatabaseConfig) -> DatabaseResult<Self> {
-        let client = Surreal::new::<Ws>(&config.url).await?;
-        
-        client
-            .signin(surrealdb::opt::auth::Root {
-                username: &config.username,
-                password: &config.password,
-            })
-            .await?;
-        
-        client.use_ns(&config.namespace).use_db(&config.database).await?;
-        
-        Ok(Self { 
-            client: Arc::new(client)
+        Ok(Self {
+            query_timeout: config.query_timeout(),
+            cursors: CursorSigner::from_secret(&config.cursor_secret),
+            cache: config.cache.enabled.then(|| Arc::new(QueryCache::new(config.cache.clone(), None))),
+            replicas: ReplicaSet::new(&config, None).await?,
+            breaker: CircuitBreaker::new(config.circuit_breaker.clone(), None),
+            pool: ConnectionPool::new(config, None).await?,
+        })
+    }
+
+    /// Like [`DatabaseManager::new`], reporting pool metrics through `telemetry`.
+    pub async fn with_telemetry(
+        config: DatabaseConfig,
+        telemetry: Arc<TelemetryManager>,
+    ) -> DatabaseResult<Self> {
+        Ok(Self {
+            query_timeout: config.query_timeout(),
+            cursors: CursorSigner::from_secret(&config.cursor_secret),
+            cache: config
+                .cache
+                .enabled
+                .then(|| Arc::new(QueryCache::new(config.cache.clone(), Some(telemetry.clone())))),
+            replicas: ReplicaSet::new(&config, Some(telemetry.clone())).await?,
+            breaker: CircuitBreaker::new(config.circuit_breaker.clone(), Some(telemetry.clone())),
+            pool: ConnectionPool::new(config, Some(telemetry)).await?,
         })
     }
 
-    pub async fn get_connection(&self) -> DatabaseResult<Arc<Surreal<Client>>> {
-        Ok(self.client.clone())
+    /// Check a connection to the primary out of the pool. It is returned
+    /// when dropped, so hold it only for the duration of the work that needs it.
+    pub async fn get_connection(&self) -> DatabaseResult<PooledConnection> {
+        self.admit().await?;
+        self.checkout(&self.pool).await
     }
 
+    /// A connection for read-only work: a replica chosen by the configured
+    /// routing, or the primary when `preference` asks for it or no replica
+    /// is reachable.
+    pub async fn get_read_connection(&self, preference: ReadPreference) -> DatabaseResult<PooledConnection> {
+        self.admit().await?;
+        if preference == ReadPreference::Replica {
+            if let Some(pool) = self.replicas.pick() {
+                match pool.get().await {
+                    Ok(conn) => {
+                        self.replicas.record_route("replica");
+                        return Ok(conn);
+                    }
+                    Err(e) if e.is_retryable() => {
+                        tracing::debug!("Replica unavailable, reading from the primary: {}", e);
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        self.replicas.record_route("primary");
+        self.checkout(&self.pool).await
+    }
+
+    /// Let a call through the circuit breaker. When the circuit has been
+    /// open long enough, this call runs the health check probe first.
+    async fn admit(&self) -> DatabaseResult<()> {
+        match self.breaker.admit() {
+            Admission::Allow => Ok(()),
+            Admission::Reject => Err(DatabaseError::CircuitOpen),
+            Admission::Probe => {
+                let probe = self.breaker.probe();
+                let healthy = self.health_check().await.is_ok();
+                probe.finish(healthy);
+                if healthy {
+                    Ok(())
+                } else {
+                    Err(DatabaseError::CircuitOpen)
+                }
+            }
+        }
+    }
+
+    /// Check out of `pool`, counting an unreachable server against the breaker.
+    async fn checkout(&self, pool: &Arc<ConnectionPool>) -> DatabaseResult<PooledConnection> {
+        let started = std::time::Instant::now();
+        let result = pool.get().await;
+        if let Err(e) = &result {
+            if e.is_retryable() {
+                self.breaker.record(true, started.elapsed());
+            }
+        }
+        result
+    }
+
+    pub fn circuit_state(&self) -> CircuitState {
+        self.breaker.state()
+    }
+
+    /// Connection state of each configured replica, keyed by url.
+    pub fn replica_states(&self) -> Vec<(String, ConnectionState)> {
+        self.replicas.states()
+    }
+
+    pub fn pool_status(&self) -> PoolStatus {
+        self.pool.status()
+    }
+
+    pub fn connection_state(&self) -> ConnectionState {
+        self.pool.state()
+    }
+
+    /// The configured default query timeout.
+    pub fn query_timeout(&self) -> Option<Duration> {
+        self.query_timeout
+    }
+
+    /// Signs and checks the page tokens of every list API.
+    pub fn cursor_signer(&self) -> &CursorSigner {
+        &self.cursors
+    }
+
+    /// Hit and miss counts of the result cache, `None` when it is disabled.
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(|cache| cache.stats())
+    }
+
+    /// Drop cached results read from any of `tables`, e.g. after writing to
+    /// them through a connection the manager does not see.
+    pub fn invalidate_cache(&self, tables: &[&str]) {
+        if let Some(cache) = &self.cache {
+            for table in tables {
+                cache.invalidate_table(table);
+            }
+        }
+    }
+
+    pub fn clear_cache(&self) {
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+    }
+
+    /// Drop cached results `query` may have changed: those read from the
+    /// tables it names, or all of them when the tables cannot be told.
+    pub(crate) fn invalidate_query(&self, query: &str) {
+        let Some(cache) = &self.cache else {
+            return;
+        };
+        match referenced_tables(query) {
+            Some(tables) => tables.iter().for_each(|table| cache.invalidate_table(table)),
+            None => cache.clear(),
+        }
+    }
+
+    /// Serve `key` from the result cache, or run `fetch` and remember what
+    /// it returns for `ttl` (the configured default when `None`). `tables`
+    /// are the tables `fetch` reads; each gets a live query so writes from
+    /// other clients drop the result too. Without the cache this is `fetch`.
+    pub(crate) async fn cached<T, E, F>(&self, key: String, tables: &[&str], ttl: Option<Duration>, fetch: F) -> Result<T, E>
+    where
+        T: Serialize + DeserializeOwned,
+        F: Future<Output = Result<T, E>>,
+    {
+        let Some(cache) = &self.cache else {
+            return fetch.await;
+        };
+        if let Some(value) = cache.get(&key).and_then(|value| serde_json::from_value(value).ok()) {
+            return Ok(value);
+        }
+
+        let tables: Vec<String> = tables.iter().map(|table| table.to_string()).collect();
+        if !self.watch_tables(cache, &tables).await {
+            return fetch.await;
+        }
+        let generation = cache.generation();
+        let value = fetch.await?;
+        if let Ok(json) = serde_json::to_value(&value) {
+            let ttl = ttl.unwrap_or_else(|| cache.config().default_ttl());
+            cache.insert(&key, &tables, json, ttl, generation);
+        }
+        Ok(value)
+    }
+
+    /// Make sure a live query invalidates each of `tables`. Returns false
+    /// when one cannot be subscribed, since its results could then go stale.
+    async fn watch_tables(&self, cache: &Arc<QueryCache>, tables: &[String]) -> bool {
+        for table in tables {
+            if !cache.watch(table) {
+                continue;
+            }
+            let mut stream = match self.live_table_json(table).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    tracing::debug!("Not caching reads of {}: {}", table, e);
+                    cache.unwatch(table);
+                    return false;
+                }
+            };
+
+            let weak = Arc::downgrade(cache);
+            let table = table.clone();
+            let watcher = tokio::spawn(async move {
+                while stream.next().await.is_some() {
+                    match weak.upgrade() {
+                        Some(cache) => cache.invalidate_table(&table),
+                        None => return,
+                    }
+                }
+                // Without the live query nothing would invalidate the table;
+                // the next cached read subscribes again
+                if let Some(cache) = weak.upgrade() {
+                    cache.unwatch(&table);
+                    cache.invalidate_table(&table);
+                }
+            });
+            cache.add_watcher(watcher.abort_handle());
+        }
+        true
+    }
+
+    /// Run `operation` under `timeout`, or the configured default when
+    /// `None`. When the limit is hit the operation is dropped, which cancels
+    /// the request on the client side, and [`DatabaseError::Timeout`] is
+    /// returned. Slow calls count against the circuit breaker, and so do
+    /// timeouts unless the limit was shorter than the configured one, e.g.
+    /// a client's deadline.
+    pub async fn with_timeout<T, E, F>(&self, timeout: Option<Duration>, operation: F) -> Result<T, E>
+    where
+        F: Future<Output = Result<T, E>>,
+        E: From<DatabaseError>,
+    {
+        let started = std::time::Instant::now();
+        let result = match timeout.or(self.query_timeout) {
+            Some(limit) => match tokio::time::timeout(limit, operation).await {
+                Ok(result) => result,
+                Err(_) => {
+                    if self.query_timeout.is_some_and(|configured| limit >= configured) {
+                        self.breaker.record(true, started.elapsed());
+                    }
+                    return Err(E::from(DatabaseError::Timeout(limit)));
+                }
+            },
+            None => operation.await,
+        };
+
+        // Other failures are counted at checkout; query errors are the caller's
+        if result.is_ok() {
+            self.breaker.record(false, started.elapsed());
+        }
+        result
+    }
+
+    /// Ping the primary. This bypasses the circuit breaker, which uses it to
+    /// probe whether the server has recovered.
     pub async fn health_check(&self) -> DatabaseResult<()> {
-        self.client
-            .health()
-            .await
-            .map_err(|e| DatabaseError::DatabaseError(e))?;
+        self.with_timeout(None, async {
+            self.checkout(&self.pool)
+                .await?
+                .health()
+                .await
+                .map_err(DatabaseError::from)
+        })
+        .await
+    }
+
+    /// Run `f` and commit the statements it queues on `tx` atomically.
+    /// If `f` returns an error nothing is sent. If any statement fails the
+    /// whole transaction is rolled back and the failing statement's error is
+    /// returned.
+    pub async fn transaction<F, Fut, T, E>(&self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(Transaction) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: From<DatabaseError>,
+    {
+        self.transaction_with_timeout(None, f).await
+    }
+
+    /// Like [`DatabaseManager::transaction`], limiting the commit to `timeout`
+    /// instead of the configured default.
+    pub async fn transaction_with_timeout<F, Fut, T, E>(&self, timeout: Option<Duration>, f: F) -> Result<T, E>
+    where
+        F: FnOnce(Transaction) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: From<DatabaseError>,
+    {
+        let tx = Transaction::default();
+        let value = match f(tx.clone()).await {
+            Ok(value) => value,
+            Err(e) => {
+                tracing::debug!("Transaction cancelled before commit");
+                return Err(e);
+            }
+        };
+
+        if tx.is_empty() {
+            return Ok(value);
+        }
+
+        let (script, bindings) = tx.build()?;
+        let result = self.with_timeout(timeout, async {
+            let conn = self.get_connection().await?;
+            let mut request = conn.query(script.as_str());
+            for binding in bindings {
+                request = request.bind(binding);
+            }
+
+            let response = request.await.map_err(DatabaseError::from)?;
+            match transaction_error(response) {
+                Some(e) => Err(e),
+                None => Ok(()),
+            }
+        })
+        .await;
+        // A timed out commit may still have been applied
+        self.invalidate_query(&script);
+        result?;
+
+        Ok(value)
+    }
+
+    /// Subscribe to creates, updates and deletes on `table`.
+    pub async fn live_table<T>(&self, table: &str) -> DatabaseResult<LiveStream<T>>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        Ok(LiveStream::typed(self.live(live_table_query(table)).await?))
+    }
+
+    /// Subscribe to changes of the single record `id`.
+    pub async fn live_record<T>(&self, id: &Thing) -> DatabaseResult<LiveStream<T>>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        Ok(LiveStream::typed(self.live(live_record_query(id)).await?))
+    }
+
+    /// Like [`DatabaseManager::live_table`], with records as JSON.
+    pub async fn live_table_json(&self, table: &str) -> DatabaseResult<LiveStream<serde_json::Value>> {
+        Ok(LiveStream::json(self.live(live_table_query(table)).await?))
+    }
+
+    /// Like [`DatabaseManager::live_record`], with records as JSON.
+    pub async fn live_record_json(&self, id: &Thing) -> DatabaseResult<LiveStream<serde_json::Value>> {
+        Ok(LiveStream::json(self.live(live_record_query(id)).await?))
+    }
+
+    /// Register a live query on the primary. The stream keeps its own
+    /// handle on the client, so the pooled connection goes straight back.
+    async fn live(&self, query: String) -> DatabaseResult<QueryStream<surrealdb::Value>> {
+        self.with_timeout(None, async {
+            let conn = self.get_connection().await?;
+            let mut response = conn.query(query).await?;
+            Ok(response.stream::<surrealdb::Value>(0)?)
+        })
+        .await
+    }
+
+    /// Typed access to the `user` table.
+    pub fn users(&self) -> UserRepository<'_> {
+        UserRepository::new(self)
+    }
+
+    pub async fn create_user(&self, user: &User) -> UserResult<User> {
+        self.users().create(user).await
+    }
+
+    pub async fn find_user_by_id(&self, id: &str) -> UserResult<Option<User>> {
+        self.users().find_by_id(id).await
+    }
+
+    pub async fn update_user(&self, user: User) -> UserResult<User> {
+        self.users().update(&user).await
+    }
+
+    pub async fn delete_user(&self, id: &str) -> UserResult<()> {
+        self.users().delete(id).await
+    }
+
+    pub async fn list_users(&self, page: &PageRequest) -> UserResult<Page<User>> {
+        self.users().list(page).await
+    }
+
+    /// Execute a SurrealQL query with string parameters bound as `$name`,
+    /// returning the result of every statement as a JSON array.
+    pub async fn execute_query(
+        &self,
+        query: &str,
+        params: HashMap<String, String>,
+    ) -> DatabaseResult<serde_json::Value> {
+        self.execute_query_with(query, params, QueryOptions::default()).await
+    }
+
+    /// Like [`DatabaseManager::execute_query`] with per-call options. Raw
+    /// queries are only limited on the client side. Queries made up of
+    /// read-only statements go to a replica unless the options ask for the
+    /// primary.
+    pub async fn execute_query_with(
+        &self,
+        query: &str,
+        params: HashMap<String, String>,
+        options: QueryOptions,
+    ) -> DatabaseResult<serde_json::Value> {
+        if !is_read_only(query) {
+            let result = self.run_query(query, params, options).await;
+            self.invalidate_query(query);
+            return result;
+        }
+
+        match (options.cache_ttl, referenced_tables(query)) {
+            (Some(ttl), Some(tables)) => {
+                let params: BTreeMap<_, _> = params.into_iter().collect();
+                let key = format!("query:{}:{}", query, serde_json::to_string(&params).unwrap_or_default());
+                let tables: Vec<&str> = tables.iter().map(String::as_str).collect();
+                self.cached(key, &tables, Some(ttl), self.run_query(query, params.into_iter().collect(), options))
+                    .await
+            }
+            _ => self.run_query(query, params, options).await,
+        }
+    }
+
+    async fn run_query(
+        &self,
+        query: &str,
+        params: HashMap<String, String>,
+        options: QueryOptions,
+    ) -> DatabaseResult<serde_json::Value> {
+        self.with_timeout(options.timeout, async {
+            let conn = if is_read_only(query) {
+                self.get_read_connection(options.read_preference).await?
+            } else {
+                self.get_connection().await?
+            };
+            let mut response = conn.query(query).bind(params).await?;
+
+            let mut results = Vec::with_capacity(response.num_statements());
+            for index in 0..response.num_statements() {
+                let value: surrealdb::Value = response.take(index)?;
+                results.push(value.into_inner().into_json());
+            }
+
+            Ok(serde_json::Value::Array(results))
+        })
+        .await
+    }
+
+    /// Dump the schema and data of the selected database to a SurrealQL file.
+    /// Not subject to the query timeout, since large databases take a while.
+    pub async fn export(&self, path: impl AsRef<Path>) -> DatabaseResult<()> {
+        let conn = self.get_connection().await?;
+        let mut output = String::from("-- OmniPro DB export\nOPTION IMPORT;\n\n");
+
+        let tables = info_section(&conn, "INFO FOR DB", "tables").await?;
+        for (table, definition) in &tables {
+            output.push_str(&format!("{};\n", definition));
+
+            let info_query = format!("INFO FOR TABLE {}", escape_ident(table));
+            for section in ["fields", "indexes", "events"] {
+                for definition in info_section(&conn, &info_query, section).await?.values() {
+                    output.push_str(&format!("{};\n", definition));
+                }
+            }
+            output.push('\n');
+        }
+
+        for table in tables.keys() {
+            let records: surrealdb::Value = conn
+                .query("SELECT * FROM type::table($table)")
+                .bind(("table", table.clone()))
+                .await?
+                .take(0)?;
+            let records = records.into_inner();
+            if records.is_empty_array() {
+                continue;
+            }
+            output.push_str(&format!("INSERT INTO {} {};\n", escape_ident(table), records));
+        }
+
+        tokio::fs::write(path, output).await?;
+        Ok(())
+    }
+
+    /// Replay a SurrealQL file produced by [`DatabaseManager::export`].
+    pub async fn import(&self, path: impl AsRef<Path>) -> DatabaseResult<()> {
+        let script = tokio::fs::read_to_string(path).await?;
+        let result = self.get_connection().await?.query(script).await;
+        self.clear_cache();
+        result?.check()?;
         Ok(())
     }
+
+    /// Stream rows from `reader` into `table` in batches. Rows that are
+    /// malformed, fail the table's field definitions or are rejected by the
+    /// database are listed in the report instead of stopping the import.
+    pub async fn import_table<R>(&self, table: &str, reader: R, options: &ImportOptions) -> DatabaseResult<ImportReport>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        let report = bulk::import(self, table, reader, options).await;
+        self.invalidate_cache(&[table]);
+        report
+    }
+
+    /// Stream the records of `table` to `writer`, returning how many were
+    /// written. Pages are read in id order, from a replica when available.
+    pub async fn export_table<W>(&self, table: &str, writer: W, options: &ExportOptions) -> DatabaseResult<u64>
+    where
+        W: AsyncWrite + Unpin + Send,
+    {
+        bulk::export(self, table, writer, options).await
+    }
+}
+
+pub(crate) async fn info_section(
+    conn: &PooledConnection,
+    query: &str,
+    section: &str,
+) -> DatabaseResult<BTreeMap<String, String>> {
+    let info: Option<HashMap<String, serde_json::Value>> = conn
+        .query(query)
+        .await?
+        .take(0)?;
+
+    let definitions = info
+        .and_then(|mut info| info.remove(section))
+        .and_then(|section| serde_json::from_value(section).ok())
+        .unwrap_or_default();
+
+    Ok(definitions)
+}
+
+/// A `TIMEOUT` clause (with a leading space) for statements that support it,
+/// so the server abandons the work too instead of only the client.
+pub(crate) fn timeout_clause(timeout: Option<Duration>) -> String {
+    match timeout {
+        Some(limit) => format!(" TIMEOUT {}ms", limit.as_millis().max(1)),
+        None => String::new(),
+    }
+}
+
+// Live queries do not see bound parameters, so the table and record id
+// are spliced in escaped
+fn live_table_query(table: &str) -> String {
+    format!("LIVE SELECT * FROM {}", escape_ident(table))
+}
+
+fn live_record_query(id: &Thing) -> String {
+    format!("LIVE SELECT * FROM {} WHERE id = {}", escape_ident(&id.tb), id)
+}
+
+/// Escape an identifier so it can be spliced into SurrealQL.
+pub(crate) fn escape_ident(ident: &str) -> String {
+    format!("`{}`", ident.replace('\\', "\\\\").replace('`', "\\`"))
 }
@@ -3,7 +3,10 @@ use thiserror::Error;
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("Database error: {0}")]
-    Database(#[from] surrealdb::Error),
+    Database(Box<surrealdb::Error>),
+
+    #[error("Connection error: {0}")]
+    Connection(#[from] crate::db::DatabaseError),
 
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
@@ -18,4 +21,19 @@ pub enum Error {
     Internal(String),
 }
 
+// Boxed to keep `Result<T>` small; surrealdb's error type is several hundred bytes
+impl From<surrealdb::Error> for Error {
+    fn from(error: surrealdb::Error) -> Self {
+        Error::Database(Box::new(error))
+    }
+}
+
+impl Error {
+    /// Whether the operation may succeed if retried later, e.g. after the
+    /// connection to the server has been re-established.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Error::Connection(e) if e.is_retryable())
+    }
+}
+
 pub type Result<T> = std::result::Result<T, Error>; 
\ No newline at end of file
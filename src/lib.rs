@@ -1,15 +1,60 @@
 // Path: src/lib.rs
 
+pub mod anomaly_detection;
+pub mod backup;
+pub mod bulk;
+pub mod cache;
+pub mod changefeed;
+pub mod circuit_breaker;
+pub mod config;
 pub mod db;
+pub mod error;
+pub mod live;
+pub mod migration_lock;
+pub mod migration_plan;
 pub mod migrations;
+pub mod pagination;
+pub mod pool;
+pub mod query;
+pub mod reconnect;
+pub mod reload;
+pub mod replica;
+pub mod repository;
 pub mod sanitizer;
+pub mod schema;
 pub mod security;
 pub mod surrealml;
 pub mod telemetry;
+pub mod transaction;
+pub mod user;
 
-pub use db::{DatabaseConfig, DatabaseManager};
-pub use migrations::{Migration, MigrationError, MigrationManager, MigrationResult};
+pub use backup::{
+    BackupArchive, BackupError, BackupKind, BackupManager, BackupManifest, RestoreOptions, RestoreProgress, Watermark,
+};
+pub use bulk::{DataFormat, ExportOptions, ImportOptions, ImportReport, RowError};
+pub use cache::{CacheConfig, CacheStats, QueryCache};
+pub use changefeed::{ChangeFeedConsumer, ChangeSink, ChannelSink, NdjsonSink};
+pub use circuit_breaker::{CircuitBreakerConfig, CircuitState};
+pub use config::{AppConfig, ConfigError, ServerConfig};
+pub use error::Error;
+pub use db::{DatabaseConfig, DatabaseEngine, DatabaseManager, QueryOptions};
+pub use live::{LiveAction, LiveNotification, LiveStream};
+pub use migration_lock::{LockContention, MigrationLockConfig};
+pub use migration_plan::{MigrationPlan, PlanDirection, PlannedMigration};
+pub use migrations::{Migration, MigrationConfig, MigrationError, MigrationManager, MigrationResult};
+pub use pagination::{CursorSigner, Page, PageRequest};
+pub use pool::{ConnectionPool, PoolConfig, PoolStatus, PooledConnection};
+pub use query::{Condition, Create, Delete, Query, Relate, Select, Target, Update};
+pub use reconnect::{ConnectionState, ReconnectConfig};
+pub use reload::{ConfigReloader, ReloadConfig};
+pub use replica::{ReadPreference, ReadRouting, ReplicaConfig};
+pub use repository::{FilterOp, ListOptions, Order, Record, Repository};
 pub use sanitizer::Sanitizer;
 pub use security::SecurityManager;
 pub use surrealml::{Dataset, Model, SurrealMLError, SurrealMLStorage};
 pub use telemetry::TelemetryManager;
+pub use transaction::Transaction;
+pub use user::{User, UserError, UserRepository};
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,173 @@
+// Path: src/live.rs
+
+use crate::db::{DatabaseError, DatabaseResult};
+use futures::stream::{BoxStream, Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use surrealdb::method::QueryStream;
+use surrealdb::{Action, Notification, Value};
+
+/// What happened to the record in a [`LiveNotification`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LiveAction {
+    Create,
+    Update,
+    /// `data` holds the record as it was before it was deleted
+    Delete,
+}
+
+impl LiveAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LiveAction::Create => "create",
+            LiveAction::Update => "update",
+            LiveAction::Delete => "delete",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiveNotification<T> {
+    pub action: LiveAction,
+    pub data: T,
+}
+
+/// Changes pushed by a SurrealDB live query.
+///
+/// Dropping the stream kills the live query on the server. The stream ends
+/// if the connection it was registered on goes away, so long-running
+/// subscribers should subscribe again after it finishes.
+pub struct LiveStream<T> {
+    inner: BoxStream<'static, DatabaseResult<LiveNotification<T>>>,
+}
+
+impl<T: Send + 'static> LiveStream<T> {
+    /// Records deserialized into `T`, which sees ids as record ids.
+    pub(crate) fn typed(stream: QueryStream<Value>) -> Self
+    where
+        T: DeserializeOwned,
+    {
+        Self::from_notifications(stream, |value| {
+            surrealdb::value::from_value(value).map_err(DatabaseError::from)
+        })
+    }
+
+    fn from_notifications<F>(stream: QueryStream<Value>, mut convert: F) -> Self
+    where
+        F: FnMut(Value) -> DatabaseResult<T> + Send + 'static,
+    {
+        let inner = stream
+            .filter_map(move |notification: Notification<Value>| {
+                let action = match notification.action {
+                    Action::Create => Some(LiveAction::Create),
+                    Action::Update => Some(LiveAction::Update),
+                    Action::Delete => Some(LiveAction::Delete),
+                    // Newer servers may send actions this client does not know
+                    _ => None,
+                };
+                let item = action.map(|action| {
+                    convert(notification.data).map(|data| LiveNotification { action, data })
+                });
+                futures::future::ready(item)
+            })
+            .boxed();
+
+        Self { inner }
+    }
+
+    /// Convert each record, e.g. from its stored form to a domain type.
+    pub fn map_data<U, F>(self, mut f: F) -> LiveStream<U>
+    where
+        U: Send + 'static,
+        F: FnMut(T) -> DatabaseResult<U> + Send + 'static,
+    {
+        let inner = self
+            .inner
+            .map(move |item| {
+                item.and_then(|notification| {
+                    Ok(LiveNotification {
+                        action: notification.action,
+                        data: f(notification.data)?,
+                    })
+                })
+            })
+            .boxed();
+
+        LiveStream { inner }
+    }
+}
+
+impl LiveStream<serde_json::Value> {
+    /// Records as JSON, with record ids rendered as `table:key` strings.
+    pub(crate) fn json(stream: QueryStream<Value>) -> Self {
+        Self::from_notifications(stream, |value| Ok(value.into_inner().into_json()))
+    }
+}
+
+impl<T> Stream for LiveStream<T> {
+    type Item = DatabaseResult<LiveNotification<T>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::{Record, Repository};
+    use crate::tests::test_db;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Item {
+        n: i64,
+    }
+
+    async fn next<T>(stream: &mut LiveStream<T>) -> LiveNotification<T> {
+        tokio::time::timeout(Duration::from_secs(5), stream.next())
+            .await
+            .expect("notification within 5s")
+            .expect("stream still open")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_table_notifications() {
+        let db = Arc::new(test_db("test", "live").await);
+        let items = Repository::<Item>::new(db.clone(), "item").unwrap();
+        let mut stream = db.live_table::<Record<Item>>("item").await.unwrap();
+
+        let id = items.thing("a");
+        items.create_with_id(&id, Item { n: 1 }).await.unwrap();
+        items.replace(&id, Item { n: 2 }).await.unwrap();
+        items.delete(&id).await.unwrap();
+
+        let created = next(&mut stream).await;
+        assert_eq!(created.action, LiveAction::Create);
+        assert_eq!(created.data, Record { id: id.clone(), data: Item { n: 1 } });
+        assert_eq!(next(&mut stream).await.action, LiveAction::Update);
+
+        let deleted = next(&mut stream).await;
+        assert_eq!(deleted.action, LiveAction::Delete);
+        assert_eq!(deleted.data.data, Item { n: 2 });
+    }
+
+    #[tokio::test]
+    async fn test_record_notifications() {
+        let db = Arc::new(test_db("test", "live").await);
+        let items = Repository::<Item>::new(db.clone(), "item").unwrap();
+        let mut stream = db.live_record_json(&items.thing("watched")).await.unwrap();
+
+        items.create_with_id(&items.thing("other"), Item { n: 1 }).await.unwrap();
+        items.create_with_id(&items.thing("watched"), Item { n: 2 }).await.unwrap();
+
+        let notification = next(&mut stream).await;
+        assert_eq!(notification.action, LiveAction::Create);
+        assert_eq!(notification.data, serde_json::json!({ "id": "item:watched", "n": 2 }));
+    }
+}
@@ -1,29 +1,45 @@
 // Path: src/main.rs
 
-use std::sync::Arc;
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 use tokio::net::TcpListener;
+use tokio::sync::RwLock;
 use tonic::transport::Server;
 use tracing::{info, warn};
 
-mod anomaly_detection;
-mod db;
-mod migrations;
 mod proto;
-mod sanitizer;
-mod schema;
-mod security;
-mod surrealml;
-mod telemetry;
-mod error;
-
-use crate::db::{DatabaseConfig, DatabaseManager};
-use crate::migrations::MigrationManager;
-use crate::security::SecurityManager;
-use crate::telemetry::TelemetryManager;
+
+use omnipro_db::{db, schema};
+use omnipro_db::anomaly_detection::{AnomalyDetector, QueryMetrics};
+use omnipro_db::backup::{BackupManager, RestoreOptions};
+use omnipro_db::circuit_breaker::CircuitState;
+use omnipro_db::config::AppConfig;
+use omnipro_db::live::LiveAction;
+use omnipro_db::db::{DatabaseConfig, DatabaseError, DatabaseManager, QueryOptions, UserError, UserRepository};
+use omnipro_db::migrations::MigrationManager;
+use omnipro_db::pagination::PageRequest;
+use omnipro_db::reload::ConfigReloader;
+use omnipro_db::replica::ReadPreference;
+use omnipro_db::sanitizer::Sanitizer;
+use omnipro_db::schema::{ChangeFeedDefinition, FieldDefinition, IndexDefinition, TableDefinition};
+use omnipro_db::security::SecurityManager;
+use omnipro_db::telemetry::TelemetryManager;
 
 use proto::database_service_server::{DatabaseService, DatabaseServiceServer};
 use proto::{CreateUserRequest, CreateUserResponse, DeleteUserRequest, DeleteUserResponse, 
-           UpdateUserRequest, UpdateUserResponse, GetUserRequest, GetUserResponse};
+           UpdateUserRequest, UpdateUserResponse, GetUserRequest, GetUserResponse,
+           ListUsersRequest, ListUsersResponse,
+           UserAction, UserEvent, WatchUserRequest};
+use proto::omnipro::db::db_service_server::{DbService, DbServiceServer};
+use proto::omnipro::db::{BackupRequest, BackupResponse, ConnectRequest, ConnectResponse,
+           CreateTableRequest, CreateTableResponse, HealthCheckRequest, HealthCheckResponse,
+           QueryRequest, QueryResponse, RestoreRequest, RestoreResponse,
+           ChangeAction, ChangeEvent, WatchTableRequest};
+
+/// Server stream handed to tonic for the watch RPCs
+type EventStream<T> = Pin<Box<dyn Stream<Item = Result<T, tonic::Status>> + Send>>;
 
 pub struct DatabaseServiceImpl {
     db: Arc<DatabaseManager>,
@@ -31,22 +47,173 @@ pub struct DatabaseServiceImpl {
     telemetry: Arc<TelemetryManager>,
 }
 
+impl DatabaseServiceImpl {
+    fn record_call(&self, method: &str, success: bool) {
+        self.telemetry.record_metric(
+            "user_service_requests".to_string(),
+            1.0,
+            vec![
+                ("method".to_string(), method.to_string()),
+                ("success".to_string(), success.to_string()),
+            ],
+        );
+    }
+
+    /// The user repository, limited by the client's deadline if it is
+    /// shorter than the configured query timeout.
+    fn users<T>(&self, request: &tonic::Request<T>) -> UserRepository<'_> {
+        let users = self.db.users();
+        match request_timeout(request, self.db.query_timeout()) {
+            Some(timeout) => users.with_timeout(timeout),
+            None => users,
+        }
+    }
+
+    /// Check the profile fields that are set; empty fields are skipped.
+    fn validate_profile(&self, email: &str, name: &str, role: &str) -> Result<(), &'static str> {
+        if !email.is_empty() && !self.security.is_valid_email(email) {
+            return Err("Invalid email format");
+        }
+        if !name.is_empty() && !self.security.is_valid_name(name) {
+            return Err("Invalid name");
+        }
+        if !role.is_empty() && !self.security.is_valid_role(role) {
+            return Err("Invalid role");
+        }
+        Ok(())
+    }
+}
+
+/// Map repository errors onto gRPC status codes.
+fn user_status(method: &str, error: UserError) -> tonic::Status {
+    match error {
+        UserError::DuplicateEmail(_) => tonic::Status::already_exists(error.to_string()),
+        UserError::NotFound(_) => tonic::Status::not_found("User not found"),
+        UserError::Database(e) => database_status(method, e),
+    }
+}
+
+fn database_status(method: &str, error: DatabaseError) -> tonic::Status {
+    match error {
+        DatabaseError::Timeout(_) => tonic::Status::deadline_exceeded(error.to_string()),
+        DatabaseError::InvalidInput(message) => tonic::Status::invalid_argument(message),
+        e if e.is_retryable() => tonic::Status::unavailable(e.to_string()),
+        e => {
+            warn!("Failed to {}: {}", method.replace('_', " "), e);
+            tonic::Status::internal(format!("Failed to {}", method.replace('_', " ")))
+        }
+    }
+}
+
+/// The time left for a request: the client's `grpc-timeout` if it sent one
+/// and it is shorter than `default`, otherwise `None` so the default applies.
+fn request_timeout<T>(request: &tonic::Request<T>, default: Option<Duration>) -> Option<Duration> {
+    let deadline = request
+        .metadata()
+        .get("grpc-timeout")
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_grpc_timeout)?;
+
+    match default {
+        Some(default) if default <= deadline => None,
+        _ => Some(deadline),
+    }
+}
+
+/// Parse a `grpc-timeout` header value: up to 8 digits and a unit.
+fn parse_grpc_timeout(value: &str) -> Option<Duration> {
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+    let (amount, unit) = value.split_at(value.len() - 1);
+    let amount: u64 = amount.parse().ok()?;
+
+    match unit {
+        "H" => Some(Duration::from_secs(amount * 3600)),
+        "M" => Some(Duration::from_secs(amount * 60)),
+        "S" => Some(Duration::from_secs(amount)),
+        "m" => Some(Duration::from_millis(amount)),
+        "u" => Some(Duration::from_micros(amount)),
+        "n" => Some(Duration::from_nanos(amount)),
+        _ => None,
+    }
+}
+
+/// `path` from a request, resolved inside `dir`. Absolute paths and `..`
+/// are refused so clients cannot reach other files on the server.
+#[allow(clippy::result_large_err)]
+fn confined_path(dir: &std::path::Path, path: &str) -> Result<std::path::PathBuf, tonic::Status> {
+    use std::path::Component;
+
+    let relative = std::path::Path::new(path);
+    if path.trim().is_empty()
+        || !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(tonic::Status::invalid_argument(format!(
+            "`{}` must be a relative path inside the backup directory",
+            path
+        )));
+    }
+    Ok(dir.join(relative))
+}
+
+fn non_empty(value: String) -> Option<String> {
+    (!value.is_empty()).then_some(value)
+}
+
+impl From<LiveAction> for UserAction {
+    fn from(action: LiveAction) -> Self {
+        match action {
+            LiveAction::Create => UserAction::Create,
+            LiveAction::Update => UserAction::Update,
+            LiveAction::Delete => UserAction::Delete,
+        }
+    }
+}
+
+impl From<LiveAction> for ChangeAction {
+    fn from(action: LiveAction) -> Self {
+        match action {
+            LiveAction::Create => ChangeAction::Create,
+            LiveAction::Update => ChangeAction::Update,
+            LiveAction::Delete => ChangeAction::Delete,
+        }
+    }
+}
+
+impl From<db::User> for proto::User {
+    fn from(user: db::User) -> Self {
+        Self {
+            id: user.id,
+            email: user.email,
+            name: user.name,
+            // Never hand the hash out over the API
+            password_hash: String::new(),
+            role: user.role,
+            created_at: user.created_at.to_rfc3339(),
+            updated_at: user.updated_at.to_rfc3339(),
+        }
+    }
+}
+
 #[tonic::async_trait]
 impl DatabaseService for DatabaseServiceImpl {
+    #[tracing::instrument(skip_all)]
     async fn create_user(
         &self,
         request: tonic::Request<CreateUserRequest>,
     ) -> Result<tonic::Response<CreateUserResponse>, tonic::Status> {
-        // Create a span for tracing
-        let span = self.telemetry.tracer().start("create_user");
-        let _guard = span.enter();
-
+        let users = self.users(&request);
         let req = request.into_inner();
-        
+
         // Validate input using SecurityManager
-        if !self.security.is_valid_email(&req.email) {
-            return Err(tonic::Status::invalid_argument("Invalid email format"));
+        if req.email.is_empty() || req.name.is_empty() || req.role.is_empty() {
+            return Err(tonic::Status::invalid_argument("email, name and role are required"));
         }
+        self.validate_profile(&req.email, &req.name, &req.role)
+            .map_err(tonic::Status::invalid_argument)?;
         if !self.security.is_valid_password(&req.password) {
             return Err(tonic::Status::invalid_argument("Invalid password format"));
         }
@@ -63,89 +230,500 @@ impl DatabaseService for DatabaseServiceImpl {
             req.role,
         );
 
-        match self.db.create_user(&user).await {
-            Ok(_) => Ok(tonic::Response::new(CreateUserResponse {
-                success: true,
-                user_id: user.id.to_string(),
-            })),
-            Err(e) => {
-                warn!("Failed to create user: {}", e);
-                Err(tonic::Status::internal("Failed to create user"))
-            }
-        }
+        let result = users.create(&user).await;
+        self.record_call("create_user", result.is_ok());
+        let user = result.map_err(|e| user_status("create_user", e))?;
+
+        Ok(tonic::Response::new(CreateUserResponse {
+            user: Some(user.into()),
+        }))
     }
 
+    #[tracing::instrument(skip_all)]
     async fn update_user(
         &self,
         request: tonic::Request<UpdateUserRequest>,
     ) -> Result<tonic::Response<UpdateUserResponse>, tonic::Status> {
-        let span = self.telemetry.tracer().start("update_user");
-        let _guard = span.enter();
-
+        // The lookup feeds the write, so it must not see a stale replica
+        let users = self.users(&request).with_read_preference(ReadPreference::Primary);
         let req = request.into_inner();
-        
+        self.validate_profile(&req.email, &req.name, &req.role)
+            .map_err(tonic::Status::invalid_argument)?;
+
         // Find existing user
-        let existing_user = self.db.find_user_by_id(&req.user_id).await
-            .map_err(|e| tonic::Status::internal(e.to_string()))?
+        let existing_user = users.find_by_id(&req.id).await
+            .map_err(|e| user_status("update_user", e))?
             .ok_or_else(|| tonic::Status::not_found("User not found"))?;
 
-        // Update user fields
+        // Empty fields keep the stored value
         let updated_user = existing_user.update(
-            req.name,
-            req.email,
-            req.role,
+            non_empty(req.name),
+            non_empty(req.email),
+            non_empty(req.role),
         );
 
-        // Hash password if necessary (not shown here)
+        let result = users.update(&updated_user).await;
+        self.record_call("update_user", result.is_ok());
+        let user = result.map_err(|e| user_status("update_user", e))?;
 
-        match self.db.update_user(updated_user).await {
-            Ok(_) => Ok(tonic::Response::new(UpdateUserResponse {
-                success: true,
-            })),
-            Err(e) => {
-                warn!("Failed to update user: {}", e);
-                Err(tonic::Status::internal("Failed to update user"))
-            }
-        }
+        Ok(tonic::Response::new(UpdateUserResponse {
+            user: Some(user.into()),
+        }))
     }
 
+    #[tracing::instrument(skip_all)]
     async fn delete_user(
         &self,
         request: tonic::Request<DeleteUserRequest>,
     ) -> Result<tonic::Response<DeleteUserResponse>, tonic::Status> {
-        let span = self.telemetry.tracer().start("delete_user");
-        let _guard = span.enter();
+        let users = self.users(&request);
+        let req = request.into_inner();
+
+        let result = users.delete(&req.id).await;
+        self.record_call("delete_user", result.is_ok());
+        result.map_err(|e| user_status("delete_user", e))?;
+
+        Ok(tonic::Response::new(DeleteUserResponse {}))
+    }
 
+    #[tracing::instrument(skip_all)]
+    async fn get_user(
+        &self,
+        request: tonic::Request<GetUserRequest>,
+    ) -> Result<tonic::Response<GetUserResponse>, tonic::Status> {
+        let users = self.users(&request);
         let req = request.into_inner();
-        
-        match self.db.delete_user(&req.user_id).await {
-            Ok(_) => Ok(tonic::Response::new(DeleteUserResponse {
-                success: true,
+
+        let result = users.find_by_id(&req.id).await;
+        self.record_call("get_user", result.is_ok());
+        match result.map_err(|e| user_status("get_user", e))? {
+            Some(user) => Ok(tonic::Response::new(GetUserResponse {
+                user: Some(user.into()),
             })),
+            None => Err(tonic::Status::not_found("User not found")),
+        }
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn list_users(
+        &self,
+        request: tonic::Request<ListUsersRequest>,
+    ) -> Result<tonic::Response<ListUsersResponse>, tonic::Status> {
+        let users = self.users(&request);
+        let req = request.into_inner();
+
+        let mut page = PageRequest::default();
+        if req.page_size > 0 {
+            page = PageRequest::new(req.page_size as usize);
+        }
+        let result = users.list(&page.after(&req.page_token)).await;
+        self.record_call("list_users", result.is_ok());
+        let page = result.map_err(|e| user_status("list_users", e))?;
+
+        Ok(tonic::Response::new(ListUsersResponse {
+            users: page.items.into_iter().map(Into::into).collect(),
+            next_page_token: page.next_page_token.unwrap_or_default(),
+        }))
+    }
+
+    type WatchUserStream = EventStream<UserEvent>;
+
+    #[tracing::instrument(skip_all)]
+    async fn watch_user(
+        &self,
+        request: tonic::Request<WatchUserRequest>,
+    ) -> Result<tonic::Response<Self::WatchUserStream>, tonic::Status> {
+        let req = request.into_inner();
+        if req.id.is_empty() {
+            return Err(tonic::Status::invalid_argument("id is required"));
+        }
+
+        let result = self.db.users().watch(&req.id).await;
+        self.record_call("watch_user", result.is_ok());
+        let stream = result.map_err(|e| user_status("watch_user", e))?;
+
+        // The item type is fixed by tonic
+        #[allow(clippy::result_large_err)]
+        let events = stream.map(|item| match item {
+            Ok(notification) => Ok(UserEvent {
+                action: UserAction::from(notification.action) as i32,
+                user: Some(notification.data.into()),
+            }),
+            Err(e) => Err(database_status("watch_user", e)),
+        });
+        Ok(tonic::Response::new(Box::pin(events)))
+    }
+}
+
+pub struct DbServiceImpl {
+    db: RwLock<Arc<DatabaseManager>>,
+    config: RwLock<DatabaseConfig>,
+    /// Endpoints ConnectDb may connect to, fixed at startup
+    connect_urls: Vec<String>,
+    /// Directory backup and restore paths are confined to
+    backup_dir: std::path::PathBuf,
+    sanitizer: Sanitizer,
+    anomaly_detector: Arc<Mutex<AnomalyDetector>>,
+    telemetry: Arc<TelemetryManager>,
+}
+
+impl DbServiceImpl {
+    async fn current_db(&self) -> Arc<DatabaseManager> {
+        self.db.read().await.clone()
+    }
+
+    /// Feed a query's cost to the anomaly detector and log anything unusual.
+    fn observe_query(&self, execution_time: Duration, result: &serde_json::Value) {
+        let rows_affected = result
+            .as_array()
+            .map(|statements| {
+                statements
+                    .iter()
+                    .map(|r| r.as_array().map_or(1, Vec::len))
+                    .sum()
+            })
+            .unwrap_or(0);
+        let metrics = QueryMetrics {
+            execution_time,
+            rows_affected,
+            timestamp: SystemTime::now(),
+        };
+
+        let mut detector = self.anomaly_detector.lock().unwrap_or_else(|e| e.into_inner());
+        for anomaly in detector.detect_anomalies(&metrics) {
+            warn!("{} ({:?}, {} rows)", anomaly, execution_time, rows_affected);
+        }
+        detector.record_metrics(metrics);
+    }
+
+    fn record_call(&self, method: &str, success: bool) {
+        self.telemetry.record_metric(
+            "db_service_requests".to_string(),
+            1.0,
+            vec![
+                ("method".to_string(), method.to_string()),
+                ("success".to_string(), success.to_string()),
+            ],
+        );
+    }
+}
+
+#[tonic::async_trait]
+impl DbService for DbServiceImpl {
+    async fn connect_db(
+        &self,
+        request: tonic::Request<ConnectRequest>,
+    ) -> Result<tonic::Response<ConnectResponse>, tonic::Status> {
+        let req = request.into_inner();
+
+        // Empty fields keep the currently configured value. Only configured
+        // endpoints are accepted, so clients cannot make the server connect
+        // to arbitrary hosts.
+        let mut config = self.config.read().await.clone();
+        if !req.url.is_empty() {
+            if !self.connect_urls.contains(&req.url) {
+                return Err(tonic::Status::permission_denied(format!(
+                    "{} is not a configured database endpoint",
+                    req.url
+                )));
+            }
+            config.url = req.url;
+        }
+        if !req.namespace.is_empty() {
+            config.namespace = self.sanitizer.sanitize_name(&req.namespace)
+                .map_err(tonic::Status::invalid_argument)?;
+        }
+        if !req.database.is_empty() {
+            config.database = self.sanitizer.sanitize_name(&req.database)
+                .map_err(tonic::Status::invalid_argument)?;
+        }
+
+        match DatabaseManager::with_telemetry(config.clone(), self.telemetry.clone()).await {
+            Ok(db) => {
+                info!("Connected to {} ({}/{})", config.url, config.namespace, config.database);
+                *self.db.write().await = Arc::new(db);
+                *self.config.write().await = config;
+                self.record_call("connect_db", true);
+                Ok(tonic::Response::new(ConnectResponse {
+                    success: true,
+                    error: String::new(),
+                }))
+            }
             Err(e) => {
-                warn!("Failed to delete user: {}", e);
-                Err(tonic::Status::internal("Failed to delete user"))
+                warn!("Failed to connect to {}: {}", config.url, e);
+                self.record_call("connect_db", false);
+                Ok(tonic::Response::new(ConnectResponse {
+                    success: false,
+                    error: e.to_string(),
+                }))
             }
         }
     }
 
-    async fn get_user(
+    async fn execute_query(
         &self,
-        request: tonic::Request<GetUserRequest>,
-    ) -> Result<tonic::Response<GetUserResponse>, tonic::Status> {
-        let span = self.telemetry.tracer().start("get_user");
-        let _guard = span.enter();
+        request: tonic::Request<QueryRequest>,
+    ) -> Result<tonic::Response<QueryResponse>, tonic::Status> {
+        let db = self.current_db().await;
+        let timeout = request_timeout(&request, db.query_timeout());
+        let req = request.into_inner();
+        let options = QueryOptions {
+            timeout,
+            read_preference: if req.read_from_primary {
+                ReadPreference::Primary
+            } else {
+                ReadPreference::Replica
+            },
+            ..Default::default()
+        };
+
+        let query = self.sanitizer.sanitize_query(&req.query)
+            .map_err(tonic::Status::invalid_argument)?;
+        for name in req.parameters.keys() {
+            self.sanitizer.sanitize_name(name)
+                .map_err(tonic::Status::invalid_argument)?;
+        }
+
+        let started = Instant::now();
+        match db.execute_query_with(&query, req.parameters, options).await {
+            Ok(result) => {
+                self.observe_query(started.elapsed(), &result);
+                self.record_call("execute_query", true);
+                Ok(tonic::Response::new(QueryResponse {
+                    success: true,
+                    result: result.to_string(),
+                    error: String::new(),
+                }))
+            }
+            Err(e @ DatabaseError::Timeout(_)) => {
+                warn!("Query cancelled: {}", e);
+                self.record_call("execute_query", false);
+                Err(database_status("execute_query", e))
+            }
+            Err(e) => {
+                warn!("Failed to execute query: {}", e);
+                self.record_call("execute_query", false);
+                Ok(tonic::Response::new(QueryResponse {
+                    success: false,
+                    result: String::new(),
+                    error: e.to_string(),
+                }))
+            }
+        }
+    }
 
+    async fn create_table(
+        &self,
+        request: tonic::Request<CreateTableRequest>,
+    ) -> Result<tonic::Response<CreateTableResponse>, tonic::Status> {
         let req = request.into_inner();
-        
-        match self.db.find_user_by_id(&req.user_id).await {
-            Ok(Some(user)) => Ok(tonic::Response::new(GetUserResponse {
-                user: Some(user.into()),
+
+        let table = TableDefinition {
+            name: req.name,
+            fields: req.fields
+                .into_iter()
+                .map(|f| FieldDefinition {
+                    name: f.name,
+                    field_type: f.field_type,
+                    required: f.required,
+                })
+                .collect(),
+            indexes: req.indexes
+                .into_iter()
+                .map(|i| IndexDefinition {
+                    name: i.name,
+                    fields: i.fields,
+                    unique: i.unique,
+                })
+                .collect(),
+            changefeed: (req.changefeed_retention_secs > 0).then_some(ChangeFeedDefinition {
+                retention_secs: req.changefeed_retention_secs,
+                include_original: req.changefeed_include_original,
+            }),
+        };
+
+        // Reject malformed definitions before touching the database
+        table.to_surql(&self.sanitizer).map_err(tonic::Status::invalid_argument)?;
+
+        let result = schema::create_table(&*self.current_db().await, &table)
+            .await
+            .map_err(|e| e.to_string());
+
+        match result {
+            Ok(()) => {
+                self.record_call("create_table", true);
+                Ok(tonic::Response::new(CreateTableResponse {
+                    success: true,
+                    error: String::new(),
+                }))
+            }
+            Err(e) => {
+                warn!("Failed to create table {}: {}", table.name, e);
+                self.record_call("create_table", false);
+                Ok(tonic::Response::new(CreateTableResponse {
+                    success: false,
+                    error: e,
+                }))
+            }
+        }
+    }
+
+    async fn health_check(
+        &self,
+        _request: tonic::Request<HealthCheckRequest>,
+    ) -> Result<tonic::Response<HealthCheckResponse>, tonic::Status> {
+        let db = self.current_db().await;
+        let result = db.health_check().await;
+        let circuit_state = db.circuit_state();
+
+        // Calls fail fast while the circuit is open, even if the ping passes
+        match result {
+            Ok(()) if circuit_state == CircuitState::Open => Ok(tonic::Response::new(HealthCheckResponse {
+                healthy: false,
+                status: DatabaseError::CircuitOpen.to_string(),
+                circuit_state: circuit_state.as_str().to_string(),
+            })),
+            Ok(()) => Ok(tonic::Response::new(HealthCheckResponse {
+                healthy: true,
+                status: "SERVING".to_string(),
+                circuit_state: circuit_state.as_str().to_string(),
             })),
-            Ok(None) => Err(tonic::Status::not_found("User not found")),
             Err(e) => {
-                warn!("Failed to get user: {}", e);
-                Err(tonic::Status::internal("Failed to get user"))
+                warn!("Health check failed: {}", e);
+                Ok(tonic::Response::new(HealthCheckResponse {
+                    healthy: false,
+                    status: e.to_string(),
+                    circuit_state: circuit_state.as_str().to_string(),
+                }))
+            }
+        }
+    }
+
+    async fn backup(
+        &self,
+        request: tonic::Request<BackupRequest>,
+    ) -> Result<tonic::Response<BackupResponse>, tonic::Status> {
+        let req = request.into_inner();
+        let path = confined_path(&self.backup_dir, &req.path)?;
+        let base = match req.base.as_str() {
+            "" => None,
+            base => Some(confined_path(&self.backup_dir, base)?),
+        };
+
+        let backups = BackupManager::new(self.current_db().await);
+        let result = match base {
+            None => backups.backup(&path).await,
+            Some(base) => backups.backup_incremental(&path, &base).await,
+        };
+
+        match result {
+            Ok(archive) => {
+                self.record_call("backup", true);
+                // Reported relative to the backup directory, like the request
+                let written = archive.path.strip_prefix(&self.backup_dir).unwrap_or(&archive.path);
+                Ok(tonic::Response::new(BackupResponse {
+                    success: true,
+                    error: String::new(),
+                    archive: written.display().to_string(),
+                    records: archive.manifest.total_records(),
+                }))
+            }
+            Err(e) => {
+                warn!("Failed to back up to {}: {}", req.path, e);
+                self.record_call("backup", false);
+                Ok(tonic::Response::new(BackupResponse {
+                    success: false,
+                    error: e.to_string(),
+                    archive: String::new(),
+                    records: 0,
+                }))
+            }
+        }
+    }
+
+    type WatchTableStream = EventStream<ChangeEvent>;
+
+    async fn watch_table(
+        &self,
+        request: tonic::Request<WatchTableRequest>,
+    ) -> Result<tonic::Response<Self::WatchTableStream>, tonic::Status> {
+        let req = request.into_inner();
+        let table = self.sanitizer.sanitize_name(&req.table)
+            .map_err(tonic::Status::invalid_argument)?;
+
+        let result = self.current_db().await.live_table_json(&table).await;
+        self.record_call("watch_table", result.is_ok());
+        let stream = result.map_err(|e| database_status("watch_table", e))?;
+
+        #[allow(clippy::result_large_err)]
+        let events = stream.map(move |item| match item {
+            Ok(notification) => Ok(ChangeEvent {
+                action: ChangeAction::from(notification.action) as i32,
+                table: table.clone(),
+                id: notification.data["id"].as_str().unwrap_or_default().to_string(),
+                data: notification.data.to_string(),
+            }),
+            Err(e) => Err(database_status("watch_table", e)),
+        });
+        Ok(tonic::Response::new(Box::pin(events)))
+    }
+
+    async fn restore(
+        &self,
+        request: tonic::Request<RestoreRequest>,
+    ) -> Result<tonic::Response<RestoreResponse>, tonic::Status> {
+        let req = request.into_inner();
+        let mut archives = vec![confined_path(&self.backup_dir, &req.path)?];
+        for incremental in &req.incrementals {
+            archives.push(confined_path(&self.backup_dir, incremental)?);
+        }
+
+        let mut options = RestoreOptions::default();
+        if !req.namespace.is_empty() {
+            options.namespace = Some(self.sanitizer.sanitize_name(&req.namespace)
+                .map_err(tonic::Status::invalid_argument)?);
+        }
+        if !req.database.is_empty() {
+            options.database = Some(self.sanitizer.sanitize_name(&req.database)
+                .map_err(tonic::Status::invalid_argument)?);
+        }
+
+        let until = match req.until.as_str() {
+            "" => None,
+            until => Some(
+                chrono::DateTime::parse_from_rfc3339(until)
+                    .map_err(|e| tonic::Status::invalid_argument(format!("Invalid until: {}", e)))?
+                    .with_timezone(&chrono::Utc),
+            ),
+        };
+
+        let backups = BackupManager::new(self.current_db().await);
+        let result = backups
+            .restore_chain(&archives, until, &options, |progress| {
+                info!(
+                    "Restoring {}: {}/{} records",
+                    progress.table, progress.records_restored, progress.records_total
+                );
+            })
+            .await;
+
+        match result {
+            Ok(applied) => {
+                self.record_call("restore", true);
+                Ok(tonic::Response::new(RestoreResponse {
+                    success: true,
+                    error: String::new(),
+                    records: applied.iter().map(|manifest| manifest.total_records()).sum(),
+                }))
+            }
+            Err(e) => {
+                warn!("Failed to restore from {}: {}", req.path, e);
+                self.record_call("restore", false);
+                Ok(tonic::Response::new(RestoreResponse {
+                    success: false,
+                    error: e.to_string(),
+                    records: 0,
+                }))
             }
         }
     }
@@ -153,87 +731,132 @@ impl DatabaseService for DatabaseServiceImpl {
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Load configuration: defaults < config file < environment
+    let config_path = config_path_from_args();
+    let config = AppConfig::load(config_path.as_deref())?;
+
     // Initialize telemetry
-    let telemetry = Arc::new(TelemetryManager::init().await?);
+    let telemetry = Arc::new(
+        TelemetryManager::with_config(&config.telemetry)
+            .await
+            .map_err(|e| e as Box<dyn std::error::Error>)?,
+    );
     info!("Telemetry initialized");
+    if let Some(path) = &config_path {
+        info!("Configuration loaded from {}", path.display());
+    }
 
     // Initialize security manager
-    let security = Arc::new(SecurityManager::new());
+    let security = Arc::new(SecurityManager::with_config(&config.security));
     info!("Security manager initialized");
 
     // Initialize database
-    let config = DatabaseConfig {
-        url: std::env::var("DB_URL").unwrap_or_else(|_| "ws://localhost:8000".to_string()),
-        namespace: std::env::var("DB_NAMESPACE").unwrap_or_else(|_| "test".to_string()),
-        database: std::env::var("DB_NAME").unwrap_or_else(|_| "test".to_string()),
-        username: std::env::var("DB_USER").unwrap_or_else(|_| "root".to_string()),
-        password: std::env::var("DB_PASS").unwrap_or_else(|_| "root".to_string()),
-    };
-
-    let db = Arc::new(DatabaseManager::new(config).await?);
+    let db = Arc::new(DatabaseManager::with_telemetry(config.database.clone(), telemetry.clone()).await?);
     info!("Database connection established");
 
-    // Initialize schema
-    schema::init_schema(&db).await?;
-    info!("Schema initialized");
-
-    // Initialize migration manager and run migrations
-    let migration_manager = MigrationManager::new(db.get_connection().await?, telemetry.clone()).await?;
-
-    // Add migrations
-    migration_manager.add_migration(migrations::Migration {
-        version: 1,
-        name: "Initial schema".to_string(),
-        description: "Create initial database schema".to_string(),
-        up: r#"
-            DEFINE TABLE user SCHEMAFULL;
-            DEFINE FIELD id ON user TYPE string;
-            DEFINE FIELD email ON user TYPE string;
-            DEFINE FIELD name ON user TYPE string;
-            DEFINE FIELD password_hash ON user TYPE string;
-            DEFINE FIELD role ON user TYPE string;
-            DEFINE FIELD created_at ON user TYPE datetime;
-            DEFINE FIELD updated_at ON user TYPE datetime;
-            DEFINE INDEX user_email ON user FIELDS email UNIQUE;
-        "#.to_string(),
-        down: "REMOVE TABLE user;".to_string(),
-        applied_at: None,
+    // --plan-migrations[=json] prints what would run and exits, leaving the
+    // database untouched
+    let plan_format = std::env::args().skip(1).find_map(|arg| match arg.as_str() {
+        "--plan-migrations" => Some("text"),
+        "--plan-migrations=json" => Some("json"),
+        _ => None,
     });
 
-    migration_manager.add_migration(migrations::Migration {
-        version: 2,
-        name: "Add user roles".to_string(),
-        description: "Add role-based access control".to_string(),
-        up: r#"
-            DEFINE FIELD permissions ON user TYPE array;
-            DEFINE FIELD last_login ON user TYPE datetime;
-        "#.to_string(),
-        down: r#"
-            REMOVE FIELD permissions ON user;
-            REMOVE FIELD last_login ON user;
-        "#.to_string(),
-        applied_at: None,
-    });
+    // Initialize schema
+    if plan_format.is_none() {
+        schema::init_schema(&db).await?;
+        info!("Schema initialized");
+    }
 
+    // Initialize migration manager and run migrations
+    let mut migration_manager = MigrationManager::new(db.clone(), telemetry.clone())
+        .await?
+        .with_lock(config.migrations.lock.clone());
+
+    // Migrations come from migrations.dir, or are compiled in from migrations/
+    match &config.migrations.dir {
+        Some(dir) => migration_manager.load_dir(dir).await?,
+        None => migration_manager.load_embedded()?,
+    }
+    if let Some(format) = plan_format {
+        let plan = migration_manager.plan().await?;
+        match format {
+            "json" => println!("{}", plan.to_json()),
+            _ => print!("{}", plan),
+        }
+        return Ok(());
+    }
+    if std::env::args().skip(1).any(|arg| arg == "--repair-migrations") {
+        // Accept edits to applied migrations, then exit without serving
+        let repaired = migration_manager.repair().await?;
+        info!("Repaired checksums of migrations {:?}", repaired);
+        return Ok(());
+    }
     migration_manager.run_pending_migrations().await?;
     info!("Migrations completed");
 
     // Create service implementation
     let service = DatabaseServiceImpl {
         db: db.clone(),
-        security,
+        security: security.clone(),
         telemetry: telemetry.clone(),
     };
 
+    let sanitizer = Sanitizer::with_blocked_patterns(&config.security.blocked_patterns);
+    let anomaly_detector = Arc::new(Mutex::new(AnomalyDetector::from_config(&config.anomaly_detection)));
+
+    // Backup and Restore RPCs only work inside this directory
+    std::fs::create_dir_all(&config.server.backup_dir)?;
+    let db_service = DbServiceImpl {
+        db: RwLock::new(db.clone()),
+        config: RwLock::new(config.database.clone()),
+        connect_urls: std::iter::once(config.database.url.clone())
+            .chain(config.server.connect_urls.iter().cloned())
+            .collect(),
+        backup_dir: config.server.backup_dir.clone(),
+        sanitizer: sanitizer.clone(),
+        anomaly_detector: anomaly_detector.clone(),
+        telemetry: telemetry.clone(),
+    };
+
+    let addr = config.server.socket_addr()?;
+
+    // Watch the config file for changes to the reloadable settings
+    if let Some(path) = config_path {
+        let reloader = ConfigReloader::new(
+            path,
+            config,
+            telemetry.clone(),
+            security.clone(),
+            sanitizer,
+            anomaly_detector,
+        );
+        Arc::new(reloader).spawn();
+    }
+
     // Start gRPC server
-    let addr = "[::1]:50051".parse()?;
     let listener = TcpListener::bind(&addr).await?;
     info!("gRPC server listening on {}", addr);
 
     Server::builder()
         .add_service(DatabaseServiceServer::new(service))
+        .add_service(DbServiceServer::new(db_service))
         .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener))
         .await?;
 
     Ok(())
 }
+
+/// Config file path from `--config <path>`, falling back to `OMNIPRO_CONFIG`.
+fn config_path_from_args() -> Option<std::path::PathBuf> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().map(Into::into);
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(path.into());
+        }
+    }
+    std::env::var_os("OMNIPRO_CONFIG").map(Into::into)
+}
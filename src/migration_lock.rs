@@ -0,0 +1,474 @@
+// Path: src/migration_lock.rs
+
+use crate::db::{DatabaseError, DatabaseManager};
+use crate::migrations::{MigrationError, MigrationResult};
+use crate::transaction::Transaction;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// The record every runner competes for
+const LOCK_RECORD: &str = "migration_lock:runner";
+
+/// Thrown by [`LockGuard::fence`] when the lock is no longer ours
+const FENCE_ERROR: &str = "migration lock is no longer held by this runner";
+
+/// What a runner does when another instance is already migrating.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LockContention {
+    /// Wait for it to finish, then apply whatever is still pending
+    #[default]
+    Wait,
+    /// Return straight away without migrating
+    Skip,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MigrationLockConfig {
+    pub enabled: bool,
+    /// How long a lease lasts unless renewed. The holder renews it while
+    /// working, so a runner that crashed loses the lock this long after
+    /// its last renewal.
+    pub lease_ms: u64,
+    pub on_contention: LockContention,
+    /// Longest wait for another runner; 0 waits indefinitely
+    pub wait_timeout_ms: u64,
+    /// How often a waiting runner retries
+    pub poll_interval_ms: u64,
+}
+
+impl Default for MigrationLockConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            lease_ms: 30_000,
+            on_contention: LockContention::Wait,
+            wait_timeout_ms: 600_000,
+            poll_interval_ms: 1_000,
+        }
+    }
+}
+
+impl MigrationLockConfig {
+    pub fn lease(&self) -> Duration {
+        Duration::from_millis(self.lease_ms)
+    }
+
+    pub fn wait_timeout(&self) -> Option<Duration> {
+        (self.wait_timeout_ms > 0).then(|| Duration::from_millis(self.wait_timeout_ms))
+    }
+
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.poll_interval_ms)
+    }
+}
+
+/// The lock record as stored.
+#[derive(Debug, Clone, Deserialize)]
+struct Lease {
+    owner: String,
+    acquired_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    expired: bool,
+}
+
+/// Lease on the `migration_lock` record, so only one instance migrates a
+/// database at a time. The lock is taken with a single conditional UPSERT:
+/// it succeeds when the record is missing, already ours, or its lease has
+/// expired, and the datastore serializes competing attempts.
+pub(crate) struct MigrationLock {
+    db: Arc<DatabaseManager>,
+    config: MigrationLockConfig,
+    owner: String,
+}
+
+impl MigrationLock {
+    pub(crate) fn new(db: Arc<DatabaseManager>, config: MigrationLockConfig) -> Self {
+        let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "unknown".to_string());
+        let owner = format!("{}:{}:{}", host, std::process::id(), uuid::Uuid::new_v4().simple());
+        Self { db, config, owner }
+    }
+
+    pub(crate) fn config(&self) -> &MigrationLockConfig {
+        &self.config
+    }
+
+    /// Take the lock, or `None` when another runner holds it and
+    /// `contention` says to skip. With the lock disabled this always
+    /// succeeds with a guard that holds nothing.
+    pub(crate) async fn acquire(&self, contention: LockContention) -> MigrationResult<Option<LockGuard>> {
+        if !self.config.enabled {
+            return Ok(Some(LockGuard { held: None }));
+        }
+
+        let started = Instant::now();
+        let mut announced = false;
+        loop {
+            // The lease runs from when the server sees the attempt, which is
+            // no earlier than this
+            let attempted = Instant::now();
+            let holder = match self.try_acquire().await? {
+                Ok(()) => return Ok(Some(self.guard(attempted))),
+                Err(holder) => holder,
+            };
+
+            if contention == LockContention::Skip {
+                info!(
+                    "Skipping migrations: {} is running them",
+                    holder.as_ref().map_or("another instance", |h| h.owner.as_str())
+                );
+                return Ok(None);
+            }
+            // The holder is unknown after a write conflict, which must not
+            // let the wait run past its limit
+            if let Some(limit) = self.config.wait_timeout().filter(|limit| started.elapsed() >= *limit) {
+                return Err(match holder {
+                    Some(holder) => MigrationError::LockHeld {
+                        owner: holder.owner,
+                        expires_at: holder.expires_at,
+                    },
+                    None => MigrationError::LockTimeout(limit),
+                });
+            }
+            if let (false, Some(holder)) = (announced, &holder) {
+                info!(
+                    "Waiting for the migration lock held by {} since {}",
+                    holder.owner, holder.acquired_at
+                );
+                announced = true;
+            }
+            tokio::time::sleep(self.config.poll_interval()).await;
+        }
+    }
+
+    /// `Err` carries the current holder, if it could be read.
+    async fn try_acquire(&self) -> MigrationResult<Result<(), Option<Lease>>> {
+        let query = format!(
+            "SELECT owner, acquired_at, expires_at, expires_at < time::now() AS expired FROM ONLY {record};
+             UPSERT {record} SET owner = $owner, acquired_at = time::now(), expires_at = time::now() + $lease
+                 WHERE owner = NONE OR owner = $owner OR expires_at < time::now();",
+            record = LOCK_RECORD
+        );
+        // A query outliving the lease could not tell whether it still holds it
+        let attempt = self
+            .db
+            .with_timeout(Some(self.config.lease()), async {
+                let conn = self.db.get_connection().await?;
+                let mut response = conn
+                    .query(query)
+                    .bind(("owner", self.owner.clone()))
+                    .bind(("lease", surrealdb::sql::Duration::from(self.config.lease())))
+                    .await
+                    .map_err(DatabaseError::from)?;
+                let previous: Option<Lease> = response.take(0).map_err(DatabaseError::from)?;
+                let taken: Option<surrealdb::RecordId> = response.take((1, "id")).map_err(DatabaseError::from)?;
+                Ok::<_, DatabaseError>((previous, taken.is_some()))
+            })
+            .await;
+
+        let (previous, taken) = match attempt {
+            Ok(attempt) => attempt,
+            // Another runner wrote the record at the same moment
+            Err(e) if is_conflict(&e) => return Ok(Err(None)),
+            Err(e) => return Err(e.into()),
+        };
+        if !taken {
+            return Ok(Err(previous));
+        }
+        if let Some(previous) = previous.filter(|p| p.owner != self.owner && p.expired) {
+            warn!(
+                "Recovered the migration lock from {}, whose lease expired at {}",
+                previous.owner, previous.expires_at
+            );
+        }
+        Ok(Ok(()))
+    }
+
+    fn guard(&self, acquired: Instant) -> LockGuard {
+        let lost = Arc::new(AtomicBool::new(false));
+        let renewed_at = Arc::new(Mutex::new(acquired));
+        let renewer = tokio::spawn(renew(
+            self.db.clone(),
+            self.owner.clone(),
+            self.config.lease(),
+            lost.clone(),
+            renewed_at.clone(),
+        ));
+        LockGuard {
+            held: Some(Held {
+                db: self.db.clone(),
+                owner: self.owner.clone(),
+                lease: self.config.lease(),
+                lost,
+                renewed_at,
+                renewer,
+            }),
+        }
+    }
+}
+
+/// Extend the lease every third of its length until the task is aborted,
+/// flagging `lost` if another runner has taken the lock over or a renewal
+/// fails for any reason other than a write conflict, since the lease may
+/// then run out unnoticed. Each renewal is limited to a third of the lease
+/// and records in `renewed_at` when it was sent.
+async fn renew(
+    db: Arc<DatabaseManager>,
+    owner: String,
+    lease: Duration,
+    lost: Arc<AtomicBool>,
+    renewed_at: Arc<Mutex<Instant>>,
+) {
+    let query = format!(
+        "UPDATE {} SET expires_at = time::now() + $lease WHERE owner = $owner",
+        LOCK_RECORD
+    );
+    let mut interval = tokio::time::interval((lease / 3).max(Duration::from_millis(1)));
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let sent = Instant::now();
+        let renewed = db
+            .with_timeout(Some(lease / 3), async {
+                let conn = db.get_connection().await?;
+                let id: Option<surrealdb::RecordId> = conn
+                    .query(query.as_str())
+                    .bind(("owner", owner.clone()))
+                    .bind(("lease", surrealdb::sql::Duration::from(lease)))
+                    .await?
+                    .take((0, "id"))?;
+                Ok::<_, DatabaseError>(id.is_some())
+            })
+            .await;
+
+        match renewed {
+            Ok(true) => *renewed_at.lock().unwrap_or_else(|e| e.into_inner()) = sent,
+            Ok(false) => {
+                warn!("Lost the migration lock to another runner");
+                lost.store(true, Ordering::SeqCst);
+                return;
+            }
+            // A waiting runner tried to take it at the same moment
+            Err(e) if is_conflict(&e) => {}
+            Err(e) => {
+                warn!("Failed to renew the migration lock, giving it up: {}", e);
+                lost.store(true, Ordering::SeqCst);
+                return;
+            }
+        }
+    }
+}
+
+/// Whether `error` is a write conflict with another runner. Depending on
+/// the engine and statement it arrives typed or only as a message.
+fn is_conflict(error: &DatabaseError) -> bool {
+    use surrealdb::error::Db;
+
+    match error {
+        DatabaseError::DatabaseError(e) => {
+            matches!(**e, surrealdb::Error::Db(Db::TxRetryable)) || e.to_string().contains("read or write conflict")
+        }
+        _ => false,
+    }
+}
+
+/// Whether `error` comes from a [`LockGuard::fence`] that found the lock
+/// taken over or expired.
+pub(crate) fn is_fenced(error: &DatabaseError) -> bool {
+    error.to_string().contains(FENCE_ERROR)
+}
+
+/// Attempts at releasing the lock before leaving it to expire
+const RELEASE_ATTEMPTS: usize = 3;
+
+struct Held {
+    db: Arc<DatabaseManager>,
+    owner: String,
+    lease: Duration,
+    lost: Arc<AtomicBool>,
+    /// When the last successful renewal, or the acquisition, was sent
+    renewed_at: Arc<Mutex<Instant>>,
+    renewer: JoinHandle<()>,
+}
+
+impl Held {
+    /// Whether the lease has run out since the last renewal, even if the
+    /// renewer has not noticed yet, e.g. while a renewal is stalled.
+    fn expired(&self) -> bool {
+        self.renewed_at.lock().unwrap_or_else(|e| e.into_inner()).elapsed() >= self.lease
+    }
+}
+
+/// Proof of holding the migration lock. Dropping it stops the renewals, so
+/// the lease runs out; [`LockGuard::release`] frees it straight away.
+pub(crate) struct LockGuard {
+    /// `None` when locking is disabled
+    held: Option<Held>,
+}
+
+impl LockGuard {
+    /// Fail once the lease has been lost, could not be renewed or has run
+    /// out, so no further migration or statement is applied without the lock.
+    pub(crate) fn check(&self) -> MigrationResult<()> {
+        match &self.held {
+            Some(held) if held.lost.load(Ordering::SeqCst) || held.expired() => Err(MigrationError::LockLost),
+            _ => Ok(()),
+        }
+    }
+
+    /// Queue a check on `tx` that fails it, see [`is_fenced`], unless this
+    /// runner still holds an unexpired lease. Queued last, it keeps a runner
+    /// whose lease ran out during a long migration from committing after
+    /// another has taken over.
+    pub(crate) fn fence(&self, tx: &Transaction) {
+        if let Some(held) = &self.held {
+            tx.query(format!(
+                "IF {record}.owner != $owner OR {record}.expires_at < time::now() {{ THROW $error }}",
+                record = LOCK_RECORD
+            ))
+            .bind(("owner", held.owner.clone()))
+            .bind(("error", FENCE_ERROR));
+        }
+    }
+
+    pub(crate) async fn release(mut self) {
+        let Some(held) = self.held.take() else {
+            return;
+        };
+        held.renewer.abort();
+
+        for attempt in 1..=RELEASE_ATTEMPTS {
+            let released = held
+                .db
+                .with_timeout(Some(held.lease), async {
+                    let conn = held.db.get_connection().await?;
+                    conn.query(format!("DELETE {} WHERE owner = $owner", LOCK_RECORD))
+                        .bind(("owner", held.owner.clone()))
+                        .await?
+                        .check()?;
+                    Ok::<_, DatabaseError>(())
+                })
+                .await;
+            match released {
+                Ok(()) => return,
+                // A waiting runner tried to take it at the same moment
+                Err(e) if is_conflict(&e) && attempt < RELEASE_ATTEMPTS => continue,
+                // Not fatal: the lease expires on its own
+                Err(e) => {
+                    warn!("Failed to release the migration lock: {}", e);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        if let Some(held) = &self.held {
+            held.renewer.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_db;
+
+    fn config(lease_ms: u64, wait_timeout_ms: u64) -> MigrationLockConfig {
+        MigrationLockConfig {
+            lease_ms,
+            wait_timeout_ms,
+            poll_interval_ms: 10,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_only_one_holder() {
+        let db = Arc::new(test_db("test", "migration_lock").await);
+        let first = MigrationLock::new(db.clone(), config(30_000, 50));
+        let second = MigrationLock::new(db.clone(), config(30_000, 50));
+
+        let guard = first.acquire(LockContention::Wait).await.unwrap().unwrap();
+        assert!(second.acquire(LockContention::Skip).await.unwrap().is_none());
+        assert!(matches!(
+            second.acquire(LockContention::Wait).await,
+            Err(MigrationError::LockHeld { owner, .. }) if owner == first.owner
+        ));
+
+        guard.release().await;
+        let guard = second.acquire(LockContention::Skip).await.unwrap().unwrap();
+        guard.check().unwrap();
+        guard.release().await;
+    }
+
+    async fn fenced_write(db: &DatabaseManager, guard: &LockGuard, id: &'static str) -> Result<(), DatabaseError> {
+        db.transaction(|tx| async move {
+            tx.query("CREATE type::thing('item', $id)").bind(("id", id));
+            guard.fence(&tx);
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_fence() {
+        let db = Arc::new(test_db("test", "migration_lock_fence").await);
+        let lock = MigrationLock::new(db.clone(), config(30_000, 0));
+        let guard = lock.acquire(LockContention::Wait).await.unwrap().unwrap();
+
+        fenced_write(&db, &guard, "held").await.unwrap();
+
+        db.execute_query("UPDATE migration_lock:runner SET owner = 'intruder'", Default::default())
+            .await
+            .unwrap();
+        assert!(fenced_write(&db, &guard, "lost").await.is_err_and(|e| is_fenced(&e)));
+        let items = db.execute_query("SELECT VALUE meta::id(id) FROM item", Default::default()).await.unwrap();
+        assert_eq!(items, serde_json::json!([["held"]]));
+    }
+
+    #[tokio::test]
+    async fn test_check_fails_once_the_lease_runs_out() {
+        let db = Arc::new(test_db("test", "migration_lock_expiry").await);
+        let lock = MigrationLock::new(db, config(100, 0));
+        let guard = lock.acquire(LockContention::Wait).await.unwrap().unwrap();
+
+        // A stalled renewer never flags the lease as lost
+        guard.held.as_ref().unwrap().renewer.abort();
+        guard.check().unwrap();
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(matches!(guard.check(), Err(MigrationError::LockLost)));
+    }
+
+    #[tokio::test]
+    async fn test_stale_lease_is_recovered() {
+        let db = Arc::new(test_db("test", "migration_lock").await);
+        let crashed = MigrationLock::new(db.clone(), config(100, 0));
+        let waiting = MigrationLock::new(db.clone(), config(100, 5_000));
+
+        // Dropping the guard stops renewals without releasing, like a crash
+        drop(crashed.acquire(LockContention::Wait).await.unwrap().unwrap());
+        assert!(waiting.acquire(LockContention::Skip).await.unwrap().is_none());
+        let guard = waiting.acquire(LockContention::Wait).await.unwrap().unwrap();
+
+        // Renewals keep the lease alive past its length
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert!(crashed.acquire(LockContention::Skip).await.unwrap().is_none());
+        guard.check().unwrap();
+
+        // A holder whose lease was taken over notices on its next renewal
+        db.execute_query("UPDATE migration_lock:runner SET owner = 'intruder'", Default::default())
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(matches!(guard.check(), Err(MigrationError::LockLost)));
+    }
+}
@@ -0,0 +1,266 @@
+// Path: src/migration_plan.rs
+
+use crate::migrations::{AppliedMigration, Migration};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Statements that drop data along with the schema
+const DESTRUCTIVE: &[(&str, &str)] = &[
+    ("REMOVE TABLE", "drops the table and all of its records"),
+    ("REMOVE FIELD", "drops the field from every record"),
+    ("REMOVE DATABASE", "drops the whole database"),
+    ("REMOVE NAMESPACE", "drops the whole namespace"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlanDirection {
+    Apply,
+    Rollback,
+}
+
+/// One migration a run would apply or roll back.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedMigration {
+    pub version: i32,
+    pub name: String,
+    pub transactional: bool,
+    /// The up script when applying, the down script when rolling back
+    pub statements: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// What [`MigrationManager::run_pending_migrations`] or
+/// [`MigrationManager::rollback`] would do, in order. Display renders it as
+/// text; it also serializes to JSON.
+///
+/// [`MigrationManager::run_pending_migrations`]: crate::migrations::MigrationManager::run_pending_migrations
+/// [`MigrationManager::rollback`]: crate::migrations::MigrationManager::rollback
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MigrationPlan {
+    pub direction: PlanDirection,
+    pub current_version: i32,
+    pub target_version: i32,
+    pub migrations: Vec<PlannedMigration>,
+    /// Problems outside the planned migrations, e.g. edited applied ones
+    pub warnings: Vec<String>,
+}
+
+impl MigrationPlan {
+    /// Plan from the known `migrations` (in version order) and the rows of
+    /// the `migration` table, mirroring the choices the manager makes.
+    pub(crate) fn build(
+        direction: PlanDirection,
+        target_version: Option<i32>,
+        migrations: &[Migration],
+        applied: &[AppliedMigration],
+    ) -> Self {
+        let current_version = applied.iter().map(|a| a.version).max().unwrap_or(0);
+
+        let mut warnings = Vec::new();
+        for applied in applied {
+            let Some(migration) = migrations.iter().find(|m| m.version == applied.version) else {
+                continue;
+            };
+            if applied.checksum.as_ref().is_some_and(|checksum| *checksum != migration.checksum()) {
+                warnings.push(format!(
+                    "migration {} ({}) changed after it was applied; the run will fail until it is repaired",
+                    migration.version, migration.name
+                ));
+            }
+        }
+
+        let (selected, target_version): (Vec<&Migration>, i32) = match direction {
+            PlanDirection::Apply => (
+                migrations.iter().filter(|m| m.version > current_version).collect(),
+                migrations.last().map_or(current_version, |m| m.version.max(current_version)),
+            ),
+            PlanDirection::Rollback => {
+                let target = target_version.unwrap_or(current_version);
+                let undone = migrations
+                    .iter()
+                    .filter(|m| m.version > target && applied.iter().any(|a| a.version == m.version))
+                    .rev()
+                    .collect();
+                (undone, target)
+            }
+        };
+
+        let migrations = selected
+            .into_iter()
+            .map(|migration| plan_migration(direction, migration))
+            .collect();
+        Self {
+            direction,
+            current_version,
+            target_version,
+            migrations,
+            warnings,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.migrations.is_empty()
+    }
+
+    /// Whether anything in the plan deserves a look before running it.
+    pub fn has_warnings(&self) -> bool {
+        !self.warnings.is_empty() || self.migrations.iter().any(|m| !m.warnings.is_empty())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("plans always serialize")
+    }
+}
+
+fn plan_migration(direction: PlanDirection, migration: &Migration) -> PlannedMigration {
+    let script = match direction {
+        PlanDirection::Apply => &migration.up,
+        PlanDirection::Rollback => &migration.down,
+    };
+
+    let mut warnings = Vec::new();
+    let statements = match surrealdb::sql::parse(script) {
+        Ok(query) => query.iter().map(|statement| statement.to_string()).collect(),
+        Err(e) => {
+            warnings.push(format!("the script does not parse: {}", e));
+            vec![script.trim().to_string()]
+        }
+    };
+
+    for statement in &statements {
+        let upper = statement.to_ascii_uppercase();
+        if let Some((_, effect)) = DESTRUCTIVE.iter().find(|(prefix, _)| upper.starts_with(prefix)) {
+            warnings.push(format!("`{}` {}", statement, effect));
+        }
+    }
+    if migration.down.trim().is_empty() {
+        warnings.push(match direction {
+            PlanDirection::Apply => "has no down script, so it cannot be rolled back".to_string(),
+            PlanDirection::Rollback => "has no down script; only its history row is removed".to_string(),
+        });
+    }
+    if !migration.transactional {
+        warnings.push("is not transactional: a failure leaves the statements before it applied".to_string());
+    }
+
+    PlannedMigration {
+        version: migration.version,
+        name: migration.name.clone(),
+        transactional: migration.transactional,
+        statements,
+        warnings,
+    }
+}
+
+impl fmt::Display for MigrationPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = match self.direction {
+            PlanDirection::Apply => "apply",
+            PlanDirection::Rollback => "roll back",
+        };
+        if self.is_empty() {
+            writeln!(f, "Nothing to {}; the database is at version {}", verb, self.current_version)?;
+        } else {
+            writeln!(
+                f,
+                "Plan to {} {} migration(s), from version {} to {}:",
+                verb,
+                self.migrations.len(),
+                self.current_version,
+                self.target_version
+            )?;
+        }
+        for warning in &self.warnings {
+            writeln!(f, "  warning: {}", warning)?;
+        }
+
+        for migration in &self.migrations {
+            let mode = if migration.transactional { "transactional" } else { "not transactional" };
+            writeln!(f, "\n[{}] {} ({})", migration.version, migration.name, mode)?;
+            for statement in &migration.statements {
+                writeln!(f, "    {};", statement)?;
+            }
+            for warning in &migration.warnings {
+                writeln!(f, "  warning: {}", warning)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migration(version: i32, up: &str, down: &str) -> Migration {
+        Migration {
+            version,
+            name: format!("step_{}", version),
+            description: String::new(),
+            up: up.to_string(),
+            down: down.to_string(),
+            applied_at: None,
+            transactional: true,
+        }
+    }
+
+    fn applied(migration: &Migration) -> AppliedMigration {
+        AppliedMigration {
+            version: migration.version,
+            checksum: Some(migration.checksum()),
+        }
+    }
+
+    #[test]
+    fn test_apply_plan() {
+        let mut migrations = vec![
+            migration(1, "DEFINE TABLE item;", "REMOVE TABLE item;"),
+            migration(2, "DEFINE FIELD name ON item TYPE string; UPDATE item SET name = 'x';", ""),
+            migration(3, "REMOVE FIELD legacy ON item;", "DEFINE FIELD legacy ON item;"),
+        ];
+        migrations[2].transactional = false;
+
+        let plan = MigrationPlan::build(PlanDirection::Apply, None, &migrations, &[applied(&migrations[0])]);
+        assert_eq!((plan.current_version, plan.target_version), (1, 3));
+        assert_eq!(plan.migrations.iter().map(|m| m.version).collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(plan.migrations[0].statements.len(), 2);
+        assert!(plan.migrations[0].warnings[0].contains("no down script"));
+        assert_eq!(plan.migrations[1].statements, vec!["REMOVE FIELD legacy ON item"]);
+        assert_eq!(plan.migrations[1].warnings.len(), 2);
+        assert!(plan.warnings.is_empty() && plan.has_warnings());
+
+        let text = plan.to_string();
+        assert!(text.contains("from version 1 to 3"), "{}", text);
+        assert!(text.contains("    REMOVE FIELD legacy ON item;"), "{}", text);
+        let json: MigrationPlan = serde_json::from_str(&plan.to_json()).unwrap();
+        assert_eq!(json, plan);
+
+        // Everything applied, but one of them was edited since
+        let mut all: Vec<_> = migrations.iter().map(applied).collect();
+        all[0].checksum = Some("stale".to_string());
+        let plan = MigrationPlan::build(PlanDirection::Apply, None, &migrations, &all);
+        assert!(plan.is_empty());
+        assert!(plan.warnings[0].contains("migration 1 (step_1) changed"), "{:?}", plan.warnings);
+        assert!(plan.to_string().starts_with("Nothing to apply"));
+    }
+
+    #[test]
+    fn test_rollback_plan() {
+        let migrations = vec![
+            migration(1, "DEFINE TABLE item;", "REMOVE TABLE item;"),
+            migration(2, "DEFINE TABLE tag;", "not surrealql"),
+            migration(3, "DEFINE TABLE note;", "REMOVE TABLE note;"),
+        ];
+        let applied: Vec<_> = migrations[..2].iter().map(applied).collect();
+
+        // Migration 3 was never applied, so there is nothing to undo
+        let plan = MigrationPlan::build(PlanDirection::Rollback, Some(0), &migrations, &applied);
+        assert_eq!(plan.direction, PlanDirection::Rollback);
+        assert_eq!(plan.migrations.iter().map(|m| m.version).collect::<Vec<_>>(), vec![2, 1]);
+        assert!(plan.migrations[0].warnings.iter().any(|w| w.contains("does not parse")));
+        assert_eq!(plan.migrations[1].statements, vec!["REMOVE TABLE item"]);
+        assert!(plan.migrations[1].warnings[0].contains("drops the table"));
+        assert!(plan.to_json().contains("\"direction\": \"rollback\""));
+    }
+}
@@ -2,20 +2,60 @@
 
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use surrealdb::engine::remote::ws::Client;
-use surrealdb::Surreal;
-use tracing::{info, error, instrument};
+use std::time::Duration;
+use tracing::{info, instrument, warn};
+use crate::db::{DatabaseError, DatabaseManager};
+use crate::migration_lock::{is_fenced, LockContention, LockGuard, MigrationLock, MigrationLockConfig};
+use crate::migration_plan::{MigrationPlan, PlanDirection};
 use crate::telemetry::TelemetryManager;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum MigrationError {
     #[error("Database error: {0}")]
-    DatabaseError(#[from] surrealdb::Error),
+    DatabaseError(Box<surrealdb::Error>),
+
+    #[error("Connection error: {0}")]
+    ConnectionError(#[from] crate::db::DatabaseError),
 
     #[error("Migration failed: {0}")]
     MigrationFailed(String),
+
+    #[error("Invalid migration: {0}")]
+    InvalidMigration(String),
+
+    #[error("IO error: {0}")]
+    IOError(#[from] std::io::Error),
+
+    #[error(
+        "Migration {version} ({name}) changed after it was applied: recorded checksum {recorded}, now {actual}. \
+         Repair the migrations to accept the change"
+    )]
+    ChecksumMismatch {
+        version: i32,
+        name: String,
+        recorded: String,
+        actual: String,
+    },
+
+    #[error("Migration lock is held by {owner} until {expires_at}")]
+    LockHeld { owner: String, expires_at: DateTime<Utc> },
+
+    #[error("Timed out after {0:?} waiting for the migration lock")]
+    LockTimeout(Duration),
+
+    #[error("Migration lock was lost to another runner")]
+    LockLost,
+}
+
+impl From<surrealdb::Error> for MigrationError {
+    fn from(error: surrealdb::Error) -> Self {
+        MigrationError::DatabaseError(Box::new(error))
+    }
 }
 
 pub type MigrationResult<T> = std::result::Result<T, MigrationError>;
@@ -28,40 +68,295 @@ pub struct Migration {
     pub up: String,
     pub down: String,
     pub applied_at: Option<DateTime<Utc>>,
+    /// Whether the scripts and the history row are applied in one
+    /// transaction. Migrations that cannot run inside one, e.g. very large
+    /// backfills, set this to false: their statements then run one by one,
+    /// and a failure leaves the earlier ones applied, so they should be
+    /// safe to re-run.
+    #[serde(default = "transactional_default")]
+    pub transactional: bool,
+}
+
+fn transactional_default() -> bool {
+    true
+}
+
+impl Migration {
+    /// SHA-256 of the up and down scripts, recorded when the migration is
+    /// applied so later edits to it are caught.
+    pub fn checksum(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.up.as_bytes());
+        hasher.update([0]);
+        hasher.update(self.down.as_bytes());
+        hex::encode(hasher.finalize())
+    }
+}
+
+/// A migration whose last attempt failed, as recorded in the `migration`
+/// table. The row is replaced once the migration succeeds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationFailure {
+    pub version: i32,
+    pub name: String,
+    /// A failed non-transactional migration may be partly applied
+    pub transactional: bool,
+    pub error: String,
+    pub failed_at: DateTime<Utc>,
+}
+
+/// An applied row of the `migration` table.
+#[derive(Debug, Deserialize)]
+pub(crate) struct AppliedMigration {
+    pub(crate) version: i32,
+    /// Missing for migrations applied before checksums were recorded
+    pub(crate) checksum: Option<String>,
+}
+
+/// Migration files from the crate's `migrations/` directory, compiled in by build.rs
+const EMBEDDED: &[(&str, &str)] = include!(concat!(env!("OUT_DIR"), "/embedded_migrations.rs"));
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MigrationConfig {
+    /// Directory of `.surql` migrations read at startup. When unset the
+    /// migrations embedded at build time are used.
+    pub dir: Option<PathBuf>,
+    pub lock: MigrationLockConfig,
+}
+
+/// Optional YAML block between `---` lines at the top of an up file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FrontMatter {
+    name: Option<String>,
+    description: String,
+    transactional: Option<bool>,
+}
+
+/// The files found for one version, as `(file name, contents)`.
+struct VersionFiles {
+    /// Name part of the file names
+    name: String,
+    up: Option<(String, String)>,
+    down: Option<(String, String)>,
+}
+
+/// Split `---` delimited front matter off the start of `contents`.
+fn split_front_matter(contents: &str) -> Option<(&str, &str)> {
+    let rest = contents.strip_prefix("---\n").or_else(|| contents.strip_prefix("---\r\n"))?;
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            return Some((&rest[..offset], &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// Build migrations from `(file name, contents)` pairs named
+/// `<version>_<name>.up.surql` and `<version>_<name>.down.surql`. Every
+/// version needs both files; an empty down file marks a migration that
+/// cannot be undone. Other files are ignored. The up file may start with
+/// front matter setting `name`, `description` and `transactional`.
+pub fn parse_migrations<I, N, C>(files: I) -> MigrationResult<Vec<Migration>>
+where
+    I: IntoIterator<Item = (N, C)>,
+    N: AsRef<str>,
+    C: AsRef<str>,
+{
+    let invalid = |file: &str, message: &str| MigrationError::InvalidMigration(format!("{}: {}", file, message));
+
+    let mut found: BTreeMap<i32, VersionFiles> = BTreeMap::new();
+    for (file, contents) in files {
+        let (file, contents) = (file.as_ref(), contents.as_ref());
+        let Some(stem) = file.strip_suffix(".surql") else {
+            continue;
+        };
+        let (stem, up) = match (stem.strip_suffix(".up"), stem.strip_suffix(".down")) {
+            (Some(stem), _) => (stem, true),
+            (_, Some(stem)) => (stem, false),
+            _ => return Err(invalid(file, "expected a .up.surql or .down.surql suffix")),
+        };
+        let (version, name) = stem
+            .split_once('_')
+            .ok_or_else(|| invalid(file, "expected <version>_<name>"))?;
+        let version: i32 = version
+            .parse()
+            .ok()
+            .filter(|version| *version > 0)
+            .ok_or_else(|| invalid(file, "the version must be a positive number"))?;
+
+        let entry = found.entry(version).or_insert_with(|| VersionFiles {
+            name: name.to_string(),
+            up: None,
+            down: None,
+        });
+        if entry.name != name {
+            return Err(invalid(file, &format!("version {} is also used by {}", version, entry.name)));
+        }
+        let slot = if up { &mut entry.up } else { &mut entry.down };
+        if slot.replace((file.to_string(), contents.to_string())).is_some() {
+            return Err(invalid(file, "duplicate file"));
+        }
+    }
+
+    let mut migrations = Vec::with_capacity(found.len());
+    for (version, VersionFiles { name, up, down }) in found {
+        let (Some((file, up)), Some((down_file, down))) = (up, down) else {
+            return Err(MigrationError::InvalidMigration(format!(
+                "version {} ({}) needs both an up and a down file",
+                version, name
+            )));
+        };
+        if split_front_matter(&down).is_some() {
+            return Err(invalid(&down_file, "front matter belongs in the up file"));
+        }
+
+        let (front, up) = match split_front_matter(&up) {
+            Some((yaml, body)) => {
+                let front: FrontMatter = serde_yaml::from_str(yaml).map_err(|e| invalid(&file, &e.to_string()))?;
+                (front, body.to_string())
+            }
+            None => (FrontMatter::default(), up),
+        };
+        migrations.push(Migration {
+            version,
+            name: front.name.unwrap_or_else(|| name.replace('_', " ")),
+            description: front.description,
+            up,
+            down,
+            applied_at: None,
+            transactional: front.transactional.unwrap_or(true),
+        });
+    }
+    Ok(migrations)
 }
 
 pub struct MigrationManager {
-    db: Arc<Surreal<Client>>,
+    db: Arc<DatabaseManager>,
     telemetry: Arc<TelemetryManager>,
     migrations: Vec<Migration>,
+    timeout: Option<Duration>,
+    lock: MigrationLock,
 }
 
 impl MigrationManager {
-    pub async fn new(db: Arc<Surreal<Client>>, telemetry: Arc<TelemetryManager>) -> MigrationResult<Self> {
+    pub async fn new(db: Arc<DatabaseManager>, telemetry: Arc<TelemetryManager>) -> MigrationResult<Self> {
         Ok(Self {
+            lock: MigrationLock::new(db.clone(), MigrationLockConfig::default()),
             db,
             telemetry,
             migrations: Vec::new(),
+            timeout: None,
         })
     }
 
+    /// Limit each migration to `timeout` instead of the configured query
+    /// timeout, e.g. for migrations that rebuild large indexes.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Coordinate with other instances through `config` instead of the
+    /// default lock settings.
+    pub fn with_lock(mut self, config: MigrationLockConfig) -> Self {
+        self.lock = MigrationLock::new(self.db.clone(), config);
+        self
+    }
+
+    /// Add a migration, keeping them in version order.
     pub fn add_migration(&mut self, migration: Migration) {
-        self.migrations.push(migration);
+        let position = self.migrations.partition_point(|m| m.version <= migration.version);
+        self.migrations.insert(position, migration);
+    }
+
+    /// Add the migrations in `dir`; see [`parse_migrations`] for the layout.
+    pub async fn load_dir(&mut self, dir: impl AsRef<Path>) -> MigrationResult<()> {
+        let mut files = Vec::new();
+        let mut entries = tokio::fs::read_dir(dir.as_ref()).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let file = entry.file_name().to_string_lossy().into_owned();
+            if file.ends_with(".surql") && entry.file_type().await?.is_file() {
+                files.push((file, tokio::fs::read_to_string(entry.path()).await?));
+            }
+        }
+        self.load(parse_migrations(files)?)
+    }
+
+    /// Add the migrations compiled into the binary from `migrations/`.
+    pub fn load_embedded(&mut self) -> MigrationResult<()> {
+        self.load(parse_migrations(EMBEDDED.iter().copied())?)
+    }
+
+    fn load(&mut self, migrations: Vec<Migration>) -> MigrationResult<()> {
+        let count = migrations.len();
+        for migration in migrations {
+            self.add_migration(migration);
+        }
+        self.validate()?;
+        info!("Loaded {} migrations", count);
+        Ok(())
+    }
+
+    /// Check that the versions run 1, 2, 3, ... without duplicates or gaps.
+    pub fn validate(&self) -> MigrationResult<()> {
+        for (expected, migration) in (1..).zip(&self.migrations) {
+            if migration.version != expected {
+                let problem = match migration.version < expected {
+                    true => "is duplicated",
+                    false => "follows a gap",
+                };
+                return Err(MigrationError::InvalidMigration(format!(
+                    "version {} ({}) {}; expected version {}",
+                    migration.version, migration.name, problem, expected
+                )));
+            }
+        }
+        Ok(())
     }
 
     #[instrument(name = "run_pending_migrations", skip(self))]
     pub async fn run_pending_migrations(&self) -> MigrationResult<()> {
+        self.validate()?;
+        let Some(guard) = self.acquire_lock(self.lock.config().on_contention).await? else {
+            return Ok(());
+        };
+        let result = self.apply_pending(&guard).await;
+        guard.release().await;
+        result
+    }
+
+    /// Apply pending migrations while holding the lock. The current version
+    /// is read after taking it, so work done by the previous holder is not
+    /// repeated.
+    async fn apply_pending(&self, guard: &LockGuard) -> MigrationResult<()> {
+        self.verify_checksums().await?;
         let current_version = self.get_current_version().await?;
-        
+
         for migration in self.migrations.iter().filter(|m| m.version > current_version) {
-            self.apply_migration(migration).await?;
+            guard.check()?;
+            self.apply_migration(migration, guard).await?;
         }
 
         Ok(())
     }
 
-    #[instrument(name = "apply_migration", skip(self), fields(version = %migration.version, name = %migration.name))]
-    async fn apply_migration(&self, migration: &Migration) -> MigrationResult<()> {
+    async fn acquire_lock(&self, contention: LockContention) -> MigrationResult<Option<LockGuard>> {
+        let guard = self.lock.acquire(contention).await?;
+        let result = if guard.is_some() { "acquired" } else { "skipped" };
+        self.telemetry.record_metric(
+            "migration_lock".to_string(),
+            1.0,
+            vec![("result".to_string(), result.to_string())],
+        );
+        Ok(guard)
+    }
+
+    #[instrument(name = "apply_migration", skip(self, guard), fields(version = %migration.version, name = %migration.name))]
+    async fn apply_migration(&self, migration: &Migration, guard: &LockGuard) -> MigrationResult<()> {
         info!("Applying migration {} - {}", migration.version, migration.name);
         
         // Clone the necessary data upfront
@@ -69,6 +364,7 @@ impl MigrationManager {
         let name = migration.name.clone();
         let description = migration.description.clone();
         let up_query = migration.up.clone();
+        let checksum = migration.checksum();
 
         self.telemetry.record_metric(
             "migration_apply_details".to_string(),
@@ -79,18 +375,38 @@ impl MigrationManager {
             ],
         );
 
-        // Execute migration using cloned query
-        self.db.query(&up_query)
+        // Each commit is fenced on the lock, so a runner that lost it
+        // mid-migration cannot apply anything after another took over
+        let result = match migration.transactional {
+            // A failing script leaves neither partial changes nor an applied row behind
+            true => self.db.transaction_with_timeout(self.timeout, |tx| async move {
+                tx.query(up_query);
+                record_applied(&tx, version, name, description, checksum, true);
+                guard.fence(&tx);
+                Ok::<_, DatabaseError>(())
+            })
             .await
-            .map_err(|e| MigrationError::MigrationFailed(format!("Failed to apply migration: {}", e)))?;
-
-        // Record migration using already cloned data
-        self.db.query("CREATE migration SET version = $version, name = $name, description = $description, applied_at = time::now()")
-            .bind(("version", version))
-            .bind(("name", name))
-            .bind(("description", description))
-            .await
-            .map_err(MigrationError::DatabaseError)?;
+            .map_err(fenced),
+            false => match self.run_statements(&up_query, guard).await {
+                Ok(()) => self.db.transaction_with_timeout(self.timeout, |tx| async move {
+                    record_applied(&tx, version, name, description, checksum, false);
+                    guard.fence(&tx);
+                    Ok::<_, DatabaseError>(())
+                })
+                .await
+                .map_err(fenced),
+                Err(e) => Err(e),
+            },
+        };
+        match result {
+            Ok(()) => {}
+            Err(MigrationError::LockLost) => return Err(MigrationError::LockLost),
+            Err(e) => {
+                let e = e.to_string();
+                self.record_failure(migration, &e).await;
+                return Err(MigrationError::MigrationFailed(format!("Failed to apply migration: {}", e)));
+            }
+        }
 
         info!("Migration {} applied successfully", migration.version);
         Ok(())
@@ -98,8 +414,18 @@ impl MigrationManager {
 
     #[instrument(name = "rollback", skip(self), fields(target_version = %target_version))]
     pub async fn rollback(&self, target_version: i32) -> MigrationResult<()> {
+        self.validate()?;
+        let guard = self.wait_for_lock().await?;
+        let result = self.rollback_locked(target_version, &guard).await;
+        guard.release().await;
+        result
+    }
+
+    async fn rollback_locked(&self, target_version: i32, guard: &LockGuard) -> MigrationResult<()> {
+        self.verify_checksums().await?;
         let current_version = self.get_current_version().await?;
-        
+        let applied = self.applied_migrations().await?;
+
         self.telemetry.record_metric(
             "migration_rollback".to_string(),
             1.0,
@@ -109,15 +435,22 @@ impl MigrationManager {
             ],
         );
 
-        for migration in self.migrations.iter().filter(|m| m.version > target_version).rev() {
-            self.rollback_migration(migration).await?;
+        // Migrations that were never applied, e.g. added since the last
+        // run, have nothing to undo
+        let to_undo = self
+            .migrations
+            .iter()
+            .filter(|m| m.version > target_version && applied.iter().any(|a| a.version == m.version));
+        for migration in to_undo.rev() {
+            guard.check()?;
+            self.rollback_migration(migration, guard).await?;
         }
 
         Ok(())
     }
 
-    #[instrument(name = "rollback_migration", skip(self), fields(version = %migration.version, name = %migration.name))]
-    async fn rollback_migration(&self, migration: &Migration) -> MigrationResult<()> {
+    #[instrument(name = "rollback_migration", skip(self, guard), fields(version = %migration.version, name = %migration.name))]
+    async fn rollback_migration(&self, migration: &Migration, guard: &LockGuard) -> MigrationResult<()> {
         info!("Rolling back migration {} - {}", migration.version, migration.name);
         
         self.telemetry.record_metric(
@@ -129,72 +462,602 @@ impl MigrationManager {
             ],
         );
 
-        // Execute rollback
-        self.db.query(&migration.down)
-            .await
-            .map_err(|e| MigrationError::MigrationFailed(format!("Failed to rollback migration: {}", e)))?;
-
-        // Remove migration record
+        // Execute rollback and remove the migration record atomically,
+        // unless the migration opted out of transactions
         let version = migration.version;
-        self.db.query("DELETE FROM migration WHERE version = $version")
-            .bind(("version", version))
-            .await
-            .map_err(MigrationError::DatabaseError)?;
+        let down_query = match migration.transactional {
+            true => migration.down.clone(),
+            false => {
+                self.run_statements(&migration.down, guard).await.map_err(|e| match e {
+                    MigrationError::LockLost => e,
+                    e => MigrationError::MigrationFailed(format!("Failed to rollback migration: {}", e)),
+                })?;
+                String::new()
+            }
+        };
+        self.db.transaction_with_timeout(self.timeout, |tx| async move {
+            if !down_query.trim().is_empty() {
+                tx.query(down_query);
+            }
+            tx.query("DELETE FROM migration WHERE version = $version")
+                .bind(("version", version));
+            guard.fence(&tx);
+            Ok::<_, crate::db::DatabaseError>(())
+        })
+        .await
+        .map_err(|e| match is_fenced(&e) {
+            true => MigrationError::LockLost,
+            false => MigrationError::MigrationFailed(format!("Failed to rollback migration: {}", e)),
+        })?;
 
         info!("Migration {} rolled back successfully", migration.version);
         Ok(())
     }
 
+    /// Run `script` outside a transaction, one statement at a time, stopping
+    /// at the first failure or once the lock is lost. Each statement gets
+    /// the migration timeout.
+    async fn run_statements(&self, script: &str, guard: &LockGuard) -> MigrationResult<()> {
+        let statements = surrealdb::sql::parse(script)
+            .map_err(|e| MigrationError::MigrationFailed(format!("cannot parse script: {}", e)))?;
+        let total = statements.len();
+        for (index, statement) in statements.into_iter().enumerate() {
+            guard.check()?;
+            self.db
+                .with_timeout(self.timeout, async {
+                    let conn = self.db.get_connection().await?;
+                    conn.query(statement.to_string()).await?.check()?;
+                    Ok::<_, MigrationError>(())
+                })
+                .await
+                .map_err(|e| {
+                    MigrationError::MigrationFailed(format!(
+                        "statement {} of {} failed, the ones before it stay applied: {}",
+                        index + 1,
+                        total,
+                        e
+                    ))
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Leave a `failed` row with the error for operators, replacing the
+    /// one from an earlier failed attempt.
+    async fn record_failure(&self, migration: &Migration, error: &str) {
+        self.telemetry.record_metric(
+            "migration_failed".to_string(),
+            1.0,
+            vec![("version".to_string(), migration.version.to_string())],
+        );
+
+        let (version, name, transactional) = (migration.version, migration.name.clone(), migration.transactional);
+        let (description, checksum, error) = (migration.description.clone(), migration.checksum(), error.to_string());
+        let recorded = self.db.transaction_with_timeout(self.timeout, |tx| async move {
+            tx.query("DELETE migration WHERE version = $version AND status = 'failed'")
+                .bind(("version", version));
+            tx.query("CREATE migration SET version = $version, name = $name, description = $description, checksum = $checksum, transactional = $transactional, status = 'failed', error = $error, failed_at = time::now()")
+                .bind(("version", version))
+                .bind(("name", name))
+                .bind(("description", description))
+                .bind(("checksum", checksum))
+                .bind(("transactional", transactional))
+                .bind(("error", error));
+            Ok::<_, DatabaseError>(())
+        })
+        .await;
+        if let Err(e) = recorded {
+            warn!("Failed to record the failure of migration {}: {}", version, e);
+        }
+    }
+
+    /// Migrations whose last attempt failed, oldest version first.
+    pub async fn failures(&self) -> MigrationResult<Vec<MigrationFailure>> {
+        self.db.with_timeout(self.timeout, async {
+            let conn = self.db.get_connection().await?;
+            Ok(conn
+                .query("SELECT version, name, transactional, error, failed_at FROM migration WHERE status = 'failed' ORDER BY version")
+                .await?
+                .take(0)?)
+        }).await
+    }
+
+    /// What [`run_pending_migrations`](Self::run_pending_migrations) would
+    /// apply, without taking the lock or writing anything.
+    pub async fn plan(&self) -> MigrationResult<MigrationPlan> {
+        self.validate()?;
+        let applied = self.applied_migrations().await?;
+        Ok(MigrationPlan::build(PlanDirection::Apply, None, &self.migrations, &applied))
+    }
+
+    /// What [`rollback`](Self::rollback) to `target_version` would undo,
+    /// without taking the lock or writing anything.
+    pub async fn plan_rollback(&self, target_version: i32) -> MigrationResult<MigrationPlan> {
+        self.validate()?;
+        let applied = self.applied_migrations().await?;
+        Ok(MigrationPlan::build(
+            PlanDirection::Rollback,
+            Some(target_version),
+            &self.migrations,
+            &applied,
+        ))
+    }
+
+    /// Check that no applied migration was edited since it was applied.
+    /// Migrations applied before checksums were recorded get theirs now.
+    pub async fn verify_checksums(&self) -> MigrationResult<()> {
+        for applied in self.applied_migrations().await? {
+            let Some(migration) = self.migrations.iter().find(|m| m.version == applied.version) else {
+                continue;
+            };
+            let actual = migration.checksum();
+            match applied.checksum {
+                Some(recorded) if recorded != actual => {
+                    self.telemetry.record_metric(
+                        "migration_checksum_mismatch".to_string(),
+                        1.0,
+                        vec![("version".to_string(), migration.version.to_string())],
+                    );
+                    return Err(MigrationError::ChecksumMismatch {
+                        version: migration.version,
+                        name: migration.name.clone(),
+                        recorded,
+                        actual,
+                    });
+                }
+                Some(_) => {}
+                None => {
+                    info!("Recording the checksum of migration {}", migration.version);
+                    self.record_checksum(migration.version, actual).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Accept the current contents of applied migrations that were edited
+    /// after being applied, returning their versions. Nothing is re-run.
+    #[instrument(name = "repair_migrations", skip(self))]
+    pub async fn repair(&self) -> MigrationResult<Vec<i32>> {
+        let guard = self.wait_for_lock().await?;
+        let result = self.repair_locked().await;
+        guard.release().await;
+        result
+    }
+
+    async fn repair_locked(&self) -> MigrationResult<Vec<i32>> {
+        let mut repaired = Vec::new();
+        for applied in self.applied_migrations().await? {
+            let Some(migration) = self.migrations.iter().find(|m| m.version == applied.version) else {
+                continue;
+            };
+            let actual = migration.checksum();
+            if applied.checksum.as_ref() != Some(&actual) {
+                info!("Accepting the current contents of migration {} - {}", migration.version, migration.name);
+                self.record_checksum(migration.version, actual).await?;
+                repaired.push(migration.version);
+            }
+        }
+        Ok(repaired)
+    }
+
+    /// Rollbacks and repairs always wait for the lock, whatever the
+    /// configured contention behavior.
+    async fn wait_for_lock(&self) -> MigrationResult<LockGuard> {
+        self.acquire_lock(LockContention::Wait)
+            .await?
+            .ok_or_else(|| MigrationError::MigrationFailed("migration lock was not acquired".to_string()))
+    }
+
+    async fn applied_migrations(&self) -> MigrationResult<Vec<AppliedMigration>> {
+        self.db.with_timeout(self.timeout, async {
+            let conn = self.db.get_connection().await?;
+            Ok(conn
+                .query("SELECT version, checksum FROM migration WHERE status != 'failed' ORDER BY version")
+                .await?
+                .take(0)?)
+        }).await
+    }
+
+    async fn record_checksum(&self, version: i32, checksum: String) -> MigrationResult<()> {
+        self.db.with_timeout(self.timeout, async {
+            let conn = self.db.get_connection().await?;
+            conn.query("UPDATE migration SET checksum = $checksum WHERE version = $version AND status != 'failed'")
+                .bind(("version", version))
+                .bind(("checksum", checksum))
+                .await?
+                .check()?;
+            Ok(())
+        }).await
+    }
+
     pub async fn get_current_version(&self) -> MigrationResult<i32> {
-        let mut response = self.db.query("SELECT version FROM migration ORDER BY version DESC LIMIT 1").await
-            .map_err(MigrationError::DatabaseError)?;
-        
-        let version = response.take::<Option<i32>>(0)
-            .map_err(MigrationError::DatabaseError)?
-            .unwrap_or(0);
-        
-        Ok(version)
+        self.db.with_timeout(self.timeout, async {
+            let conn = self.db.get_connection().await?;
+            let mut response = conn.query("SELECT version FROM migration WHERE status != 'failed' ORDER BY version DESC LIMIT 1").await
+                .map_err(MigrationError::from)?;
+
+            let version = response.take::<Option<i32>>((0, "version"))
+                .map_err(MigrationError::from)?
+                .unwrap_or(0);
+
+            Ok(version)
+        }).await
     }
 }
 
+/// [`MigrationError::LockLost`] when a commit failed its lock fence.
+fn fenced(error: DatabaseError) -> MigrationError {
+    match is_fenced(&error) {
+        true => MigrationError::LockLost,
+        false => error.into(),
+    }
+}
+
+/// Queue the history row of a successful migration on `tx`, replacing any
+/// row left by an earlier failed attempt.
+fn record_applied(
+    tx: &crate::transaction::Transaction,
+    version: i32,
+    name: String,
+    description: String,
+    checksum: String,
+    transactional: bool,
+) {
+    tx.query("DELETE migration WHERE version = $version AND status = 'failed'")
+        .bind(("version", version));
+    tx.query("CREATE migration SET version = $version, name = $name, description = $description, checksum = $checksum, transactional = $transactional, status = 'applied', applied_at = time::now()")
+        .bind(("version", version))
+        .bind(("name", name))
+        .bind(("description", description))
+        .bind(("checksum", checksum))
+        .bind(("transactional", transactional));
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use surrealdb::engine::remote::ws::Ws;
+    use crate::tests::{test_db, test_telemetry};
 
-    #[derive(Debug)]
-    struct TestConfig {
-        url: String,
-        namespace: String,
-        database: String,
-        username: String,
-        password: String,
+    async fn setup_test_migration() -> MigrationResult<MigrationManager> {
+        let db = Arc::new(test_db("test", "test").await);
+        let manager = MigrationManager::new(db, test_telemetry().await).await?;
+        
+        Ok(manager)
     }
 
-    async fn setup_test_migration() -> MigrationResult<MigrationManager> {
-        let config = TestConfig {
-            url: "ws://localhost:8000".to_string(),
-            namespace: "test".to_string(),
-            database: "test".to_string(),
-            username: "root".to_string(),
-            password: "root".to_string(),
+    #[tokio::test]
+    async fn test_fresh_database_has_no_version() {
+        let manager = setup_test_migration().await.unwrap();
+        assert_eq!(manager.get_current_version().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_migration_is_fenced_on_the_lock() {
+        let mut manager = setup_test_migration().await.unwrap();
+        // The lease runs out mid-migration and another runner takes over
+        manager.add_migration(Migration {
+            version: 1,
+            name: "slow".to_string(),
+            description: String::new(),
+            up: "CREATE item:1; UPDATE migration_lock:runner SET owner = 'intruder';".to_string(),
+            down: "DELETE item:1;".to_string(),
+            applied_at: None,
+            transactional: true,
+        });
+
+        assert!(matches!(manager.run_pending_migrations().await, Err(MigrationError::LockLost)));
+        assert_eq!(manager.get_current_version().await.unwrap(), 0);
+        let items = manager.db.execute_query("SELECT * FROM item", Default::default()).await.unwrap();
+        assert_eq!(items, serde_json::json!([[]]));
+        assert!(manager.failures().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_migration_is_not_recorded() {
+        let mut manager = setup_test_migration().await.unwrap();
+        manager.add_migration(Migration {
+            version: 1,
+            name: "broken".to_string(),
+            description: "Fails halfway through".to_string(),
+            up: "DEFINE TABLE half_done SCHEMAFULL; THROW 'boom';".to_string(),
+            down: "REMOVE TABLE half_done;".to_string(),
+            applied_at: None,
+            transactional: true,
+        });
+
+        assert!(matches!(
+            manager.run_pending_migrations().await,
+            Err(MigrationError::MigrationFailed(_))
+        ));
+        assert_eq!(manager.get_current_version().await.unwrap(), 0);
+
+        let tables = manager.db.execute_query("INFO FOR DB", Default::default()).await.unwrap();
+        assert!(tables[0]["tables"].get("half_done").is_none());
+
+        let failures = manager.failures().await.unwrap();
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].error.contains("boom"), "{}", failures[0].error);
+
+        // A fixed version replaces the failed row once it succeeds
+        let db = manager.db.clone();
+        let mut manager = MigrationManager::new(db, test_telemetry().await).await.unwrap();
+        manager.add_migration(Migration {
+            version: 1,
+            name: "fixed".to_string(),
+            description: String::new(),
+            up: "DEFINE TABLE half_done SCHEMAFULL;".to_string(),
+            down: "REMOVE TABLE half_done;".to_string(),
+            applied_at: None,
+            transactional: true,
+        });
+        manager.run_pending_migrations().await.unwrap();
+        assert_eq!(manager.get_current_version().await.unwrap(), 1);
+        assert!(manager.failures().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_non_transactional_migration_stops_at_failure() {
+        let mut manager = setup_test_migration().await.unwrap();
+        manager.add_migration(Migration {
+            version: 1,
+            name: "backfill".to_string(),
+            description: String::new(),
+            up: "CREATE item:1; THROW 'boom'; CREATE item:2;".to_string(),
+            down: "DELETE item;".to_string(),
+            applied_at: None,
+            transactional: false,
+        });
+
+        assert!(manager.run_pending_migrations().await.is_err());
+        assert_eq!(manager.get_current_version().await.unwrap(), 0);
+        let items = manager.db.execute_query("SELECT VALUE id FROM item", Default::default()).await.unwrap();
+        assert_eq!(items[0], serde_json::json!(["item:1"]));
+
+        let failures = manager.failures().await.unwrap();
+        assert!(!failures[0].transactional);
+        assert!(failures[0].error.contains("statement 2 of 3"), "{}", failures[0].error);
+    }
+
+    #[test]
+    fn test_parse_migrations() {
+        let migrations = parse_migrations([
+            ("0002_add_index.up.surql", "DEFINE INDEX by_name ON item FIELDS name;"),
+            ("0002_add_index.down.surql", "REMOVE INDEX by_name ON item;"),
+            (
+                "0001_items.up.surql",
+                "---\nname: Items\ndescription: The item table\ntransactional: false\n---\nDEFINE TABLE item;",
+            ),
+            ("0001_items.down.surql", "REMOVE TABLE item;"),
+            ("README.md", "ignored"),
+        ])
+        .unwrap();
+
+        assert_eq!(migrations.len(), 2);
+        assert_eq!((migrations[0].version, migrations[0].name.as_str()), (1, "Items"));
+        assert_eq!(migrations[0].description, "The item table");
+        assert_eq!(migrations[0].up, "DEFINE TABLE item;");
+        assert!(!migrations[0].transactional && migrations[1].transactional);
+        assert_eq!((migrations[1].version, migrations[1].name.as_str()), (2, "add index"));
+
+        let invalid = [
+            vec![("0001_items.up.surql", "")],
+            vec![("0001_items.up.surql", ""), ("0001_items.down.surql", ""), ("0001_other.up.surql", "")],
+            vec![("items.up.surql", ""), ("items.down.surql", "")],
+            vec![("0001_items.surql", "")],
+            vec![("0001_items.up.surql", "---\nowner: me\n---\n"), ("0001_items.down.surql", "")],
+        ];
+        for files in invalid {
+            assert!(
+                matches!(parse_migrations(files.clone()), Err(MigrationError::InvalidMigration(_))),
+                "{:?} should be rejected",
+                files
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_versions_must_be_contiguous() {
+        let mut manager = setup_test_migration().await.unwrap();
+        let migration = |version| Migration {
+            version,
+            name: format!("v{}", version),
+            description: String::new(),
+            up: String::new(),
+            down: String::new(),
+            applied_at: None,
+            transactional: true,
         };
 
-        let client = Surreal::new::<Ws>(&config.url).await.unwrap();
-        client.signin(surrealdb::opt::auth::Root {
-            username: &config.username,
-            password: &config.password,
-        }).await.unwrap();
-        client.use_ns(&config.namespace).use_db(&config.database).await.unwrap();
+        manager.add_migration(migration(2));
+        manager.add_migration(migration(1));
+        assert!(manager.validate().is_ok());
 
-        let telemetry_manager = TelemetryManager::init()
-            .await
-            .map_err(|e| MigrationError::MigrationFailed(e.to_string()))?;
-        let manager = MigrationManager::new(
-            Arc::new(client), 
-            Arc::new(telemetry_manager)
-        ).await?;
-        
-        Ok(manager)
+        manager.add_migration(migration(4));
+        assert!(matches!(manager.validate(), Err(MigrationError::InvalidMigration(_))));
+        assert!(manager.run_pending_migrations().await.is_err());
+        assert_eq!(manager.get_current_version().await.unwrap(), 0);
+
+        let mut manager = setup_test_migration().await.unwrap();
+        manager.add_migration(migration(1));
+        manager.add_migration(migration(1));
+        assert!(matches!(manager.validate(), Err(MigrationError::InvalidMigration(_))));
+    }
+
+    #[tokio::test]
+    async fn test_load_dir() {
+        let dir = std::env::temp_dir().join(format!("omnipro-migrations-{}", uuid::Uuid::new_v4()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        for (file, contents) in [
+            ("0001_items.up.surql", "DEFINE TABLE item SCHEMALESS;"),
+            ("0001_items.down.surql", "REMOVE TABLE item;"),
+            ("0002_seed.up.surql", "CREATE item:1 SET name = 'first';"),
+            ("0002_seed.down.surql", "DELETE item:1;"),
+        ] {
+            tokio::fs::write(dir.join(file), contents).await.unwrap();
+        }
+
+        let mut manager = setup_test_migration().await.unwrap();
+        manager.load_dir(&dir).await.unwrap();
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+        manager.run_pending_migrations().await.unwrap();
+        assert_eq!(manager.get_current_version().await.unwrap(), 2);
+
+        manager.rollback(0).await.unwrap();
+        assert_eq!(manager.get_current_version().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_rollback_skips_unapplied_migrations() {
+        let mut manager = setup_test_migration().await.unwrap();
+        manager.add_migration(Migration {
+            version: 1,
+            name: "items".to_string(),
+            description: String::new(),
+            up: "DEFINE TABLE item;".to_string(),
+            down: "REMOVE TABLE item;".to_string(),
+            applied_at: None,
+            transactional: true,
+        });
+        manager.run_pending_migrations().await.unwrap();
+
+        // Added after the last run, so its down script must not run
+        manager.add_migration(Migration {
+            version: 2,
+            name: "tags".to_string(),
+            description: String::new(),
+            up: "DEFINE TABLE tag;".to_string(),
+            down: "THROW 'never applied';".to_string(),
+            applied_at: None,
+            transactional: true,
+        });
+        let plan = manager.plan_rollback(0).await.unwrap();
+        assert_eq!(plan.migrations.iter().map(|m| m.version).collect::<Vec<_>>(), vec![1]);
+
+        manager.rollback(0).await.unwrap();
+        assert_eq!(manager.get_current_version().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_embedded_migrations() {
+        let mut manager = setup_test_migration().await.unwrap();
+        manager.load_embedded().unwrap();
+        assert!(!manager.migrations.is_empty());
+        assert_eq!(manager.migrations[0].name, "Initial schema");
+
+        manager.run_pending_migrations().await.unwrap();
+        assert_eq!(manager.get_current_version().await.unwrap(), manager.migrations.len() as i32);
+    }
+
+    #[tokio::test]
+    async fn test_embedded_migrations_after_init_schema() {
+        // Startup initializes the schema before running migrations
+        let mut manager = setup_test_migration().await.unwrap();
+        crate::schema::init_schema(&manager.db).await.unwrap();
+        manager.load_embedded().unwrap();
+
+        manager.run_pending_migrations().await.unwrap();
+        assert_eq!(manager.get_current_version().await.unwrap(), manager.migrations.len() as i32);
+    }
+
+    #[tokio::test]
+    async fn test_edited_migration_is_detected() {
+        let mut manager = setup_test_migration().await.unwrap();
+        let mut migration = Migration {
+            version: 1,
+            name: "items".to_string(),
+            description: String::new(),
+            up: "DEFINE TABLE item;".to_string(),
+            down: "REMOVE TABLE item;".to_string(),
+            applied_at: None,
+            transactional: true,
+        };
+        manager.add_migration(migration.clone());
+        manager.run_pending_migrations().await.unwrap();
+        manager.run_pending_migrations().await.unwrap();
+
+        let db = manager.db.clone();
+        let mut manager = MigrationManager::new(db.clone(), test_telemetry().await).await.unwrap();
+        migration.up = "DEFINE TABLE item SCHEMAFULL;".to_string();
+        manager.add_migration(migration.clone());
+        assert!(matches!(
+            manager.run_pending_migrations().await,
+            Err(MigrationError::ChecksumMismatch { version: 1, .. })
+        ));
+        assert!(manager.rollback(0).await.is_err());
+
+        assert_eq!(manager.repair().await.unwrap(), vec![1]);
+        assert!(manager.repair().await.unwrap().is_empty());
+        manager.run_pending_migrations().await.unwrap();
+
+        // Rows from before checksums were kept are filled in, not rejected
+        db.execute_query("UPDATE migration SET checksum = NONE", Default::default()).await.unwrap();
+        manager.run_pending_migrations().await.unwrap();
+        let rows = db.execute_query("SELECT VALUE checksum FROM migration", Default::default()).await.unwrap();
+        assert_eq!(rows[0][0], serde_json::json!(migration.checksum()));
+    }
+
+    #[tokio::test]
+    async fn test_plan_does_not_touch_the_database() {
+        let mut manager = setup_test_migration().await.unwrap();
+        manager.add_migration(Migration {
+            version: 1,
+            name: "items".to_string(),
+            description: String::new(),
+            up: "DEFINE TABLE item; CREATE item:1;".to_string(),
+            down: "REMOVE TABLE item;".to_string(),
+            applied_at: None,
+            transactional: true,
+        });
+
+        let plan = manager.plan().await.unwrap();
+        assert_eq!(plan.migrations.len(), 1);
+        assert_eq!(
+            plan.migrations[0].statements,
+            vec!["DEFINE TABLE item TYPE ANY SCHEMALESS PERMISSIONS NONE", "CREATE item:1"]
+        );
+        let info = manager.db.execute_query("INFO FOR DB", Default::default()).await.unwrap();
+        assert_eq!(info[0]["tables"], serde_json::json!({}));
+
+        manager.run_pending_migrations().await.unwrap();
+        assert!(manager.plan().await.unwrap().is_empty());
+        let plan = manager.plan_rollback(0).await.unwrap();
+        assert_eq!((plan.current_version, plan.target_version), (1, 0));
+        assert!(plan.has_warnings());
+        assert_eq!(manager.get_current_version().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_runners_apply_once() {
+        let db = Arc::new(test_db("test", "test").await);
+        let mut runners = Vec::new();
+        for _ in 0..4 {
+            let mut manager = MigrationManager::new(db.clone(), test_telemetry().await)
+                .await
+                .unwrap()
+                .with_lock(MigrationLockConfig {
+                    poll_interval_ms: 10,
+                    ..Default::default()
+                });
+            manager.add_migration(Migration {
+                version: 1,
+                name: "counter".to_string(),
+                description: String::new(),
+                // Fails if applied twice
+                up: "CREATE counter:only SET n = 1;".to_string(),
+                down: "DELETE counter:only;".to_string(),
+                applied_at: None,
+                transactional: true,
+            });
+            runners.push(tokio::spawn(async move { manager.run_pending_migrations().await }));
+        }
+        for runner in runners {
+            runner.await.unwrap().unwrap();
+        }
+
+        let rows = db.execute_query("SELECT count() FROM migration GROUP ALL", Default::default()).await.unwrap();
+        assert_eq!(rows[0][0]["count"], serde_json::json!(1));
+        let locks = db.execute_query("SELECT * FROM migration_lock", Default::default()).await.unwrap();
+        assert_eq!(locks[0], serde_json::json!([]));
     }
 }
@@ -0,0 +1,327 @@
+// Path: src/pagination.rs
+
+use crate::db::{DatabaseError, DatabaseResult};
+use crate::pool::PooledConnection;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::sync::Arc;
+use surrealdb::sql::{self, Thing};
+
+/// Largest page any list API returns, whatever the caller asks for.
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+/// Which page of a list to return. Lists are ordered newest first by
+/// `(created_at, id)`, so records inserted while paging never shift the
+/// pages that follow.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageRequest {
+    page_size: usize,
+    page_token: Option<String>,
+}
+
+impl Default for PageRequest {
+    fn default() -> Self {
+        Self::new(50)
+    }
+}
+
+impl PageRequest {
+    /// The first page, holding at most `page_size` records.
+    pub fn new(page_size: usize) -> Self {
+        Self {
+            page_size: page_size.clamp(1, MAX_PAGE_SIZE),
+            page_token: None,
+        }
+    }
+
+    /// Continue after the page that returned `token`. An empty token
+    /// starts from the beginning.
+    pub fn after(mut self, token: &str) -> Self {
+        self.page_token = (!token.is_empty()).then(|| token.to_string());
+        self
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    pub fn page_token(&self) -> Option<&str> {
+        self.page_token.as_deref()
+    }
+}
+
+/// One page of a list and the token for the next one, `None` on the last page.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_page_token: Option<String>,
+}
+
+/// Position after the last record of a page.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Cursor {
+    /// Table the cursor was issued for
+    table: String,
+    created_at: DateTime<Utc>,
+    /// Record id as `table:key`
+    id: String,
+}
+
+/// Signs page tokens so clients cannot forge positions in a list. The
+/// token itself is opaque: base64 of the cursor, a dot, and its HMAC.
+#[derive(Clone)]
+pub struct CursorSigner {
+    key: Arc<[u8]>,
+}
+
+impl std::fmt::Debug for CursorSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CursorSigner").finish_non_exhaustive()
+    }
+}
+
+impl CursorSigner {
+    pub fn new(secret: &[u8]) -> Self {
+        Self { key: secret.into() }
+    }
+
+    /// A signer with a random key. Its tokens stop working when the process
+    /// exits and are not accepted by other instances.
+    pub fn random() -> Self {
+        let mut key = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut key);
+        Self::new(&key)
+    }
+
+    /// The configured secret, or a random key when it is empty.
+    pub(crate) fn from_secret(secret: &str) -> Self {
+        match secret.is_empty() {
+            true => Self::random(),
+            false => Self::new(secret.as_bytes()),
+        }
+    }
+
+    fn mac(&self) -> Hmac<Sha256> {
+        Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts keys of any length")
+    }
+
+    fn encode(&self, cursor: &Cursor) -> String {
+        let payload = serde_json::to_vec(cursor).expect("cursors always serialize");
+        let mut mac = self.mac();
+        mac.update(&payload);
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(&payload),
+            URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+        )
+    }
+
+    fn decode(&self, token: &str, table: &str) -> DatabaseResult<Cursor> {
+        let invalid = || DatabaseError::InvalidInput("invalid page token".to_string());
+        let (payload, signature) = token.split_once('.').ok_or_else(invalid)?;
+        let payload = URL_SAFE_NO_PAD.decode(payload).map_err(|_| invalid())?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| invalid())?;
+
+        let mut mac = self.mac();
+        mac.update(&payload);
+        mac.verify_slice(&signature).map_err(|_| invalid())?;
+
+        let cursor: Cursor = serde_json::from_slice(&payload).map_err(|_| invalid())?;
+        if cursor.table != table {
+            return Err(DatabaseError::InvalidInput(format!(
+                "page token was issued for {}, not {}",
+                cursor.table, table
+            )));
+        }
+        Ok(cursor)
+    }
+}
+
+/// A newest-first keyset query over `table`, whose `created_at` must be stored
+/// as a datetime. `fields` is the projection and `conditions` are ANDed with
+/// the keyset condition; they may refer to the bound `bindings` but not to
+/// `$table`, `$cursor_at`, `$cursor_id` or `$limit`.
+pub(crate) struct PageQuery<'a> {
+    table: &'a str,
+    fields: &'a str,
+    conditions: Vec<String>,
+    bindings: Vec<(String, serde_json::Value)>,
+    timeout_clause: String,
+}
+
+impl<'a> PageQuery<'a> {
+    pub(crate) fn new(table: &'a str, fields: &'a str) -> Self {
+        Self {
+            table,
+            fields,
+            conditions: Vec::new(),
+            bindings: Vec::new(),
+            timeout_clause: String::new(),
+        }
+    }
+
+    pub(crate) fn filter(mut self, conditions: Vec<String>, bindings: Vec<(String, serde_json::Value)>) -> Self {
+        self.conditions = conditions;
+        self.bindings = bindings;
+        self
+    }
+
+    pub(crate) fn timeout_clause(mut self, clause: String) -> Self {
+        self.timeout_clause = clause;
+        self
+    }
+
+    pub(crate) async fn fetch<T>(
+        mut self,
+        conn: &PooledConnection,
+        signer: &CursorSigner,
+        page: &PageRequest,
+    ) -> DatabaseResult<Page<T>>
+    where
+        T: DeserializeOwned,
+    {
+        let cursor = page
+            .page_token
+            .as_deref()
+            .map(|token| signer.decode(token, self.table))
+            .transpose()?;
+        let after = match &cursor {
+            Some(cursor) => {
+                let id = sql::thing(&cursor.id).map_err(|_| DatabaseError::InvalidInput("invalid page token".to_string()))?;
+                Some((sql::Datetime::from(cursor.created_at), id))
+            }
+            None => None,
+        };
+
+        // Records without a creation time cannot be placed in the list
+        self.conditions.push("created_at != NONE".to_string());
+        let select = |keyset: Option<&str>| {
+            let conditions: Vec<&str> = keyset.into_iter().chain(self.conditions.iter().map(String::as_str)).collect();
+            // One extra row tells whether another page follows
+            format!(
+                "SELECT {}, created_at AS cursor_at, id AS cursor_id FROM type::table($table) WHERE {} \
+                 ORDER BY cursor_at DESC, cursor_id DESC LIMIT $limit{}",
+                self.fields,
+                conditions.join(" AND "),
+                self.timeout_clause
+            )
+        };
+        // The stored field is compared directly so the created_at index can
+        // be used. SurrealDB does not use indexes for conditions joined with
+        // OR, so records tied with the cursor and older ones are read by two
+        // range queries.
+        let query = match &after {
+            Some(_) => format!(
+                "RETURN array::slice(array::concat(({}), ({})), 0, $limit)",
+                select(Some("created_at = $cursor_at AND id < $cursor_id")),
+                select(Some("created_at < $cursor_at"))
+            ),
+            None => select(None),
+        };
+
+        let mut request = conn
+            .query(query)
+            .bind(("table", self.table.to_string()))
+            .bind(("limit", page.page_size + 1));
+        if let Some((created_at, id)) = after {
+            request = request.bind(("cursor_at", created_at)).bind(("cursor_id", id));
+        }
+        for binding in self.bindings {
+            request = request.bind(binding);
+        }
+        // Taking the keyset columns first removes them from the rows
+        let mut response = request.await?;
+        let created_at: Vec<DateTime<Utc>> = response.take((0, "cursor_at"))?;
+        let ids: Vec<Thing> = response.take((0, "cursor_id"))?;
+        let mut items: Vec<T> = response.take(0)?;
+
+        let next_page_token = match items.len() > page.page_size {
+            true => {
+                items.truncate(page.page_size);
+                let last = page.page_size - 1;
+                Some(signer.encode(&Cursor {
+                    table: self.table.to_string(),
+                    created_at: created_at[last],
+                    id: ids[last].to_string(),
+                }))
+            }
+            false => None,
+        };
+
+        Ok(Page { items, next_page_token })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cursor() -> Cursor {
+        Cursor {
+            table: "user".to_string(),
+            created_at: Utc::now(),
+            id: "user:alice".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_tokens_are_signed() {
+        let signer = CursorSigner::new(b"secret");
+        let original = cursor();
+        let token = signer.encode(&original);
+        assert_eq!(signer.decode(&token, "user").unwrap(), original);
+
+        // Another key, another table, or a tampered payload are all rejected
+        assert!(CursorSigner::new(b"other").decode(&token, "user").is_err());
+        assert!(signer.decode(&token, "model").is_err());
+
+        let (_, signature) = token.split_once('.').unwrap();
+        let mut forged = cursor();
+        forged.id = "user:zed".to_string();
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&forged).unwrap());
+        assert!(signer.decode(&format!("{}.{}", payload, signature), "user").is_err());
+        assert!(signer.decode("garbage", "user").is_err());
+    }
+
+    #[test]
+    fn test_page_request() {
+        assert_eq!(PageRequest::new(0).page_size(), 1);
+        assert_eq!(PageRequest::new(1_000_000).page_size(), MAX_PAGE_SIZE);
+        assert_eq!(PageRequest::new(10).after(""), PageRequest::new(10));
+    }
+
+    #[tokio::test]
+    async fn test_pages_through_ties() {
+        let db = crate::tests::test_db("test", "pagination_ties").await;
+        let conn = db.get_connection().await.unwrap();
+        conn.query("DEFINE INDEX item_created_at ON item FIELDS created_at")
+            .query("FOR $n IN 1..=5 { CREATE type::thing('item', $n) SET created_at = d'2024-01-01T00:00:00Z' }")
+            .query("CREATE item:0 SET created_at = d'2023-01-01T00:00:00Z'")
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+
+        let signer = CursorSigner::random();
+        let mut page = PageRequest::new(2);
+        let mut ids = Vec::new();
+        loop {
+            let result: Page<serde_json::Value> = PageQuery::new("item", "meta::id(id) AS n")
+                .fetch(&conn, &signer, &page)
+                .await
+                .unwrap();
+            ids.extend(result.items.iter().map(|item| item["n"].as_i64().unwrap()));
+            match result.next_page_token {
+                Some(token) => page = PageRequest::new(2).after(&token),
+                None => break,
+            }
+        }
+        assert_eq!(ids, [5, 4, 3, 2, 1, 0]);
+    }
+}
@@ -0,0 +1,480 @@
+// Path: src/pool.rs
+
+use crate::db::{DatabaseConfig, DatabaseError, DatabaseResult};
+use crate::reconnect::ConnectionState;
+use crate::telemetry::TelemetryManager;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+use surrealdb::engine::any::{self, Any};
+use surrealdb::Surreal;
+use tokio::sync::{Notify, OnceCell, OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, info, warn};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PoolConfig {
+    /// Connections opened up front and kept even when idle
+    pub min_size: usize,
+    /// Upper bound on connections checked out at the same time
+    pub max_size: usize,
+    /// Idle connections above `min_size` are closed after this long
+    pub idle_timeout_secs: u64,
+    /// How long `get_connection` waits for a free slot before failing
+    pub checkout_timeout_ms: u64,
+    /// Ping idle connections before handing them out
+    pub health_check_on_checkout: bool,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            min_size: 1,
+            max_size: 10,
+            idle_timeout_secs: 300,
+            checkout_timeout_ms: 5_000,
+            health_check_on_checkout: true,
+        }
+    }
+}
+
+impl PoolConfig {
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_secs(self.idle_timeout_secs)
+    }
+
+    pub fn checkout_timeout(&self) -> Duration {
+        Duration::from_millis(self.checkout_timeout_ms)
+    }
+}
+
+/// Point-in-time view of the pool, mostly useful for health reporting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStatus {
+    pub open: usize,
+    pub idle: usize,
+    pub in_use: usize,
+}
+
+struct IdleConnection {
+    client: Surreal<Any>,
+    idle_since: Instant,
+}
+
+/// A bounded pool of authenticated SurrealDB connections.
+///
+/// Embedded engines keep their datastore inside the client, so for those
+/// every pooled connection is a handle onto one shared datastore. Remote
+/// pools are watched by a supervisor task that notices a dead server and
+/// reconnects with backoff; checkouts fail fast until it succeeds.
+pub struct ConnectionPool {
+    config: DatabaseConfig,
+    idle: Mutex<VecDeque<IdleConnection>>,
+    permits: Arc<Semaphore>,
+    open: AtomicUsize,
+    state: AtomicU8,
+    wake: Arc<Notify>,
+    embedded: OnceCell<Surreal<Any>>,
+    telemetry: Option<Arc<TelemetryManager>>,
+}
+
+impl ConnectionPool {
+    pub async fn new(
+        config: DatabaseConfig,
+        telemetry: Option<Arc<TelemetryManager>>,
+    ) -> DatabaseResult<Arc<Self>> {
+        if config.pool.max_size == 0 || config.pool.min_size > config.pool.max_size {
+            return Err(DatabaseError::InvalidInput(format!(
+                "invalid pool size: min {} / max {}",
+                config.pool.min_size, config.pool.max_size
+            )));
+        }
+
+        let pool = Arc::new(Self {
+            permits: Arc::new(Semaphore::new(config.pool.max_size)),
+            idle: Mutex::new(VecDeque::with_capacity(config.pool.max_size)),
+            open: AtomicUsize::new(0),
+            state: AtomicU8::new(ConnectionState::Connected.to_u8()),
+            wake: Arc::new(Notify::new()),
+            embedded: OnceCell::new(),
+            telemetry,
+            config,
+        });
+
+        // Warm the pool, which also surfaces bad credentials at startup
+        for _ in 0..pool.config.pool.min_size {
+            let client = pool.open_connection().await?;
+            pool.release(client);
+        }
+
+        if !pool.config.engine.is_embedded() {
+            tokio::spawn(supervise(Arc::downgrade(&pool)));
+        }
+
+        Ok(pool)
+    }
+
+    /// Check out a connection, waiting up to `checkout_timeout` for a free slot.
+    pub async fn get(self: &Arc<Self>) -> DatabaseResult<PooledConnection> {
+        let started = Instant::now();
+        let timeout = self.config.pool.checkout_timeout();
+
+        let state = self.state();
+        if state != ConnectionState::Connected {
+            return Err(DatabaseError::Unavailable(format!("connection is {}", state.as_str())));
+        }
+
+        let permit = tokio::time::timeout(timeout, self.permits.clone().acquire_owned())
+            .await
+            .map_err(|_| DatabaseError::PoolExhausted(timeout))?
+            .expect("pool semaphore is never closed");
+
+        let (client, outcome) = loop {
+            match self.take_idle() {
+                Some(client) => {
+                    if self.config.pool.health_check_on_checkout && client.health().await.is_err() {
+                        warn!("Discarding unhealthy pooled connection");
+                        self.close_connection("unhealthy");
+                        self.wake.notify_one();
+                        continue;
+                    }
+                    break (client, "reused");
+                }
+                None => match self.open_connection().await {
+                    Ok(client) => break (client, "created"),
+                    Err(e) => {
+                        if e.is_retryable() {
+                            self.wake.notify_one();
+                        }
+                        return Err(e);
+                    }
+                },
+            }
+        };
+
+        self.record_metric("db_pool_checkout", vec![("outcome", outcome)]);
+        self.record_histogram("db_pool_checkout_wait_ms", started.elapsed());
+        self.record_up_down("db_pool_in_use", 1);
+
+        Ok(PooledConnection {
+            client: Some(client),
+            pool: self.clone(),
+            _permit: permit,
+        })
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        ConnectionState::from_u8(self.state.load(Ordering::SeqCst))
+    }
+
+    pub fn status(&self) -> PoolStatus {
+        let idle = self.idle.lock().unwrap().len();
+        PoolStatus {
+            open: self.open.load(Ordering::SeqCst),
+            idle,
+            in_use: self.config.pool.max_size - self.permits.available_permits(),
+        }
+    }
+
+    /// Pop the most recently used idle connection, closing stale ones on the way.
+    fn take_idle(&self) -> Option<Surreal<Any>> {
+        let mut idle = self.idle.lock().unwrap();
+        let idle_timeout = self.config.pool.idle_timeout();
+
+        while let Some(front) = idle.front() {
+            let expired = front.idle_since.elapsed() >= idle_timeout;
+            if !expired || self.open.load(Ordering::SeqCst) <= self.config.pool.min_size {
+                break;
+            }
+            idle.pop_front();
+            self.close_connection("idle_timeout");
+        }
+
+        idle.pop_back().map(|conn| conn.client)
+    }
+
+    fn release(&self, client: Surreal<Any>) {
+        self.idle.lock().unwrap().push_back(IdleConnection {
+            client,
+            idle_since: Instant::now(),
+        });
+    }
+
+    async fn open_connection(&self) -> DatabaseResult<Surreal<Any>> {
+        let client = if self.config.engine.is_embedded() {
+            self.embedded
+                .get_or_try_init(|| connect(&self.config))
+                .await?
+                .clone()
+        } else {
+            connect(&self.config).await?
+        };
+
+        self.track_opened();
+        Ok(client)
+    }
+
+    fn track_opened(&self) {
+        self.open.fetch_add(1, Ordering::SeqCst);
+        debug!("Opened pooled connection");
+        self.record_metric("db_pool_connection_opened", vec![]);
+        self.record_up_down("db_pool_connections", 1);
+    }
+
+    /// Close every idle connection, e.g. after the server went away.
+    fn drain_idle(&self) {
+        let drained: Vec<_> = self.idle.lock().unwrap().drain(..).collect();
+        for _ in drained {
+            self.close_connection("disconnected");
+        }
+    }
+
+    /// Ping the server through an idle connection, or a fresh one when
+    /// every pooled connection is checked out.
+    async fn probe(&self) -> bool {
+        match self.take_idle() {
+            Some(client) => {
+                if client.health().await.is_ok() {
+                    self.release(client);
+                    true
+                } else {
+                    self.close_connection("unhealthy");
+                    false
+                }
+            }
+            None => connect(&self.config).await.is_ok(),
+        }
+    }
+
+    fn set_state(&self, state: ConnectionState) {
+        let previous = ConnectionState::from_u8(self.state.swap(state.to_u8(), Ordering::SeqCst));
+        if previous == state {
+            return;
+        }
+
+        match state {
+            ConnectionState::Connected => info!("Database connection {} -> {}", previous.as_str(), state.as_str()),
+            _ => warn!("Database connection {} -> {}", previous.as_str(), state.as_str()),
+        }
+        self.record_metric(
+            "db_connection_state_transition",
+            vec![("from", previous.as_str()), ("to", state.as_str())],
+        );
+    }
+
+    fn close_connection(&self, reason: &str) {
+        self.open.fetch_sub(1, Ordering::SeqCst);
+        debug!("Closed pooled connection ({})", reason);
+        self.record_metric("db_pool_connection_closed", vec![("reason", reason)]);
+        self.record_up_down("db_pool_connections", -1);
+    }
+
+    fn record_metric(&self, name: &str, attributes: Vec<(&str, &str)>) {
+        if let Some(telemetry) = &self.telemetry {
+            telemetry.record_metric(
+                name.to_string(),
+                1.0,
+                attributes
+                    .into_iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            );
+        }
+    }
+
+    fn record_histogram(&self, name: &str, elapsed: Duration) {
+        if let Some(telemetry) = &self.telemetry {
+            telemetry.record_histogram(name.to_string(), elapsed.as_secs_f64() * 1000.0, vec![]);
+        }
+    }
+
+    fn record_up_down(&self, name: &str, delta: i64) {
+        if let Some(telemetry) = &self.telemetry {
+            telemetry.record_up_down(name.to_string(), delta, vec![]);
+        }
+    }
+}
+
+/// Watch a remote pool and reconnect when the server stops answering. The
+/// task only holds a weak reference, so it exits once the pool is dropped.
+async fn supervise(pool: Weak<ConnectionPool>) {
+    loop {
+        let (wake, interval) = match pool.upgrade() {
+            Some(pool) => (pool.wake.clone(), pool.config.reconnect.health_interval()),
+            None => return,
+        };
+
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = wake.notified() => {}
+        }
+
+        match pool.upgrade() {
+            Some(pool) if pool.probe().await => continue,
+            Some(_) => reconnect(&pool).await,
+            None => return,
+        }
+    }
+}
+
+async fn reconnect(pool: &Weak<ConnectionPool>) {
+    let mut attempt = 0;
+
+    loop {
+        let delay = match pool.upgrade() {
+            Some(pool) => {
+                if attempt == 0 {
+                    pool.set_state(ConnectionState::Disconnected);
+                    pool.drain_idle();
+                }
+                attempt += 1;
+                pool.config.reconnect.backoff(attempt)
+            }
+            None => return,
+        };
+
+        tokio::time::sleep(delay).await;
+
+        let Some(pool) = pool.upgrade() else { return };
+        pool.set_state(ConnectionState::Reconnecting);
+
+        match connect(&pool.config).await {
+            Ok(client) => {
+                pool.track_opened();
+                pool.release(client);
+                pool.record_metric("db_reconnect_attempt", vec![("outcome", "success")]);
+                info!("Reconnected to the database after {} attempt(s)", attempt);
+                pool.set_state(ConnectionState::Connected);
+                return;
+            }
+            Err(e) => {
+                pool.record_metric("db_reconnect_attempt", vec![("outcome", "failure")]);
+                warn!("Reconnect attempt {} failed: {}", attempt, e);
+                pool.set_state(ConnectionState::Disconnected);
+            }
+        }
+    }
+}
+
+/// Open a single connection, sign in and select the configured
+/// namespace/database. Reconnects go through here too, which is what
+/// restores the session on a fresh socket.
+async fn connect(config: &DatabaseConfig) -> DatabaseResult<Surreal<Any>> {
+    let endpoint = config.endpoint().map_err(DatabaseError::InvalidInput)?;
+    let client = any::connect(endpoint).await?;
+
+    // Embedded datastores run without authentication
+    if !config.engine.is_embedded() {
+        client
+            .signin(surrealdb::opt::auth::Root {
+                username: &config.username,
+                password: &config.password,
+            })
+            .await?;
+    }
+
+    client.use_ns(&config.namespace).use_db(&config.database).await?;
+    Ok(client)
+}
+
+/// A connection checked out of the pool. It goes back to the idle set when dropped.
+pub struct PooledConnection {
+    client: Option<Surreal<Any>>,
+    pool: Arc<ConnectionPool>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Deref for PooledConnection {
+    type Target = Surreal<Any>;
+
+    fn deref(&self) -> &Self::Target {
+        self.client.as_ref().expect("connection is present until drop")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            self.pool.release(client);
+            self.pool.record_up_down("db_pool_in_use", -1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool_config(min_size: usize, max_size: usize) -> DatabaseConfig {
+        let mut config = DatabaseConfig::in_memory("test", "test");
+        config.pool = PoolConfig {
+            min_size,
+            max_size,
+            idle_timeout_secs: 0,
+            checkout_timeout_ms: 50,
+            health_check_on_checkout: true,
+        };
+        config
+    }
+
+    #[tokio::test]
+    async fn test_connections_are_reused() {
+        let pool = ConnectionPool::new(pool_config(1, 2), None).await.unwrap();
+        assert_eq!(pool.status(), PoolStatus { open: 1, idle: 1, in_use: 0 });
+
+        let conn = pool.get().await.unwrap();
+        assert_eq!(pool.status(), PoolStatus { open: 1, idle: 0, in_use: 1 });
+        drop(conn);
+
+        let _conn = pool.get().await.unwrap();
+        assert_eq!(pool.status().open, 1);
+    }
+
+    #[tokio::test]
+    async fn test_checkout_times_out_when_exhausted() {
+        let pool = ConnectionPool::new(pool_config(0, 1), None).await.unwrap();
+        let _held = pool.get().await.unwrap();
+
+        assert!(matches!(pool.get().await, Err(DatabaseError::PoolExhausted(_))));
+    }
+
+    #[tokio::test]
+    async fn test_idle_connections_above_min_are_closed() {
+        let pool = ConnectionPool::new(pool_config(1, 3), None).await.unwrap();
+        let first = pool.get().await.unwrap();
+        let second = pool.get().await.unwrap();
+        drop(first);
+        drop(second);
+        assert_eq!(pool.status().open, 2);
+
+        // With a zero idle timeout the stale connection is closed on checkout
+        let _conn = pool.get().await.unwrap();
+        assert_eq!(pool.status().open, 1);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_server_is_retryable_and_supervised() {
+        let mut config = pool_config(0, 1);
+        config.engine = crate::db::DatabaseEngine::Ws;
+        config.url = "127.0.0.1:9".to_string();
+        config.reconnect.initial_backoff_ms = 10_000;
+
+        let pool = ConnectionPool::new(config, None).await.unwrap();
+        let error = pool.get().await.err().unwrap();
+        assert!(error.is_retryable(), "{}", error);
+
+        // The failed checkout wakes the supervisor, which marks the pool down
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(pool.state(), ConnectionState::Disconnected);
+        assert!(matches!(pool.get().await, Err(DatabaseError::Unavailable(_))));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_invalid_sizes() {
+        let result = ConnectionPool::new(pool_config(3, 2), None).await;
+        assert!(matches!(result, Err(DatabaseError::InvalidInput(_))));
+    }
+}
@@ -65,6 +65,44 @@ pub struct DeleteUserRequest {
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct DeleteUserResponse {
 }
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListUsersRequest {
+    /// 0 uses the default page size
+    #[prost(uint32, tag="1")]
+    pub page_size: u32,
+    /// next_page_token of the previous response; empty for the first page
+    #[prost(string, tag="2")]
+    pub page_token: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListUsersResponse {
+    #[prost(message, repeated, tag="1")]
+    pub users: ::prost::alloc::vec::Vec<User>,
+    /// Empty on the last page
+    #[prost(string, tag="2")]
+    pub next_page_token: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct WatchUserRequest {
+    #[prost(string, tag="1")]
+    pub id: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct UserEvent {
+    #[prost(enumeration="UserAction", tag="1")]
+    pub action: i32,
+    /// For deletes, the user as it was before
+    #[prost(message, optional, tag="2")]
+    pub user: ::core::option::Option<User>,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum UserAction {
+    Unspecified = 0,
+    Create = 1,
+    Update = 2,
+    Delete = 3,
+}
 /// Generated client implementations.
 pub mod database_service_client {
     #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
@@ -205,6 +243,49 @@ pub mod database_service_client {
             );
             self.inner.unary(request.into_request(), path, codec).await
         }
+        /// Newest first, one page at a time
+        pub async fn list_users(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ListUsersRequest>,
+        ) -> Result<tonic::Response<super::ListUsersResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/database.DatabaseService/ListUsers",
+            );
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        /// Pushes every change to the user until the client disconnects
+        pub async fn watch_user(
+            &mut self,
+            request: impl tonic::IntoRequest<super::WatchUserRequest>,
+        ) -> Result<
+            tonic::Response<tonic::codec::Streaming<super::UserEvent>>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/database.DatabaseService/WatchUser",
+            );
+            self.inner.server_streaming(request.into_request(), path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -230,6 +311,22 @@ pub mod database_service_server {
             &self,
             request: tonic::Request<super::DeleteUserRequest>,
         ) -> Result<tonic::Response<super::DeleteUserResponse>, tonic::Status>;
+        /// Newest first, one page at a time
+        async fn list_users(
+            &self,
+            request: tonic::Request<super::ListUsersRequest>,
+        ) -> Result<tonic::Response<super::ListUsersResponse>, tonic::Status>;
+        ///Server streaming response type for the WatchUser method.
+        type WatchUserStream: futures_core::Stream<
+                Item = Result<super::UserEvent, tonic::Status>,
+            >
+            + Send
+            + 'static;
+        /// Pushes every change to the user until the client disconnects
+        async fn watch_user(
+            &self,
+            request: tonic::Request<super::WatchUserRequest>,
+        ) -> Result<tonic::Response<Self::WatchUserStream>, tonic::Status>;
     }
     #[derive(Debug)]
     pub struct DatabaseServiceServer<T: DatabaseService> {
@@ -430,6 +527,83 @@ pub mod database_service_server {
                     };
                     Box::pin(fut)
                 }
+                "/database.DatabaseService/ListUsers" => {
+                    #[allow(non_camel_case_types)]
+                    struct ListUsersSvc<T: DatabaseService>(pub Arc<T>);
+                    impl<
+                        T: DatabaseService,
+                    > tonic::server::UnaryService<super::ListUsersRequest>
+                    for ListUsersSvc<T> {
+                        type Response = super::ListUsersResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ListUsersRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).list_users(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = ListUsersSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/database.DatabaseService/WatchUser" => {
+                    #[allow(non_camel_case_types)]
+                    struct WatchUserSvc<T: DatabaseService>(pub Arc<T>);
+                    impl<
+                        T: DatabaseService,
+                    > tonic::server::ServerStreamingService<super::WatchUserRequest>
+                    for WatchUserSvc<T> {
+                        type Response = super::UserEvent;
+                        type ResponseStream = T::WatchUserStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::WatchUserRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).watch_user(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = WatchUserSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         Ok(
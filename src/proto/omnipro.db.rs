@@ -20,6 +20,9 @@ pub struct QueryRequest {
     pub query: ::prost::alloc::string::String,
     #[prost(map="string, string", tag="2")]
     pub parameters: ::std::collections::HashMap<::prost::alloc::string::String, ::prost::alloc::string::String>,
+    /// Serve read-only queries from the primary instead of a replica
+    #[prost(bool, tag="3")]
+    pub read_from_primary: bool,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct QueryResponse {
@@ -38,6 +41,11 @@ pub struct CreateTableRequest {
     pub fields: ::prost::alloc::vec::Vec<FieldDefinition>,
     #[prost(message, repeated, tag="3")]
     pub indexes: ::prost::alloc::vec::Vec<IndexDefinition>,
+    /// Keep a change feed for this long; 0 disables it
+    #[prost(uint64, tag="4")]
+    pub changefeed_retention_secs: u64,
+    #[prost(bool, tag="5")]
+    pub changefeed_include_original: bool,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct CreateTableResponse {
@@ -73,11 +81,18 @@ pub struct HealthCheckResponse {
     pub healthy: bool,
     #[prost(string, tag="2")]
     pub status: ::prost::alloc::string::String,
+    /// closed | open | half_open
+    #[prost(string, tag="3")]
+    pub circuit_state: ::prost::alloc::string::String,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct BackupRequest {
+    /// Archive or directory, relative to the server's backup directory
     #[prost(string, tag="1")]
     pub path: ::prost::alloc::string::String,
+    /// Archive of an earlier backup; when set only the changes since it are backed up
+    #[prost(string, tag="2")]
+    pub base: ::prost::alloc::string::String,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct BackupResponse {
@@ -85,11 +100,29 @@ pub struct BackupResponse {
     pub success: bool,
     #[prost(string, tag="2")]
     pub error: ::prost::alloc::string::String,
+    /// Path of the written archive; differs from the request when it names a directory
+    #[prost(string, tag="3")]
+    pub archive: ::prost::alloc::string::String,
+    #[prost(uint64, tag="4")]
+    pub records: u64,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct RestoreRequest {
+    /// Full backup, relative to the server's backup directory like the incrementals
     #[prost(string, tag="1")]
     pub path: ::prost::alloc::string::String,
+    /// Restore into this namespace and database; the database is required and may
+    /// not be the one being served. An empty namespace keeps the archive's
+    #[prost(string, tag="2")]
+    pub namespace: ::prost::alloc::string::String,
+    #[prost(string, tag="3")]
+    pub database: ::prost::alloc::string::String,
+    /// Incremental backups to replay on top of the full backup at path
+    #[prost(string, repeated, tag="4")]
+    pub incrementals: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// RFC 3339; stop at the last backup taken at or before this time
+    #[prost(string, tag="5")]
+    pub until: ::prost::alloc::string::String,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct RestoreResponse {
@@ -97,6 +130,34 @@ pub struct RestoreResponse {
     pub success: bool,
     #[prost(string, tag="2")]
     pub error: ::prost::alloc::string::String,
+    #[prost(uint64, tag="3")]
+    pub records: u64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct WatchTableRequest {
+    #[prost(string, tag="1")]
+    pub table: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ChangeEvent {
+    #[prost(enumeration="ChangeAction", tag="1")]
+    pub action: i32,
+    #[prost(string, tag="2")]
+    pub table: ::prost::alloc::string::String,
+    /// Record id as table:key
+    #[prost(string, tag="3")]
+    pub id: ::prost::alloc::string::String,
+    /// The record as JSON; for deletes, as it was before
+    #[prost(string, tag="4")]
+    pub data: ::prost::alloc::string::String,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ChangeAction {
+    Unspecified = 0,
+    Create = 1,
+    Update = 2,
+    Delete = 3,
 }
 /// Generated client implementations.
 pub mod db_service_client {
@@ -276,6 +337,29 @@ pub mod db_service_client {
             );
             self.inner.unary(request.into_request(), path, codec).await
         }
+        /// Pushes every change to the table until the client disconnects
+        pub async fn watch_table(
+            &mut self,
+            request: impl tonic::IntoRequest<super::WatchTableRequest>,
+        ) -> Result<
+            tonic::Response<tonic::codec::Streaming<super::ChangeEvent>>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/omnipro.db.DbService/WatchTable",
+            );
+            self.inner.server_streaming(request.into_request(), path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -309,6 +393,17 @@ pub mod db_service_server {
             &self,
             request: tonic::Request<super::RestoreRequest>,
         ) -> Result<tonic::Response<super::RestoreResponse>, tonic::Status>;
+        ///Server streaming response type for the WatchTable method.
+        type WatchTableStream: futures_core::Stream<
+                Item = Result<super::ChangeEvent, tonic::Status>,
+            >
+            + Send
+            + 'static;
+        /// Pushes every change to the table until the client disconnects
+        async fn watch_table(
+            &self,
+            request: tonic::Request<super::WatchTableRequest>,
+        ) -> Result<tonic::Response<Self::WatchTableStream>, tonic::Status>;
     }
     #[derive(Debug)]
     pub struct DbServiceServer<T: DbService> {
@@ -583,6 +678,45 @@ pub mod db_service_server {
                     };
                     Box::pin(fut)
                 }
+                "/omnipro.db.DbService/WatchTable" => {
+                    #[allow(non_camel_case_types)]
+                    struct WatchTableSvc<T: DbService>(pub Arc<T>);
+                    impl<
+                        T: DbService,
+                    > tonic::server::ServerStreamingService<super::WatchTableRequest>
+                    for WatchTableSvc<T> {
+                        type Response = super::ChangeEvent;
+                        type ResponseStream = T::WatchTableStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::WatchTableRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).watch_table(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = WatchTableSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         Ok(
@@ -0,0 +1,697 @@
+// Path: src/query.rs
+
+use crate::db::{escape_ident, timeout_clause, DatabaseManager};
+use crate::error::{Error, Result};
+use crate::replica::ReadPreference;
+use crate::repository::{field_path, FilterOp, Order};
+use crate::sanitizer::Sanitizer;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use surrealdb::sql::{self, Thing};
+
+/// A value converted for binding, or why it could not be.
+type Bound = std::result::Result<sql::Value, String>;
+
+fn bound<V: Serialize + 'static>(value: V) -> Bound {
+    sql::to_value(value).map_err(|e| e.to_string())
+}
+
+/// What a statement operates on: every record of a table, or one record.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    Table(String),
+    Record(Thing),
+}
+
+impl From<&str> for Target {
+    fn from(table: &str) -> Self {
+        Target::Table(table.to_string())
+    }
+}
+
+impl From<String> for Target {
+    fn from(table: String) -> Self {
+        Target::Table(table)
+    }
+}
+
+impl From<Thing> for Target {
+    fn from(id: Thing) -> Self {
+        Target::Record(id)
+    }
+}
+
+impl From<&Thing> for Target {
+    fn from(id: &Thing) -> Self {
+        Target::Record(id.clone())
+    }
+}
+
+/// A WHERE condition. Conditions combine with [`Condition::and`],
+/// [`Condition::or`] and [`Condition::negate`]; values are always bound.
+#[derive(Debug, Clone)]
+pub struct Condition(Expr);
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Compare { field: String, op: FilterOp, value: Bound },
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+impl Condition {
+    pub fn new<V: Serialize + 'static>(field: &str, op: FilterOp, value: V) -> Self {
+        Condition(Expr::Compare {
+            field: field.to_string(),
+            op,
+            value: bound(value),
+        })
+    }
+
+    pub fn and(self, other: Condition) -> Self {
+        Condition(Expr::And(Box::new(self.0), Box::new(other.0)))
+    }
+
+    pub fn or(self, other: Condition) -> Self {
+        Condition(Expr::Or(Box::new(self.0), Box::new(other.0)))
+    }
+
+    pub fn negate(self) -> Self {
+        Condition(Expr::Not(Box::new(self.0)))
+    }
+}
+
+impl Expr {
+    fn render(&self, params: &mut Params) -> Result<String> {
+        Ok(match self {
+            Expr::Compare { field, op, value } => {
+                let field = field_path(&params.sanitizer, field)?;
+                format!("{} {} {}", field, op.as_surql(), params.bind(value)?)
+            }
+            Expr::And(left, right) => format!("({} AND {})", left.render(params)?, right.render(params)?),
+            Expr::Or(left, right) => format!("({} OR {})", left.render(params)?, right.render(params)?),
+            Expr::Not(inner) => format!("!({})", inner.render(params)?),
+        })
+    }
+}
+
+/// Add `condition` to an optional WHERE clause with AND.
+fn and_where(existing: Option<Condition>, condition: Condition) -> Option<Condition> {
+    Some(match existing {
+        Some(existing) => existing.and(condition),
+        None => condition,
+    })
+}
+
+/// Parameters collected while rendering, named `$p0`, `$p1`, ...
+struct Params {
+    sanitizer: Sanitizer,
+    values: Vec<(String, sql::Value)>,
+    /// Tables named by the statement
+    tables: Vec<String>,
+}
+
+impl Params {
+    fn new() -> Self {
+        Self {
+            sanitizer: Sanitizer::new(),
+            values: Vec::new(),
+            tables: Vec::new(),
+        }
+    }
+
+    fn bind(&mut self, value: &Bound) -> Result<String> {
+        let value = value
+            .clone()
+            .map_err(|e| Error::InvalidInput(format!("cannot bind value: {}", e)))?;
+        let name = format!("p{}", self.values.len());
+        let placeholder = format!("${}", name);
+        self.values.push((name, value));
+        Ok(placeholder)
+    }
+
+    fn target(&mut self, target: &Target) -> Result<String> {
+        match target {
+            Target::Table(table) => self.table(table),
+            Target::Record(id) => {
+                self.table(&id.tb)?;
+                self.bind(&Ok(sql::Value::from(id.clone())))
+            }
+        }
+    }
+
+    fn table(&mut self, table: &str) -> Result<String> {
+        let table = self
+            .sanitizer
+            .sanitize_name(table)
+            .map_err(|e| Error::InvalidInput(format!("{}: {}", table, e)))?;
+        let escaped = escape_ident(&table);
+        if !self.tables.contains(&table) {
+            self.tables.push(table);
+        }
+        Ok(escaped)
+    }
+
+    fn fields(&self, fields: &[String]) -> Result<String> {
+        Ok(fields
+            .iter()
+            .map(|field| field_path(&self.sanitizer, field))
+            .collect::<Result<Vec<_>>>()?
+            .join(", "))
+    }
+
+    fn condition(&mut self, condition: &Option<Condition>) -> Result<String> {
+        match condition {
+            Some(condition) => Ok(format!(" WHERE {}", condition.0.render(self)?)),
+            None => Ok(String::new()),
+        }
+    }
+
+    fn finish(self, text: String, read_only: bool) -> Query {
+        Query {
+            text,
+            params: self.values,
+            tables: self.tables,
+            read_only,
+        }
+    }
+}
+
+/// A rendered statement and the values it binds.
+#[derive(Debug, Clone)]
+pub struct Query {
+    text: String,
+    params: Vec<(String, sql::Value)>,
+    tables: Vec<String>,
+    read_only: bool,
+}
+
+impl Query {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn params(&self) -> &[(String, sql::Value)] {
+        &self.params
+    }
+
+    /// Run the statement with the configured query timeout. SELECTs read
+    /// from a replica when one is available; everything else goes to the
+    /// primary.
+    pub async fn execute<T>(&self, db: &DatabaseManager) -> Result<Vec<T>>
+    where
+        T: DeserializeOwned,
+    {
+        self.run(db, |mut response| Ok(response.take(0)?)).await
+    }
+
+    /// Like [`Query::execute`], with records as JSON and record ids as
+    /// `table:key` strings.
+    pub async fn execute_json(&self, db: &DatabaseManager) -> Result<Vec<serde_json::Value>> {
+        self.run(db, |mut response| {
+            let value: surrealdb::Value = response.take(0)?;
+            match value.into_inner().into_json() {
+                serde_json::Value::Array(records) => Ok(records),
+                serde_json::Value::Null => Ok(Vec::new()),
+                record => Ok(vec![record]),
+            }
+        })
+        .await
+    }
+
+    async fn run<T>(&self, db: &DatabaseManager, take: impl FnOnce(surrealdb::Response) -> Result<T>) -> Result<T> {
+        let text = format!("{}{}", self.text, timeout_clause(db.query_timeout()));
+        let result = db.with_timeout(None, async {
+            let conn = if self.read_only {
+                db.get_read_connection(ReadPreference::default()).await?
+            } else {
+                db.get_connection().await?
+            };
+            let mut request = conn.query(text);
+            for (name, value) in &self.params {
+                request = request.bind((name.clone(), value.clone()));
+            }
+
+            take(request.await?)
+        })
+        .await;
+
+        if !self.read_only {
+            let tables: Vec<&str> = self.tables.iter().map(String::as_str).collect();
+            db.invalidate_cache(&tables);
+        }
+        result
+    }
+}
+
+/// `SELECT` builder.
+#[derive(Debug, Clone)]
+pub struct Select {
+    target: Target,
+    fields: Vec<String>,
+    condition: Option<Condition>,
+    split: Vec<String>,
+    order_by: Vec<(String, Order)>,
+    limit: Option<usize>,
+    start: Option<usize>,
+    fetch: Vec<String>,
+}
+
+impl Select {
+    pub fn new(target: impl Into<Target>) -> Self {
+        Self {
+            target: target.into(),
+            fields: Vec::new(),
+            condition: None,
+            split: Vec::new(),
+            order_by: Vec::new(),
+            limit: None,
+            start: None,
+            fetch: Vec::new(),
+        }
+    }
+
+    /// Fields to return; all of them by default.
+    pub fn fields(mut self, fields: &[&str]) -> Self {
+        self.fields.extend(fields.iter().map(|f| f.to_string()));
+        self
+    }
+
+    pub fn filter<V: Serialize + 'static>(self, field: &str, op: FilterOp, value: V) -> Self {
+        self.condition(Condition::new(field, op, value))
+    }
+
+    /// Add a condition, combined with any earlier ones by AND.
+    pub fn condition(mut self, condition: Condition) -> Self {
+        self.condition = and_where(self.condition, condition);
+        self
+    }
+
+    /// Return one row per element of the array `field`.
+    pub fn split(mut self, field: &str) -> Self {
+        self.split.push(field.to_string());
+        self
+    }
+
+    pub fn order_by(mut self, field: &str, order: Order) -> Self {
+        self.order_by.push((field.to_string(), order));
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn start(mut self, start: usize) -> Self {
+        self.start = Some(start);
+        self
+    }
+
+    /// Replace the record ids in `field` with the records they point to.
+    pub fn fetch(mut self, field: &str) -> Self {
+        self.fetch.push(field.to_string());
+        self
+    }
+
+    pub fn build(&self) -> Result<Query> {
+        let mut params = Params::new();
+        let fields = match self.fields.is_empty() {
+            true => "*".to_string(),
+            false => params.fields(&self.fields)?,
+        };
+        let mut text = format!("SELECT {} FROM {}", fields, params.target(&self.target)?);
+        text.push_str(&params.condition(&self.condition)?);
+        if !self.split.is_empty() {
+            text.push_str(&format!(" SPLIT {}", params.fields(&self.split)?));
+        }
+        if !self.order_by.is_empty() {
+            let order = self
+                .order_by
+                .iter()
+                .map(|(field, order)| {
+                    field_path(&params.sanitizer, field).map(|field| format!("{} {}", field, order.as_surql()))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            text.push_str(&format!(" ORDER BY {}", order.join(", ")));
+        }
+        if let Some(limit) = self.limit {
+            text.push_str(&format!(" LIMIT {}", params.bind(&bound(limit))?));
+        }
+        if let Some(start) = self.start {
+            text.push_str(&format!(" START {}", params.bind(&bound(start))?));
+        }
+        if !self.fetch.is_empty() {
+            text.push_str(&format!(" FETCH {}", params.fields(&self.fetch)?));
+        }
+        Ok(params.finish(text, true))
+    }
+}
+
+/// The data clause of a write statement.
+#[derive(Debug, Clone, Default)]
+struct Data {
+    /// `CONTENT` or `MERGE` with the whole value
+    document: Option<(&'static str, Bound)>,
+    set: Vec<(String, Bound)>,
+}
+
+impl Data {
+    fn render(&self, params: &mut Params) -> Result<String> {
+        match (&self.document, self.set.is_empty()) {
+            (Some(_), false) => Err(Error::InvalidInput("SET cannot be combined with CONTENT or MERGE".to_string())),
+            (Some((clause, value)), true) => Ok(format!(" {} {}", clause, params.bind(value)?)),
+            (None, false) => {
+                let assignments = self
+                    .set
+                    .iter()
+                    .map(|(field, value)| Ok(format!("{} = {}", field_path(&params.sanitizer, field)?, params.bind(value)?)))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(format!(" SET {}", assignments.join(", ")))
+            }
+            (None, true) => Ok(String::new()),
+        }
+    }
+}
+
+/// `CREATE` builder.
+#[derive(Debug, Clone)]
+pub struct Create {
+    target: Target,
+    data: Data,
+}
+
+impl Create {
+    pub fn new(target: impl Into<Target>) -> Self {
+        Self {
+            target: target.into(),
+            data: Data::default(),
+        }
+    }
+
+    pub fn content<V: Serialize + 'static>(mut self, content: V) -> Self {
+        self.data.document = Some(("CONTENT", bound(content)));
+        self
+    }
+
+    pub fn set<V: Serialize + 'static>(mut self, field: &str, value: V) -> Self {
+        self.data.set.push((field.to_string(), bound(value)));
+        self
+    }
+
+    pub fn build(&self) -> Result<Query> {
+        let mut params = Params::new();
+        let mut text = format!("CREATE {}", params.target(&self.target)?);
+        text.push_str(&self.data.render(&mut params)?);
+        Ok(params.finish(text, false))
+    }
+}
+
+/// `UPDATE` builder; [`Update::upsert`] builds an `UPSERT`, which also
+/// creates the record when it does not exist.
+#[derive(Debug, Clone)]
+pub struct Update {
+    statement: &'static str,
+    target: Target,
+    data: Data,
+    condition: Option<Condition>,
+}
+
+impl Update {
+    pub fn new(target: impl Into<Target>) -> Self {
+        Self {
+            statement: "UPDATE",
+            target: target.into(),
+            data: Data::default(),
+            condition: None,
+        }
+    }
+
+    pub fn upsert(target: impl Into<Target>) -> Self {
+        Self {
+            statement: "UPSERT",
+            ..Self::new(target)
+        }
+    }
+
+    /// Replace every field.
+    pub fn content<V: Serialize + 'static>(mut self, content: V) -> Self {
+        self.data.document = Some(("CONTENT", bound(content)));
+        self
+    }
+
+    /// Update only the fields present in `patch`.
+    pub fn merge<V: Serialize + 'static>(mut self, patch: V) -> Self {
+        self.data.document = Some(("MERGE", bound(patch)));
+        self
+    }
+
+    pub fn set<V: Serialize + 'static>(mut self, field: &str, value: V) -> Self {
+        self.data.set.push((field.to_string(), bound(value)));
+        self
+    }
+
+    pub fn filter<V: Serialize + 'static>(self, field: &str, op: FilterOp, value: V) -> Self {
+        self.condition(Condition::new(field, op, value))
+    }
+
+    pub fn condition(mut self, condition: Condition) -> Self {
+        self.condition = and_where(self.condition, condition);
+        self
+    }
+
+    pub fn build(&self) -> Result<Query> {
+        let mut params = Params::new();
+        let mut text = format!("{} {}", self.statement, params.target(&self.target)?);
+        text.push_str(&self.data.render(&mut params)?);
+        text.push_str(&params.condition(&self.condition)?);
+        Ok(params.finish(text, false))
+    }
+}
+
+/// `DELETE` builder. The deleted records are returned as they were.
+#[derive(Debug, Clone)]
+pub struct Delete {
+    target: Target,
+    condition: Option<Condition>,
+}
+
+impl Delete {
+    pub fn new(target: impl Into<Target>) -> Self {
+        Self {
+            target: target.into(),
+            condition: None,
+        }
+    }
+
+    pub fn filter<V: Serialize + 'static>(self, field: &str, op: FilterOp, value: V) -> Self {
+        self.condition(Condition::new(field, op, value))
+    }
+
+    pub fn condition(mut self, condition: Condition) -> Self {
+        self.condition = and_where(self.condition, condition);
+        self
+    }
+
+    pub fn build(&self) -> Result<Query> {
+        let mut params = Params::new();
+        let mut text = format!("DELETE {}", params.target(&self.target)?);
+        text.push_str(&params.condition(&self.condition)?);
+        text.push_str(" RETURN BEFORE");
+        Ok(params.finish(text, false))
+    }
+}
+
+/// `RELATE` builder, creating an edge record in `edge` from one record to
+/// another.
+#[derive(Debug, Clone)]
+pub struct Relate {
+    from: Thing,
+    edge: String,
+    to: Thing,
+    data: Data,
+}
+
+impl Relate {
+    pub fn new(from: &Thing, edge: &str, to: &Thing) -> Self {
+        Self {
+            from: from.clone(),
+            edge: edge.to_string(),
+            to: to.clone(),
+            data: Data::default(),
+        }
+    }
+
+    pub fn content<V: Serialize + 'static>(mut self, content: V) -> Self {
+        self.data.document = Some(("CONTENT", bound(content)));
+        self
+    }
+
+    pub fn set<V: Serialize + 'static>(mut self, field: &str, value: V) -> Self {
+        self.data.set.push((field.to_string(), bound(value)));
+        self
+    }
+
+    pub fn build(&self) -> Result<Query> {
+        let mut params = Params::new();
+        let from = params.target(&Target::Record(self.from.clone()))?;
+        let edge = params.table(&self.edge)?;
+        let to = params.target(&Target::Record(self.to.clone()))?;
+        let mut text = format!("RELATE {}->{}->{}", from, edge, to);
+        text.push_str(&self.data.render(&mut params)?);
+        Ok(params.finish(text, false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_db;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[test]
+    fn test_select_rendering() {
+        let query = Select::new("product")
+            .fields(&["name", "price"])
+            .filter("price", FilterOp::Gte, 10)
+            .condition(Condition::new("name", FilterOp::Eq, "lamp").or(Condition::new("tags", FilterOp::Contains, "x").negate()))
+            .order_by("price", Order::Desc)
+            .limit(5)
+            .start(10)
+            .fetch("maker")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            query.text(),
+            "SELECT `name`, `price` FROM `product` WHERE (`price` >= $p0 AND (`name` = $p1 OR !(`tags` CONTAINS $p2))) \
+             ORDER BY `price` DESC LIMIT $p3 START $p4 FETCH `maker`"
+        );
+        let names: Vec<_> = query.params().iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["p0", "p1", "p2", "p3", "p4"]);
+        assert_eq!(query.params()[1].1, sql::Value::from("lamp"));
+    }
+
+    #[test]
+    fn test_rejects_injection() {
+        let attempts = [
+            Select::new("product; REMOVE TABLE user").build(),
+            Select::new("product").fields(&["name FROM user"]).build(),
+            Select::new("product").order_by("price; REMOVE TABLE product", Order::Asc).build(),
+            Update::new("product").set("price = 0, admin", true).build(),
+            Delete::new("product").filter("1 = 1 OR name", FilterOp::Eq, "x").build(),
+            Relate::new(&Thing::from(("user", "a")), "likes`->", &Thing::from(("product", "b"))).build(),
+            Update::new("product").content(json!({})).set("price", 1).build(),
+        ];
+        for attempt in attempts {
+            assert!(matches!(attempt, Err(Error::InvalidInput(_))), "{:?}", attempt);
+        }
+
+        // Values never reach the text
+        let query = Select::new("product").filter("name", FilterOp::Eq, "'; REMOVE TABLE product; --").build().unwrap();
+        assert!(!query.text().contains("REMOVE"));
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Product {
+        name: String,
+        price: i64,
+        tags: Vec<String>,
+    }
+
+    #[tokio::test]
+    async fn test_statements() {
+        let db = test_db("test", "query").await;
+        let lamp = Thing::from(("product", "lamp"));
+        let created: Vec<Product> = Create::new(&lamp)
+            .content(json!({ "name": "lamp", "price": 30, "tags": ["home", "light"] }))
+            .build()
+            .unwrap()
+            .execute(&db)
+            .await
+            .unwrap();
+        assert_eq!(created[0].price, 30);
+
+        Create::new("product")
+            .set("name", "desk")
+            .set("price", 120)
+            .set("tags", vec!["home"])
+            .build()
+            .unwrap()
+            .execute_json(&db)
+            .await
+            .unwrap();
+
+        let updated: Vec<Product> = Update::new("product")
+            .merge(json!({ "on_sale": true }))
+            .filter("price", FilterOp::Lt, 100)
+            .build()
+            .unwrap()
+            .execute(&db)
+            .await
+            .unwrap();
+        assert_eq!(updated.len(), 1);
+
+        let upserted: Vec<Product> = Update::upsert(Thing::from(("product", "chair")))
+            .content(json!({ "name": "chair", "price": 80, "tags": [] }))
+            .build()
+            .unwrap()
+            .execute(&db)
+            .await
+            .unwrap();
+        assert_eq!(upserted[0].name, "chair");
+
+        let user = Thing::from(("user", "ann"));
+        Create::new(&user).set("name", "Ann").build().unwrap().execute_json(&db).await.unwrap();
+        Relate::new(&user, "likes", &lamp)
+            .set("since", sql::Datetime::default())
+            .build()
+            .unwrap()
+            .execute_json(&db)
+            .await
+            .unwrap();
+        let liked = Select::new("likes")
+            .fields(&["out"])
+            .filter("in", FilterOp::Eq, user.clone())
+            .fetch("out")
+            .build()
+            .unwrap()
+            .execute_json(&db)
+            .await
+            .unwrap();
+        assert_eq!(liked[0]["out"]["name"], "lamp");
+
+        let tags = Select::new("product")
+            .fields(&["tags"])
+            .split("tags")
+            .order_by("tags", Order::Asc)
+            .build()
+            .unwrap()
+            .execute_json(&db)
+            .await
+            .unwrap();
+        assert_eq!(tags, [json!({ "tags": "home" }), json!({ "tags": "home" }), json!({ "tags": "light" })]);
+
+        let deleted: Vec<Product> = Delete::new("product")
+            .filter("tags", FilterOp::Contains, "light")
+            .build()
+            .unwrap()
+            .execute(&db)
+            .await
+            .unwrap();
+        assert_eq!(deleted, created);
+
+        let page: Vec<Product> = Select::new("product")
+            .order_by("price", Order::Asc)
+            .limit(1)
+            .start(1)
+            .build()
+            .unwrap()
+            .execute(&db)
+            .await
+            .unwrap();
+        assert_eq!(page[0].name, "desk");
+    }
+}
@@ -0,0 +1,132 @@
+// Path: src/reconnect.rs
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReconnectConfig {
+    /// Delay before the first reconnect attempt
+    pub initial_backoff_ms: u64,
+    /// Upper bound for the delay between attempts
+    pub max_backoff_ms: u64,
+    /// Growth factor applied to the delay after every failed attempt
+    pub multiplier: f64,
+    /// Fraction of each delay that is randomised, between 0.0 and 1.0
+    pub jitter: f64,
+    /// How often the supervisor pings the server while connected
+    pub health_interval_ms: u64,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            initial_backoff_ms: 100,
+            max_backoff_ms: 30_000,
+            multiplier: 2.0,
+            jitter: 0.2,
+            health_interval_ms: 5_000,
+        }
+    }
+}
+
+impl ReconnectConfig {
+    pub fn health_interval(&self) -> Duration {
+        Duration::from_millis(self.health_interval_ms)
+    }
+
+    /// Delay before reconnect attempt `attempt` (starting at 1). The
+    /// exponential delay is capped, then up to `jitter` of it is shaved off
+    /// at random so that many clients do not reconnect in lockstep.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(63) as i32;
+        let base = (self.initial_backoff_ms as f64 * self.multiplier.powi(exponent))
+            .min(self.max_backoff_ms as f64);
+
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = 1.0 - jitter * rand::thread_rng().gen::<f64>();
+
+        Duration::from_millis((base * factor) as u64)
+    }
+}
+
+/// Connection state tracked by the pool supervisor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connected,
+    /// The server stopped answering; waiting out the backoff before retrying
+    Disconnected,
+    /// A reconnect attempt is in flight
+    Reconnecting,
+}
+
+impl ConnectionState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ConnectionState::Connected => "connected",
+            ConnectionState::Disconnected => "disconnected",
+            ConnectionState::Reconnecting => "reconnecting",
+        }
+    }
+
+    pub(crate) fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    pub(crate) fn from_u8(value: u8) -> Self {
+        match value {
+            0 => ConnectionState::Connected,
+            1 => ConnectionState::Disconnected,
+            _ => ConnectionState::Reconnecting,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_and_is_capped() {
+        let config = ReconnectConfig {
+            initial_backoff_ms: 100,
+            max_backoff_ms: 1_000,
+            multiplier: 2.0,
+            jitter: 0.0,
+            ..Default::default()
+        };
+
+        assert_eq!(config.backoff(1), Duration::from_millis(100));
+        assert_eq!(config.backoff(2), Duration::from_millis(200));
+        assert_eq!(config.backoff(4), Duration::from_millis(800));
+        assert_eq!(config.backoff(5), Duration::from_millis(1_000));
+        assert_eq!(config.backoff(500), Duration::from_millis(1_000));
+    }
+
+    #[test]
+    fn test_backoff_jitter_stays_in_range() {
+        let config = ReconnectConfig {
+            initial_backoff_ms: 1_000,
+            jitter: 0.5,
+            ..Default::default()
+        };
+
+        for _ in 0..100 {
+            let delay = config.backoff(1);
+            assert!(delay >= Duration::from_millis(500));
+            assert!(delay <= Duration::from_millis(1_000));
+        }
+    }
+
+    #[test]
+    fn test_state_round_trips() {
+        for state in [
+            ConnectionState::Connected,
+            ConnectionState::Disconnected,
+            ConnectionState::Reconnecting,
+        ] {
+            assert_eq!(ConnectionState::from_u8(state.to_u8()), state);
+        }
+    }
+}
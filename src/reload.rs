@@ -0,0 +1,272 @@
+// Path: src/reload.rs
+
+use crate::anomaly_detection::AnomalyDetector;
+use crate::config::{AppConfig, ConfigError, ConfigResult};
+use crate::sanitizer::Sanitizer;
+use crate::security::SecurityManager;
+use crate::telemetry::TelemetryManager;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReloadConfig {
+    /// Poll the config file for changes. SIGHUP triggers a reload either way.
+    pub watch: bool,
+    /// How often the file modification time is checked
+    pub poll_interval_ms: u64,
+}
+
+impl Default for ReloadConfig {
+    fn default() -> Self {
+        Self {
+            watch: true,
+            poll_interval_ms: 2_000,
+        }
+    }
+}
+
+impl ReloadConfig {
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.poll_interval_ms)
+    }
+}
+
+/// Re-reads the config file and applies the settings that can change
+/// without a restart: the log filter, security policy, sanitizer blocked
+/// patterns and anomaly thresholds. Database and server settings are only
+/// read at startup; changes to them are logged and ignored.
+pub struct ConfigReloader {
+    path: PathBuf,
+    current: RwLock<Arc<AppConfig>>,
+    // Serializes reloads so two triggers never interleave their swaps
+    reloading: tokio::sync::Mutex<()>,
+    telemetry: Arc<TelemetryManager>,
+    security: Arc<SecurityManager>,
+    sanitizer: Sanitizer,
+    anomaly_detector: Arc<Mutex<AnomalyDetector>>,
+}
+
+impl ConfigReloader {
+    pub fn new(
+        path: impl Into<PathBuf>,
+        config: AppConfig,
+        telemetry: Arc<TelemetryManager>,
+        security: Arc<SecurityManager>,
+        sanitizer: Sanitizer,
+        anomaly_detector: Arc<Mutex<AnomalyDetector>>,
+    ) -> Self {
+        Self {
+            path: path.into(),
+            current: RwLock::new(Arc::new(config)),
+            reloading: tokio::sync::Mutex::new(()),
+            telemetry,
+            security,
+            sanitizer,
+            anomaly_detector,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The configuration currently in effect.
+    pub fn current(&self) -> Arc<AppConfig> {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Load, validate and apply the config file. On any error the running
+    /// configuration is left untouched.
+    pub async fn reload(&self) -> ConfigResult<()> {
+        let _guard = self.reloading.lock().await;
+
+        let result = self.load_and_apply();
+        let outcome = if result.is_ok() { "applied" } else { "rejected" };
+        self.telemetry.record_metric(
+            "config_reload".to_string(),
+            1.0,
+            vec![("outcome".to_string(), outcome.to_string())],
+        );
+
+        match &result {
+            Ok(()) => info!("Configuration reloaded from {}", self.path.display()),
+            Err(e) => warn!("Rejected configuration reload, keeping previous settings: {}", e),
+        }
+        result
+    }
+
+    fn load_and_apply(&self) -> ConfigResult<()> {
+        let mut config = AppConfig::load(Some(&self.path))?;
+        let current = self.current();
+
+        if !same(&config.database, &current.database) || !same(&config.server, &current.server) {
+            warn!("Database and server settings changed; they take effect after a restart");
+        }
+        config.database = current.database.clone();
+        config.server = current.server.clone();
+        config.reload = current.reload.clone();
+
+        // The log filter is the only step that can still fail, so it goes first
+        self.telemetry
+            .set_log_filter(&config.telemetry.log_filter)
+            .map_err(|e| ConfigError::Invalid(vec![format!("telemetry.log_filter: {}", e)]))?;
+        self.security.reload(&config.security);
+        self.sanitizer.set_blocked_patterns(&config.security.blocked_patterns);
+        self.anomaly_detector
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .apply_config(&config.anomaly_detection);
+
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(config);
+        Ok(())
+    }
+
+    /// Reload on SIGHUP and, if `watch` is enabled, whenever the file's
+    /// modification time changes.
+    pub fn spawn(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let reload = self.current().reload.clone();
+            let mut last_modified = modified(&self.path);
+            let mut poll = tokio::time::interval(reload.poll_interval());
+            let mut hangup = Hangup::new();
+
+            loop {
+                tokio::select! {
+                    _ = poll.tick(), if reload.watch => {
+                        let modified = modified(&self.path);
+                        if modified == last_modified {
+                            continue;
+                        }
+                        last_modified = modified;
+                    }
+                    _ = hangup.recv() => info!("Received SIGHUP"),
+                }
+
+                let _ = self.reload().await;
+            }
+        })
+    }
+}
+
+fn same<T: Serialize>(a: &T, b: &T) -> bool {
+    serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(unix)]
+struct Hangup(Option<tokio::signal::unix::Signal>);
+
+#[cfg(unix)]
+impl Hangup {
+    fn new() -> Self {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::hangup()) {
+            Ok(signal) => Self(Some(signal)),
+            Err(e) => {
+                warn!("Cannot listen for SIGHUP: {}", e);
+                Self(None)
+            }
+        }
+    }
+
+    async fn recv(&mut self) {
+        match &mut self.0 {
+            Some(signal) => {
+                signal.recv().await;
+            }
+            None => std::future::pending().await,
+        }
+    }
+}
+
+#[cfg(not(unix))]
+struct Hangup;
+
+#[cfg(not(unix))]
+impl Hangup {
+    fn new() -> Self {
+        Hangup
+    }
+
+    async fn recv(&mut self) {
+        std::future::pending().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_telemetry;
+
+    async fn reloader(path: &Path) -> ConfigReloader {
+        let config = AppConfig::load(Some(path)).unwrap();
+        ConfigReloader::new(
+            path,
+            config.clone(),
+            test_telemetry().await,
+            Arc::new(SecurityManager::with_config(&config.security)),
+            Sanitizer::with_blocked_patterns(&config.security.blocked_patterns),
+            Arc::new(Mutex::new(AnomalyDetector::from_config(&config.anomaly_detection))),
+        )
+    }
+
+    fn temp_config(contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-config.yaml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn test_reload_applies_new_policy() {
+        let path = temp_config("security:\n  blocked_patterns: [DROP]\n");
+        let reloader = reloader(&path).await;
+        assert!(reloader.sanitizer.sanitize_input("delete").is_ok());
+
+        std::fs::write(
+            &path,
+            "security:\n  blocked_patterns: [DELETE]\n  allowed_roles: [admin]\nanomaly_detection:\n  window_size: 2\n",
+        )
+        .unwrap();
+        reloader.reload().await.unwrap();
+
+        assert!(reloader.sanitizer.sanitize_input("delete").is_err());
+        assert!(reloader.sanitizer.sanitize_input("drop").is_ok());
+        assert!(!reloader.security.is_valid_role("user"));
+        assert_eq!(reloader.current().anomaly_detection.window_size, 2);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_reload_keeps_previous_config() {
+        let path = temp_config("security:\n  allowed_roles: [admin, user]\n");
+        let reloader = reloader(&path).await;
+
+        std::fs::write(&path, "security:\n  allowed_roles: []\n").unwrap();
+        assert!(matches!(reloader.reload().await, Err(ConfigError::Invalid(_))));
+
+        std::fs::write(&path, "security: [not, a, map").unwrap();
+        assert!(matches!(reloader.reload().await, Err(ConfigError::Parse { .. })));
+
+        assert!(reloader.security.is_valid_role("user"));
+        assert_eq!(reloader.current().security.allowed_roles.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_connection_settings_are_not_reloaded() {
+        let path = temp_config("database:\n  namespace: before\n");
+        let reloader = reloader(&path).await;
+
+        std::fs::write(&path, "database:\n  namespace: after\n").unwrap();
+        reloader.reload().await.unwrap();
+
+        assert_eq!(reloader.current().database.namespace, "before");
+    }
+}
@@ -0,0 +1,410 @@
+// Path: src/replica.rs
+
+use crate::db::{DatabaseConfig, DatabaseResult};
+use crate::pool::{ConnectionPool, PoolConfig};
+use crate::reconnect::ConnectionState;
+use crate::telemetry::TelemetryManager;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Instant;
+use tracing::{debug, warn};
+
+/// How reads are spread over the healthy replicas.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadRouting {
+    #[default]
+    RoundRobin,
+    /// The replica with the lowest recent ping time
+    LeastLatency,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReplicaConfig {
+    /// Read-only endpoints. They share the engine, credentials, namespace
+    /// and database with the primary `url`.
+    pub urls: Vec<String>,
+    pub routing: ReadRouting,
+}
+
+/// Where a read may be served from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReadPreference {
+    /// Any healthy replica, falling back to the primary
+    #[default]
+    Replica,
+    /// Always the primary, for reads that must see the caller's own writes
+    Primary,
+}
+
+struct Replica {
+    url: String,
+    pool: Arc<ConnectionPool>,
+    /// Smoothed ping time in microseconds, `u64::MAX` until first measured
+    latency_us: AtomicU64,
+}
+
+/// The replica pools behind a [`DatabaseManager`](crate::db::DatabaseManager).
+pub(crate) struct ReplicaSet {
+    replicas: Vec<Replica>,
+    routing: ReadRouting,
+    next: AtomicUsize,
+    telemetry: Option<Arc<TelemetryManager>>,
+}
+
+impl ReplicaSet {
+    /// Open a pool per replica. A replica that is down at startup does not
+    /// fail the manager: its pool starts empty and is marked disconnected
+    /// by the supervisor until the server comes back.
+    pub(crate) async fn new(
+        config: &DatabaseConfig,
+        telemetry: Option<Arc<TelemetryManager>>,
+    ) -> DatabaseResult<Arc<Self>> {
+        let mut replicas = Vec::with_capacity(config.replicas.urls.len());
+        for url in &config.replicas.urls {
+            let mut replica_config = config.clone();
+            replica_config.url = url.clone();
+            replica_config.replicas = ReplicaConfig::default();
+
+            let pool = match ConnectionPool::new(replica_config.clone(), telemetry.clone()).await {
+                Ok(pool) => pool,
+                Err(e) if e.is_retryable() => {
+                    warn!("Replica {} is unreachable, routing reads elsewhere: {}", url, e);
+                    replica_config.pool = PoolConfig {
+                        min_size: 0,
+                        ..replica_config.pool
+                    };
+                    ConnectionPool::new(replica_config, telemetry.clone()).await?
+                }
+                Err(e) => return Err(e),
+            };
+
+            replicas.push(Replica {
+                url: url.clone(),
+                pool,
+                latency_us: AtomicU64::new(u64::MAX),
+            });
+        }
+
+        let set = Arc::new(Self {
+            replicas,
+            routing: config.replicas.routing,
+            next: AtomicUsize::new(0),
+            telemetry,
+        });
+
+        if set.routing == ReadRouting::LeastLatency && !set.replicas.is_empty() {
+            tokio::spawn(measure_latency(
+                Arc::downgrade(&set),
+                config.reconnect.health_interval(),
+            ));
+        }
+
+        Ok(set)
+    }
+
+    /// The replica pool to serve the next read, or `None` when no replica
+    /// is connected.
+    pub(crate) fn pick(&self) -> Option<&Arc<ConnectionPool>> {
+        let healthy: Vec<&Replica> = self
+            .replicas
+            .iter()
+            .filter(|replica| replica.pool.state() == ConnectionState::Connected)
+            .collect();
+        if healthy.is_empty() {
+            return None;
+        }
+
+        let replica = match self.routing {
+            ReadRouting::RoundRobin => healthy[self.next.fetch_add(1, Ordering::Relaxed) % healthy.len()],
+            ReadRouting::LeastLatency => healthy
+                .iter()
+                .copied()
+                .min_by_key(|replica| replica.latency_us.load(Ordering::Relaxed))
+                .expect("at least one healthy replica"),
+        };
+        Some(&replica.pool)
+    }
+
+    pub(crate) fn states(&self) -> Vec<(String, ConnectionState)> {
+        self.replicas
+            .iter()
+            .map(|replica| (replica.url.clone(), replica.pool.state()))
+            .collect()
+    }
+
+    pub(crate) fn record_route(&self, target: &str) {
+        if let Some(telemetry) = &self.telemetry {
+            telemetry.record_metric(
+                "db_read_route".to_string(),
+                1.0,
+                vec![("target".to_string(), target.to_string())],
+            );
+        }
+    }
+}
+
+/// Ping every replica on an interval and keep a moving average of the
+/// round trip for least-latency routing. Exits once the set is dropped.
+async fn measure_latency(set: Weak<ReplicaSet>, interval: std::time::Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let Some(set) = set.upgrade() else { return };
+
+        for replica in &set.replicas {
+            let started = Instant::now();
+            let healthy = match replica.pool.get().await {
+                Ok(conn) => conn.health().await.is_ok(),
+                Err(_) => false,
+            };
+            if !healthy {
+                replica.latency_us.store(u64::MAX, Ordering::Relaxed);
+                continue;
+            }
+
+            let sample = started.elapsed().as_micros() as u64;
+            let previous = replica.latency_us.load(Ordering::Relaxed);
+            let average = if previous == u64::MAX {
+                sample
+            } else {
+                (previous * 4 + sample) / 5
+            };
+            replica.latency_us.store(average, Ordering::Relaxed);
+            debug!("Replica {} latency {}us", replica.url, average);
+        }
+    }
+}
+
+/// Statements that may start a read-only query.
+const READ_STATEMENTS: &[&str] = &["SELECT", "INFO", "SHOW", "LET", "RETURN"];
+
+/// Keywords that make a query a write wherever they appear, including in
+/// subqueries. Identifiers that happen to match (a field called `update`)
+/// only send the query to the primary, which is always safe.
+const WRITE_KEYWORDS: &[&str] = &[
+    "CREATE", "UPDATE", "UPSERT", "DELETE", "INSERT", "RELATE", "DEFINE", "REMOVE", "ALTER",
+    "REBUILD", "BEGIN", "COMMIT", "CANCEL", "KILL", "LIVE", "USE", "OPTION", "ACCESS",
+];
+
+/// HTTP functions that only fetch; the others send data to another service
+const READ_HTTP_FUNCTIONS: &[&str] = &["GET", "HEAD"];
+
+/// Whether every statement in `query` only reads data, so it may be served
+/// by a replica. Custom functions (`fn::`) can write and `http::` functions
+/// other than `get` and `head` have side effects elsewhere, so they count as
+/// writes; anything the classifier does not recognise goes to the primary.
+pub fn is_read_only(query: &str) -> bool {
+    let tokens = tokenize(query);
+    if tokens.iter().all(|token| *token == Token::Semicolon) {
+        return false;
+    }
+
+    let mut statement_start = true;
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Semicolon => {
+                statement_start = true;
+                continue;
+            }
+            Token::Word(word) => {
+                let upper = word.to_ascii_uppercase();
+                if statement_start && !READ_STATEMENTS.contains(&upper.as_str()) {
+                    return false;
+                }
+                if WRITE_KEYWORDS.contains(&upper.as_str()) {
+                    return false;
+                }
+                if tokens.get(index + 1) == Some(&Token::PathSeparator) {
+                    match upper.as_str() {
+                        "FN" => return false,
+                        "HTTP" => {
+                            let read = matches!(
+                                tokens.get(index + 2),
+                                Some(Token::Word(function))
+                                    if READ_HTTP_FUNCTIONS.contains(&function.to_ascii_uppercase().as_str())
+                            );
+                            if !read {
+                                return false;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Token::PathSeparator | Token::Other => {
+                if statement_start {
+                    return false;
+                }
+            }
+        }
+        statement_start = false;
+    }
+
+    true
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Semicolon,
+    PathSeparator,
+    Other,
+}
+
+/// Split SurrealQL into words and punctuation, skipping string literals,
+/// quoted identifiers and comments.
+fn tokenize(query: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            ';' => tokens.push(Token::Semicolon),
+            ':' if chars.peek() == Some(&':') => {
+                chars.next();
+                tokens.push(Token::PathSeparator);
+            }
+            '\'' | '"' | '`' => {
+                skip_quoted(&mut chars, c);
+                tokens.push(Token::Other);
+            }
+            '⟨' => {
+                skip_quoted(&mut chars, '⟩');
+                tokens.push(Token::Other);
+            }
+            '#' => skip_line(&mut chars),
+            '-' if chars.peek() == Some(&'-') => skip_line(&mut chars),
+            '/' if chars.peek() == Some(&'/') => skip_line(&mut chars),
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = '\0';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut word = String::from(c);
+                while let Some(&next) = chars.peek() {
+                    if !(next.is_alphanumeric() || next == '_') {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+            _ => tokens.push(Token::Other),
+        }
+    }
+
+    tokens
+}
+
+fn skip_quoted(chars: &mut std::iter::Peekable<std::str::Chars<'_>>, close: char) {
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if c == close {
+            break;
+        }
+    }
+}
+
+fn skip_line(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) {
+    for c in chars.by_ref() {
+        if c == '\n' {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{DatabaseManager, QueryOptions};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_classifies_statements() {
+        assert!(is_read_only("SELECT * FROM user WHERE email = $email"));
+        assert!(is_read_only("INFO FOR DB; select count() FROM user GROUP ALL;"));
+        assert!(is_read_only("LET $n = (SELECT * FROM user); RETURN $n"));
+        assert!(is_read_only("SELECT * FROM user WHERE name = 'DELETE me' -- UPDATE\n"));
+
+        assert!(!is_read_only("CREATE user SET name = 'x'"));
+        assert!(!is_read_only("SELECT * FROM (DELETE user RETURN BEFORE)"));
+        assert!(!is_read_only("SELECT * FROM user; UPDATE user SET seen = true"));
+        assert!(!is_read_only("RETURN fn::rotate_keys()"));
+        assert!(is_read_only("RETURN http::get('https://example.com')"));
+        assert!(is_read_only("RETURN http::HEAD('https://example.com')"));
+        for function in ["post", "put", "patch", "delete"] {
+            let query = format!("SELECT *, http::{}('https://example.com', $body) FROM user", function);
+            assert!(!is_read_only(&query), "{}", query);
+        }
+        assert!(!is_read_only("{ SELECT * FROM user }"));
+        assert!(!is_read_only(" ; "));
+    }
+
+    fn replicated_config(urls: &[&str], routing: ReadRouting) -> DatabaseConfig {
+        // Each in-memory "replica" is a separate datastore, which makes the
+        // node that served a read observable
+        let mut config = DatabaseConfig::in_memory("test", "replicas");
+        config.replicas = ReplicaConfig {
+            urls: urls.iter().map(|url| url.to_string()).collect(),
+            routing,
+        };
+        config
+    }
+
+    async fn mark(db: &DatabaseManager) {
+        db.execute_query("CREATE node:primary", HashMap::new()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reads_go_to_replicas_and_writes_to_primary() {
+        let db = DatabaseManager::new(replicated_config(&["replica-1"], ReadRouting::RoundRobin))
+            .await
+            .unwrap();
+        mark(&db).await;
+
+        // The write landed on the primary, so the replica does not see it
+        let replica_read = db.execute_query("SELECT * FROM node", HashMap::new()).await.unwrap();
+        assert_eq!(replica_read, serde_json::json!([[]]));
+
+        let primary_read = db
+            .execute_query_with(
+                "SELECT * FROM node",
+                HashMap::new(),
+                QueryOptions {
+                    read_preference: ReadPreference::Primary,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(primary_read[0].as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_round_robin_and_least_latency() {
+        let config = replicated_config(&["replica-1", "replica-2"], ReadRouting::RoundRobin);
+        let set = ReplicaSet::new(&config, None).await.unwrap();
+        let first = Arc::as_ptr(set.pick().unwrap());
+        let second = Arc::as_ptr(set.pick().unwrap());
+        assert_ne!(first, second);
+        assert_eq!(Arc::as_ptr(set.pick().unwrap()), first);
+
+        let config = replicated_config(&["replica-1", "replica-2"], ReadRouting::LeastLatency);
+        let set = ReplicaSet::new(&config, None).await.unwrap();
+        set.replicas[0].latency_us.store(900, Ordering::Relaxed);
+        set.replicas[1].latency_us.store(100, Ordering::Relaxed);
+        assert_eq!(Arc::as_ptr(set.pick().unwrap()), Arc::as_ptr(&set.replicas[1].pool));
+    }
+}
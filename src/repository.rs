@@ -0,0 +1,505 @@
+// Path: src/repository.rs
+
+use crate::db::{escape_ident, timeout_clause, DatabaseManager, DatabaseResult};
+use crate::error::{Error, Result};
+use crate::live::LiveStream;
+use crate::pagination::{Page, PageQuery, PageRequest};
+use crate::replica::ReadPreference;
+use crate::sanitizer::Sanitizer;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
+use surrealdb::sql::Thing;
+
+/// A stored row: the record id plus the table's fields.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record<T> {
+    pub id: Thing,
+    #[serde(flatten)]
+    pub data: T,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterOp {
+    Eq,
+    Ne,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    /// The field (an array or string) contains the value
+    Contains,
+}
+
+impl FilterOp {
+    pub(crate) fn as_surql(self) -> &'static str {
+        match self {
+            FilterOp::Eq => "=",
+            FilterOp::Ne => "!=",
+            FilterOp::Lt => "<",
+            FilterOp::Lte => "<=",
+            FilterOp::Gt => ">",
+            FilterOp::Gte => ">=",
+            FilterOp::Contains => "CONTAINS",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Asc,
+    Desc,
+}
+
+impl Order {
+    pub(crate) fn as_surql(self) -> &'static str {
+        match self {
+            Order::Asc => "ASC",
+            Order::Desc => "DESC",
+        }
+    }
+}
+
+/// Values bound by the filters, by parameter name
+type Bindings = Vec<(String, serde_json::Value)>;
+
+#[derive(Debug, Clone)]
+struct Filter {
+    field: String,
+    op: FilterOp,
+    value: serde_json::Value,
+}
+
+/// Filters for [`Repository::list`] and [`Repository::count`], combined with
+/// AND. Values are bound as JSON, so compare datetimes with `<datetime>`
+/// casts in raw queries instead. Lists are always ordered and paged by
+/// [`PageRequest`].
+#[derive(Debug, Clone, Default)]
+pub struct ListOptions {
+    filters: Vec<Filter>,
+}
+
+impl ListOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn filter(mut self, field: &str, op: FilterOp, value: impl Into<serde_json::Value>) -> Self {
+        self.filters.push(Filter {
+            field: field.to_string(),
+            op,
+            value: value.into(),
+        });
+        self
+    }
+
+    /// The WHERE clause and the values it binds as `$f0`, `$f1`, ...
+    fn where_clause(&self, sanitizer: &Sanitizer) -> Result<(String, Bindings)> {
+        let (conditions, bindings) = self.conditions(sanitizer)?;
+        if conditions.is_empty() {
+            return Ok((String::new(), bindings));
+        }
+        Ok((format!(" WHERE {}", conditions.join(" AND ")), bindings))
+    }
+
+    fn conditions(&self, sanitizer: &Sanitizer) -> Result<(Vec<String>, Bindings)> {
+        let mut conditions = Vec::with_capacity(self.filters.len());
+        let mut bindings = Vec::with_capacity(self.filters.len());
+        for (index, filter) in self.filters.iter().enumerate() {
+            let param = format!("f{}", index);
+            conditions.push(format!(
+                "{} {} ${}",
+                field_path(sanitizer, &filter.field)?,
+                filter.op.as_surql(),
+                param
+            ));
+            bindings.push((param, filter.value.clone()));
+        }
+
+        Ok((conditions, bindings))
+    }
+}
+
+/// Typed CRUD access to a single table.
+///
+/// `T` holds the table's fields without the id; records come back as
+/// [`Record<T>`]. Queries address the table through `type::table`, and field
+/// names used for filtering are validated and escaped.
+pub struct Repository<T> {
+    db: Arc<DatabaseManager>,
+    table: String,
+    sanitizer: Sanitizer,
+    timeout: Option<Duration>,
+    read_preference: ReadPreference,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Repository<T>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    pub fn new(db: Arc<DatabaseManager>, table: &str) -> Result<Self> {
+        let sanitizer = Sanitizer::new();
+        let table = sanitizer.sanitize_name(table).map_err(Error::InvalidInput)?;
+
+        Ok(Self {
+            db,
+            table,
+            sanitizer,
+            timeout: None,
+            read_preference: ReadPreference::default(),
+            _marker: PhantomData,
+        })
+    }
+
+    /// Use `timeout` for every query instead of the configured default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Where `get`, `list` and `count` read from; replicas by default.
+    pub fn with_read_preference(mut self, preference: ReadPreference) -> Self {
+        self.read_preference = preference;
+        self
+    }
+
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    /// Record id for `key` in this table.
+    pub fn thing(&self, key: &str) -> Thing {
+        Thing::from((self.table.as_str(), key))
+    }
+
+    /// Create a record with a generated id.
+    pub async fn create(&self, data: T) -> Result<Record<T>> {
+        let query = format!("CREATE type::table($table) CONTENT $data{}", self.timeout_clause());
+        let record: Option<Record<T>> = self
+            .run_write(async {
+                let conn = self.db.get_connection().await?;
+                Ok(conn
+                    .query(query)
+                    .bind(("table", self.table.clone()))
+                    .bind(("data", data))
+                    .await?
+                    .take(0)?)
+            })
+            .await?;
+
+        record.ok_or_else(|| Error::Internal(format!("CREATE on {} returned no record", self.table)))
+    }
+
+    /// Create a record with the given id; fails if it already exists.
+    pub async fn create_with_id(&self, id: &Thing, data: T) -> Result<Record<T>> {
+        self.check_table(id)?;
+        let query = format!("CREATE $id CONTENT $data{}", self.timeout_clause());
+        let record: Option<Record<T>> = self
+            .run_write(async {
+                let conn = self.db.get_connection().await?;
+                Ok(conn
+                    .query(query)
+                    .bind(("id", id.clone()))
+                    .bind(("data", data))
+                    .await?
+                    .take(0)?)
+            })
+            .await?;
+
+        record.ok_or_else(|| Error::Internal(format!("CREATE {} returned no record", id)))
+    }
+
+    pub async fn get(&self, id: &Thing) -> Result<Option<Record<T>>> {
+        self.check_table(id)?;
+        let query = format!("SELECT * FROM $id{}", self.timeout_clause());
+        self.run(async {
+            let conn = self.db.get_read_connection(self.read_preference).await?;
+            Ok(conn.query(query).bind(("id", id.clone())).await?.take(0)?)
+        })
+        .await
+    }
+
+    /// Replace every field of an existing record.
+    pub async fn replace(&self, id: &Thing, data: T) -> Result<Record<T>> {
+        self.write(id, "CONTENT", data).await
+    }
+
+    /// Update only the fields present in `patch`, e.g. a `serde_json::json!`
+    /// object or a struct of optional fields with `skip_serializing_if`.
+    pub async fn merge<P>(&self, id: &Thing, patch: P) -> Result<Record<T>>
+    where
+        P: Serialize + Send + 'static,
+    {
+        self.write(id, "MERGE", patch).await
+    }
+
+    async fn write<P>(&self, id: &Thing, clause: &str, data: P) -> Result<Record<T>>
+    where
+        P: Serialize + Send + 'static,
+    {
+        self.check_table(id)?;
+        let query = format!("UPDATE $id {} $data{}", clause, self.timeout_clause());
+        let record: Option<Record<T>> = self
+            .run_write(async {
+                let conn = self.db.get_connection().await?;
+                Ok(conn
+                    .query(query)
+                    .bind(("id", id.clone()))
+                    .bind(("data", data))
+                    .await?
+                    .take(0)?)
+            })
+            .await?;
+
+        record.ok_or_else(|| Error::NotFound(id.to_string()))
+    }
+
+    /// Delete a record, returning its last state.
+    pub async fn delete(&self, id: &Thing) -> Result<Record<T>> {
+        self.check_table(id)?;
+        let query = format!("DELETE $id RETURN BEFORE{}", self.timeout_clause());
+        let record: Option<Record<T>> = self
+            .run_write(async {
+                let conn = self.db.get_connection().await?;
+                Ok(conn.query(query).bind(("id", id.clone())).await?.take(0)?)
+            })
+            .await?;
+
+        record.ok_or_else(|| Error::NotFound(id.to_string()))
+    }
+
+    /// Records matching the filters in `options`, newest first by
+    /// `created_at`, which the table must store as a datetime.
+    pub async fn list(&self, options: &ListOptions, page: &PageRequest) -> Result<Page<Record<T>>> {
+        let (conditions, bindings) = options.conditions(&self.sanitizer)?;
+        self.run(async {
+            let conn = self.db.get_read_connection(self.read_preference).await?;
+            PageQuery::new(&self.table, "*")
+                .filter(conditions, bindings)
+                .timeout_clause(self.timeout_clause())
+                .fetch(&conn, self.db.cursor_signer(), page)
+                .await
+        })
+        .await
+    }
+
+    /// Number of records matching the filters in `options`.
+    pub async fn count(&self, options: &ListOptions) -> Result<usize> {
+        let (where_clause, bindings) = options.where_clause(&self.sanitizer)?;
+        let query = format!(
+            "SELECT count() FROM type::table($table){} GROUP ALL{}",
+            where_clause,
+            self.timeout_clause()
+        );
+
+        let count: Option<usize> = self
+            .run(async {
+                let conn = self.db.get_read_connection(self.read_preference).await?;
+                let mut request = conn.query(query).bind(("table", self.table.clone()));
+                for binding in bindings {
+                    request = request.bind(binding);
+                }
+
+                Ok(request.await?.take((0, "count"))?)
+            })
+            .await?;
+        Ok(count.unwrap_or(0))
+    }
+
+    /// Stream creates, updates and deletes on the table.
+    pub async fn watch(&self) -> Result<LiveStream<Record<T>>> {
+        Ok(self.db.live_table(&self.table).await?)
+    }
+
+    /// Stream changes to a single record.
+    pub async fn watch_record(&self, id: &Thing) -> Result<LiveStream<Record<T>>> {
+        self.check_table(id)?;
+        Ok(self.db.live_record(id).await?)
+    }
+
+    fn check_table(&self, id: &Thing) -> Result<()> {
+        if id.tb == self.table {
+            Ok(())
+        } else {
+            Err(Error::InvalidInput(format!("record {} does not belong to table {}", id, self.table)))
+        }
+    }
+
+    fn timeout_clause(&self) -> String {
+        timeout_clause(self.timeout.or(self.db.query_timeout()))
+    }
+
+    /// Run `operation` under the repository's timeout. Driver errors go
+    /// through [`DatabaseError`](crate::db::DatabaseError), so lost
+    /// connections come back retryable.
+    async fn run<R>(&self, operation: impl Future<Output = DatabaseResult<R>>) -> Result<R> {
+        Ok(self.db.with_timeout(self.timeout, operation).await?)
+    }
+
+    /// Like [`Repository::run`], dropping cached reads of the table afterwards.
+    async fn run_write<R>(&self, operation: impl Future<Output = DatabaseResult<R>>) -> Result<R> {
+        let result = self.run(operation).await;
+        self.db.invalidate_cache(&[&self.table]);
+        result
+    }
+}
+
+/// Validate and escape a possibly nested field name such as `address.city`.
+pub(crate) fn field_path(sanitizer: &Sanitizer, field: &str) -> Result<String> {
+    field
+        .split('.')
+        .map(|part| {
+            sanitizer
+                .sanitize_name(part)
+                .map(|part| escape_ident(&part))
+                .map_err(|e| Error::InvalidInput(format!("{}: {}", field, e)))
+        })
+        .collect::<Result<Vec<_>>>()
+        .map(|parts| parts.join("."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::DatabaseError;
+    use crate::tests::test_db;
+    use serde_json::json;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Product {
+        name: String,
+        price: i64,
+        tags: Vec<String>,
+    }
+
+    fn product(name: &str, price: i64, tags: &[&str]) -> Product {
+        Product {
+            name: name.to_string(),
+            price,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+
+    async fn products() -> Repository<Product> {
+        let db = Arc::new(test_db("test", "repository").await);
+        db.get_connection()
+            .await
+            .unwrap()
+            .query(
+                "DEFINE TABLE product SCHEMAFULL;
+                 DEFINE FIELD name ON product TYPE string;
+                 DEFINE FIELD price ON product TYPE int;
+                 DEFINE FIELD tags ON product TYPE array<string>;
+                 DEFINE FIELD created_at ON product VALUE $before OR time::now();",
+            )
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+        Repository::new(db, "product").unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_crud() {
+        let repo = products().await;
+
+        let created = repo.create(product("lamp", 30, &["home"])).await.unwrap();
+        assert_eq!(created.id.tb, "product");
+        assert_eq!(repo.get(&created.id).await.unwrap(), Some(created.clone()));
+
+        let merged = repo.merge(&created.id, json!({ "price": 25 })).await.unwrap();
+        assert_eq!(merged.data, product("lamp", 25, &["home"]));
+
+        let replaced = repo.replace(&created.id, product("desk lamp", 40, &[])).await.unwrap();
+        assert_eq!(replaced.data, product("desk lamp", 40, &[]));
+
+        let deleted = repo.delete(&created.id).await.unwrap();
+        assert_eq!(deleted, replaced);
+        assert!(repo.get(&created.id).await.unwrap().is_none());
+        assert!(matches!(repo.delete(&created.id).await, Err(Error::NotFound(_))));
+        assert!(matches!(repo.merge(&created.id, json!({})).await, Err(Error::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_explicit_ids() {
+        let repo = products().await;
+        let id = repo.thing("chair");
+
+        let created = repo.create_with_id(&id, product("chair", 80, &[])).await.unwrap();
+        assert_eq!(created.id, id);
+        // Query errors are classified like any other database error
+        let duplicate = repo.create_with_id(&id, product("chair", 80, &[])).await.unwrap_err();
+        assert!(matches!(duplicate, Error::Connection(DatabaseError::DatabaseError(_))), "{}", duplicate);
+        assert!(!duplicate.is_retryable());
+
+        let foreign = Thing::from(("user", "chair"));
+        assert!(matches!(repo.get(&foreign).await, Err(Error::InvalidInput(_))));
+    }
+
+    #[tokio::test]
+    async fn test_list_and_count() {
+        let repo = products().await;
+        for (name, price, tags) in [("a", 10, &["x"][..]), ("b", 20, &["x", "y"]), ("c", 30, &["y"]), ("d", 40, &[])] {
+            repo.create(product(name, price, tags)).await.unwrap();
+        }
+
+        let options = ListOptions::new().filter("price", FilterOp::Gte, 20);
+        let first = repo.list(&options, &PageRequest::new(2)).await.unwrap();
+        let names: Vec<_> = first.items.into_iter().map(|r| r.data.name).collect();
+        assert_eq!(names, ["d", "c"]);
+
+        let token = first.next_page_token.unwrap();
+        let last = repo.list(&options, &PageRequest::new(2).after(&token)).await.unwrap();
+        assert_eq!(last.items.len(), 1);
+        assert_eq!(last.items[0].data.name, "b");
+        assert_eq!(last.next_page_token, None);
+
+        assert_eq!(repo.count(&ListOptions::new()).await.unwrap(), 4);
+        assert_eq!(repo.count(&ListOptions::new().filter("tags", FilterOp::Contains, "y")).await.unwrap(), 2);
+
+        let invalid = ListOptions::new().filter("price; REMOVE TABLE product", FilterOp::Eq, 1);
+        assert!(matches!(repo.list(&invalid, &PageRequest::default()).await, Err(Error::InvalidInput(_))));
+    }
+
+    #[tokio::test]
+    async fn test_list_pages() {
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        struct Event {
+            kind: String,
+            created_at: chrono::DateTime<chrono::Utc>,
+        }
+
+        let db = Arc::new(test_db("test", "repository_pages").await);
+        // Timestamps serialized inside a struct arrive as strings
+        db.execute_query(
+            "DEFINE FIELD created_at ON event VALUE <datetime> $value",
+            Default::default(),
+        )
+        .await
+        .unwrap();
+        let repo: Repository<Event> = Repository::new(db, "event").unwrap();
+        let start = chrono::Utc::now();
+        for n in 0..7 {
+            let kind = if n % 2 == 0 { "even" } else { "odd" };
+            let event = Event { kind: kind.to_string(), created_at: start + chrono::Duration::seconds(n) };
+            repo.create(event).await.unwrap();
+        }
+
+        let evens = ListOptions::new().filter("kind", FilterOp::Eq, "even");
+        let first = repo.list(&evens, &PageRequest::new(3)).await.unwrap();
+        let seconds: Vec<_> = first.items.iter().map(|r| (r.data.created_at - start).num_seconds()).collect();
+        assert_eq!(seconds, [6, 4, 2]);
+
+        let token = first.next_page_token.unwrap();
+        let last = repo.list(&evens, &PageRequest::new(3).after(&token)).await.unwrap();
+        assert_eq!(last.items.len(), 1);
+        assert_eq!(last.next_page_token, None);
+
+        let other: Repository<Event> = Repository::new(repo.db.clone(), "other").unwrap();
+        assert!(other.list(&evens, &PageRequest::new(3).after(&token)).await.is_err());
+    }
+}
@@ -1,36 +1,53 @@
 use regex::Regex;
 use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
 
+/// Input and query sanitizer. Clones share the blocked pattern set, so a
+/// reload through any clone applies to all of them.
 #[derive(Debug, Clone)]
 pub struct Sanitizer {
     allowed_chars: Regex,
-    blocked_patterns: HashSet<String>,
+    name_chars: Regex,
+    blocked_patterns: Arc<RwLock<HashSet<String>>>,
+}
+
+impl Default for Sanitizer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Sanitizer {
     pub fn new() -> Self {
-        let allowed_chars = Regex::new(r"^[a-zA-Z0-9_\-\.@\s]+$").unwrap();
-        let blocked_patterns = HashSet::from([
+        Self::with_blocked_patterns(&[
             "DROP".to_string(),
             "DELETE".to_string(),
             "--".to_string(),
             ";".to_string(),
-        ]);
+        ])
+    }
+
+    pub fn with_blocked_patterns(patterns: &[String]) -> Self {
+        let allowed_chars = Regex::new(r"^[a-zA-Z0-9_\-\.@\s]+$").unwrap();
+        let name_chars = Regex::new(r"^[a-zA-Z_][a-zA-Z0-9_]*$").unwrap();
 
         Self {
             allowed_chars,
-            blocked_patterns,
+            name_chars,
+            blocked_patterns: Arc::new(RwLock::new(normalize_patterns(patterns))),
         }
     }
 
+    /// Replace the blocked patterns in one step; checks already in progress
+    /// finish against the previous set.
+    pub fn set_blocked_patterns(&self, patterns: &[String]) {
+        let patterns = normalize_patterns(patterns);
+        *self.blocked_patterns.write().unwrap_or_else(|e| e.into_inner()) = patterns;
+    }
+
     pub fn sanitize_input(&self, input: &str) -> Result<String, String> {
         // Check for blocked patterns
-        let upper_input = input.to_uppercase();
-        for pattern in &self.blocked_patterns {
-            if upper_input.contains(pattern) {
-                return Err(format!("Input contains blocked pattern: {}", pattern));
-            }
-        }
+        self.check_blocked_patterns(input)?;
 
         // Validate characters
         if !self.allowed_chars.is_match(input) {
@@ -46,4 +63,40 @@ impl Sanitizer {
         }
         Ok(identifier.to_string())
     }
-}
\ No newline at end of file
+
+    /// Check a table, field, namespace or parameter name that ends up in
+    /// SurrealQL. Stricter than `sanitize_identifier`: only letters, digits
+    /// and underscores, not starting with a digit.
+    pub fn sanitize_name(&self, name: &str) -> Result<String, String> {
+        if !self.name_chars.is_match(name) {
+            return Err(format!("`{}` is not a valid name", name));
+        }
+        Ok(name.to_string())
+    }
+
+    /// Check a raw SurrealQL query against the blocked patterns. Unlike
+    /// `sanitize_input` this does not restrict the character set, since
+    /// queries legitimately contain operators and punctuation.
+    pub fn sanitize_query(&self, query: &str) -> Result<String, String> {
+        if query.trim().is_empty() {
+            return Err("Query is empty".to_string());
+        }
+        self.check_blocked_patterns(query)?;
+        Ok(query.to_string())
+    }
+
+    fn check_blocked_patterns(&self, input: &str) -> Result<(), String> {
+        let upper_input = input.to_uppercase();
+        let blocked_patterns = self.blocked_patterns.read().unwrap_or_else(|e| e.into_inner());
+        for pattern in blocked_patterns.iter() {
+            if upper_input.contains(pattern) {
+                return Err(format!("Input contains blocked pattern: {}", pattern));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn normalize_patterns(patterns: &[String]) -> HashSet<String> {
+    patterns.iter().map(|pattern| pattern.to_uppercase()).collect()
+}
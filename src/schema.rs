@@ -1,4 +1,6 @@
-use crate::db::DatabaseManager;
+use crate::db::{escape_ident, DatabaseManager};
+use crate::sanitizer::Sanitizer;
+use regex::Regex;
 use std::error::Error;
 use surrealdb::sql::Thing;
 use tracing::{info, warn};
@@ -11,6 +13,103 @@ pub struct FieldDefinition {
     pub required: bool,
 }
 
+impl FieldDefinition {
+    /// Read a field back from the `DEFINE FIELD` statement reported by
+    /// `INFO FOR TABLE`. Fields without a `TYPE` clause accept anything, and
+    /// fields with a `DEFAULT` or `VALUE` clause may be left out since the
+    /// database fills them in.
+    pub fn from_surql(definition: &str) -> Option<Self> {
+        let pattern = Regex::new(
+            r"^DEFINE FIELD (?:OVERWRITE |IF NOT EXISTS )?(\S+) ON (?:TABLE )?\S+(?: FLEXIBLE)?(?: TYPE (.+?))?( DEFAULT| READONLY| VALUE| ASSERT| PERMISSIONS| COMMENT|$)",
+        )
+        .unwrap();
+        let captures = pattern.captures(definition)?;
+        let name = captures[1].trim_matches('`').to_string();
+        let kind = captures.get(2).map_or("any", |m| m.as_str()).trim();
+
+        // DEFAULT, READONLY and VALUE come before ASSERT and PERMISSIONS,
+        // whose expressions are not looked at
+        let clauses = &definition[captures.get(3).map_or(definition.len(), |m| m.start())..];
+        let clauses = [" ASSERT ", " PERMISSIONS ", " COMMENT "]
+            .iter()
+            .filter_map(|clause| clauses.find(clause))
+            .min()
+            .map_or(clauses, |end| &clauses[..end]);
+        let filled_in = clauses.starts_with(" DEFAULT ") || clauses.contains(" VALUE ");
+
+        let (field_type, required) = match kind.strip_prefix("option<").and_then(|k| k.strip_suffix('>')) {
+            Some(inner) => (inner.to_string(), false),
+            None => (kind.to_string(), kind != "any" && !filled_in),
+        };
+        Some(Self { name, field_type, required })
+    }
+
+    /// Check a JSON value for this field before it is written. `None` is a
+    /// missing field. Types the client cannot check, e.g. geometries, are
+    /// left to the database.
+    pub fn check(&self, value: Option<&serde_json::Value>) -> Result<(), String> {
+        match value {
+            None | Some(serde_json::Value::Null) if self.required => Err(format!("{} is required", self.name)),
+            None | Some(serde_json::Value::Null) => Ok(()),
+            Some(value) if kind_matches(&self.field_type, value) => Ok(()),
+            Some(value) => Err(format!("{} must be {}, found {}", self.name, self.field_type, value)),
+        }
+    }
+}
+
+fn kind_matches(kind: &str, value: &serde_json::Value) -> bool {
+    let kind = kind.trim();
+    let alternatives = split_top_level(kind, '|');
+    if alternatives.len() > 1 {
+        return alternatives.iter().any(|k| kind_matches(k, value));
+    }
+
+    let (base, inner) = match kind.split_once('<') {
+        Some((base, rest)) => (base, rest.strip_suffix('>')),
+        None => (kind, None),
+    };
+    match base {
+        "option" => value.is_null() || inner.is_none_or(|k| kind_matches(k, value)),
+        "string" => value.is_string(),
+        "int" => value.is_i64() || value.is_u64(),
+        "float" | "number" | "decimal" => value.is_number(),
+        "bool" => value.is_boolean(),
+        "object" => value.is_object(),
+        "datetime" => value.as_str().is_some_and(|s| chrono::DateTime::parse_from_rfc3339(s).is_ok()),
+        "uuid" => value.as_str().is_some_and(|s| uuid::Uuid::parse_str(s).is_ok()),
+        "record" => value.as_str().is_some_and(|s| s.contains(':')),
+        "array" | "set" => match (value.as_array(), inner) {
+            (Some(items), Some(inner)) => {
+                // array<string, 10>: only the item kind is checked
+                let item = split_top_level(inner, ',')[0];
+                items.iter().all(|v| kind_matches(item, v))
+            }
+            (Some(_), None) => true,
+            (None, _) => false,
+        },
+        _ => true,
+    }
+}
+
+/// Split on `separator` outside of `<...>`.
+fn split_top_level(kind: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (index, c) in kind.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            c if c == separator && depth == 0 => {
+                parts.push(kind[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(kind[start..].trim());
+    parts
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IndexDefinition {
     pub name: String,
@@ -18,11 +117,76 @@ pub struct IndexDefinition {
     pub unique: bool,
 }
 
+/// Keep a change feed for the table so changes can be read back with
+/// `SHOW CHANGES`, e.g. by a [`ChangeFeedConsumer`](crate::changefeed::ChangeFeedConsumer).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeFeedDefinition {
+    /// How long changes are kept before they are garbage collected
+    pub retention_secs: u64,
+    /// Also record the previous version of updated records
+    #[serde(default)]
+    pub include_original: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TableDefinition {
     pub name: String,
     pub fields: Vec<FieldDefinition>,
     pub indexes: Vec<IndexDefinition>,
+    #[serde(default)]
+    pub changefeed: Option<ChangeFeedDefinition>,
+}
+
+impl TableDefinition {
+    /// Render the table as `DEFINE TABLE/FIELD/INDEX` statements. Every
+    /// identifier is validated with the sanitizer before being escaped.
+    pub fn to_surql(&self, sanitizer: &Sanitizer) -> Result<String, String> {
+        let field_type = Regex::new(r"^[a-z]+(<[a-z0-9_<>|, ]+>)?$").unwrap();
+        let table = escape_ident(&sanitizer.sanitize_name(&self.name)?);
+
+        let changefeed = match &self.changefeed {
+            Some(feed) if feed.retention_secs == 0 => {
+                return Err(format!("Change feed retention for {} must be at least 1s", self.name));
+            }
+            Some(feed) => format!(
+                " CHANGEFEED {}s{}",
+                feed.retention_secs,
+                if feed.include_original { " INCLUDE ORIGINAL" } else { "" }
+            ),
+            None => String::new(),
+        };
+
+        let mut statements = vec![format!("DEFINE TABLE {} SCHEMAFULL{};", table, changefeed)];
+
+        for field in &self.fields {
+            let name = escape_ident(&sanitizer.sanitize_name(&field.name)?);
+            if !field_type.is_match(&field.field_type) {
+                return Err(format!("Invalid type for field {}: {}", field.name, field.field_type));
+            }
+            let kind = if field.required {
+                field.field_type.clone()
+            } else {
+                format!("option<{}>", field.field_type)
+            };
+            statements.push(format!("DEFINE FIELD {} ON {} TYPE {};", name, table, kind));
+        }
+
+        for index in &self.indexes {
+            let name = escape_ident(&sanitizer.sanitize_name(&index.name)?);
+            if index.fields.is_empty() {
+                return Err(format!("Index {} has no fields", index.name));
+            }
+            let fields = index.fields
+                .iter()
+                .map(|f| sanitizer.sanitize_name(f).map(|f| escape_ident(&f)))
+                .collect::<Result<Vec<_>, _>>()?
+                .join(", ");
+            let unique = if index.unique { " UNIQUE" } else { "" };
+            statements.push(format!("DEFINE INDEX {} ON {} FIELDS {}{};", name, table, fields, unique));
+        }
+
+        Ok(statements.join("\n"))
+    }
 }
 
 pub async fn init_schema(db: &DatabaseManager) -> Result<(), Box<dyn Error>> {
@@ -73,6 +237,18 @@ async fn create_user_table(db: &DatabaseManager) -> Result<(), Box<dyn Error>> {
     }
 }
 
+pub async fn create_table(db: &DatabaseManager, table: &TableDefinition) -> Result<(), Box<dyn Error>> {
+    info!("Creating table {}...", table.name);
+
+    let statements = table.to_surql(&Sanitizer::new())?;
+
+    let conn = db.get_connection().await?;
+    conn.query(statements).await?.check()?;
+
+    info!("Table {} created successfully", table.name);
+    Ok(())
+}
+
 // Helper function to check if a table exists
 pub async fn table_exists(db: &DatabaseManager, table_name: String) -> Result<bool, Box<dyn std::error::Error>> {
     let conn = db.get_connection().await?;
@@ -5,6 +5,8 @@ use argon2::{
     Argon2,
 };
 use regex::Regex;
+use std::sync::{RwLock, RwLockReadGuard};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -21,23 +23,98 @@ pub enum SecurityError {
 
 pub type SecurityResult<T> = Result<T, SecurityError>;
 
+/// Password, role and query-filtering policy loaded from the application config
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecurityConfig {
+    pub password_min_length: usize,
+    pub password_require_letter: bool,
+    pub password_require_digit: bool,
+    pub allowed_roles: Vec<String>,
+    /// Patterns the `Sanitizer` rejects in user input and raw queries
+    pub blocked_patterns: Vec<String>,
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        Self {
+            password_min_length: 8,
+            password_require_letter: true,
+            password_require_digit: true,
+            allowed_roles: vec!["admin".to_string(), "user".to_string(), "guest".to_string()],
+            blocked_patterns: vec![
+                "DROP".to_string(),
+                "DELETE".to_string(),
+                "--".to_string(),
+                ";".to_string(),
+            ],
+        }
+    }
+}
+
 pub struct SecurityManager {
     email_regex: Regex,
+    name_regex: Regex,
+    policy: RwLock<Policy>,
+}
+
+/// The configurable part of the validation rules, swapped as a whole on reload
+struct Policy {
     password_regex: Regex,
+    password_min_length: usize,
+    password_require_letter: bool,
+    password_require_digit: bool,
     role_regex: Regex,
-    name_regex: Regex,
+}
+
+impl Policy {
+    fn from_config(config: &SecurityConfig) -> Self {
+        let roles = config.allowed_roles
+            .iter()
+            .map(|role| regex::escape(role))
+            .collect::<Vec<_>>()
+            .join("|");
+
+        Self {
+            password_regex: Regex::new(&format!(r"^[A-Za-z\d]{{{},}}$", config.password_min_length)).unwrap(),
+            password_min_length: config.password_min_length,
+            password_require_letter: config.password_require_letter,
+            password_require_digit: config.password_require_digit,
+            role_regex: Regex::new(&format!("^({})$", roles)).unwrap(),
+        }
+    }
+}
+
+impl Default for SecurityManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SecurityManager {
     pub fn new() -> Self {
+        Self::with_config(&SecurityConfig::default())
+    }
+
+    pub fn with_config(config: &SecurityConfig) -> Self {
         Self {
             email_regex: Regex::new(r"^[a-zA-Z0-9._%+-]+@[a-zA-Z0-9.-]+\.[a-zA-Z]{2,}$").unwrap(),
-            password_regex: Regex::new(r"^(?=.*[A-Za-z])(?=.*\d)[A-Za-z\d]{8,}$").unwrap(),
-            role_regex: Regex::new(r"^(admin|user|guest)$").unwrap(),
             name_regex: Regex::new(r"^[a-zA-Z\s]{1,50}$").unwrap(),
+            policy: RwLock::new(Policy::from_config(config)),
         }
     }
 
+    /// Replace the password and role policy. The new rules are built before
+    /// the lock is taken, so validations never see a half-applied policy.
+    pub fn reload(&self, config: &SecurityConfig) {
+        let policy = Policy::from_config(config);
+        *self.policy.write().unwrap_or_else(|e| e.into_inner()) = policy;
+    }
+
+    fn policy(&self) -> RwLockReadGuard<'_, Policy> {
+        self.policy.read().unwrap_or_else(|e| e.into_inner())
+    }
+
     pub fn hash_password(&self, password: &str) -> SecurityResult<String> {
         let salt = SaltString::generate(&mut OsRng);
         let argon2 = Argon2::default();
@@ -62,11 +139,14 @@ impl SecurityManager {
     }
 
     pub fn is_valid_password(&self, password: &str) -> bool {
-        self.password_regex.is_match(password)
+        let policy = self.policy();
+        policy.password_regex.is_match(password)
+            && (!policy.password_require_letter || password.chars().any(|c| c.is_ascii_alphabetic()))
+            && (!policy.password_require_digit || password.chars().any(|c| c.is_ascii_digit()))
     }
 
     pub fn is_valid_role(&self, role: &str) -> bool {
-        self.role_regex.is_match(role)
+        self.policy().role_regex.is_match(role)
     }
 
     pub fn is_valid_name(&self, name: &str) -> bool {
@@ -81,10 +161,10 @@ impl SecurityManager {
         }
 
         if !self.is_valid_password(password) {
-            return Err(SecurityError::ValidationError(
-                "Password must be at least 8 characters long and contain both letters and numbers"
-                    .to_string(),
-            ));
+            return Err(SecurityError::ValidationError(format!(
+                "Password must be at least {} characters long and meet the letter and digit requirements",
+                self.policy().password_min_length
+            )));
         }
 
         Ok(())
@@ -126,6 +206,37 @@ mod tests {
         assert!(!security.is_valid_password("12345678"));
     }
 
+    #[test]
+    fn test_configured_policy() {
+        let security = SecurityManager::with_config(&SecurityConfig {
+            password_min_length: 12,
+            password_require_digit: false,
+            allowed_roles: vec!["operator".to_string()],
+            ..Default::default()
+        });
+
+        assert!(security.is_valid_password("onlyletterslong"));
+        assert!(!security.is_valid_password("Password123"));
+        assert!(security.is_valid_role("operator"));
+        assert!(!security.is_valid_role("admin"));
+    }
+
+    #[test]
+    fn test_reload_replaces_policy() {
+        let security = SecurityManager::new();
+        assert!(security.is_valid_role("guest"));
+
+        security.reload(&SecurityConfig {
+            allowed_roles: vec!["admin".to_string()],
+            password_min_length: 4,
+            ..Default::default()
+        });
+
+        assert!(!security.is_valid_role("guest"));
+        assert!(security.is_valid_role("admin"));
+        assert!(security.is_valid_password("ab12"));
+    }
+
     #[test]
     fn test_role_validation() {
         let security = SecurityManager::new();
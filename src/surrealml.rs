@@ -1,8 +1,14 @@
-use surrealdb::Surreal;
 use std::sync::Arc;
+use std::time::Duration;
 use serde::{Serialize, Deserialize};
 use thiserror::Error;
-use surrealdb::engine::remote::ws::Client;
+use crate::db::DatabaseManager;
+use crate::pagination::{Page, PageQuery, PageRequest};
+use serde::de::DeserializeOwned;
+use surrealdb::sql;
+
+/// Columns listed for datasets and models; the record key is returned as a plain string id
+const METADATA_FIELDS: &str = "record::id(id) AS id, name, description, created_at";
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Dataset {
@@ -26,122 +32,197 @@ pub enum SurrealMLError {
     DatabaseError(#[from] surrealdb::Error),
     #[error("IO error: {0}")]
     IOError(#[from] std::io::Error),
+    #[error("Connection error: {0}")]
+    ConnectionError(#[from] crate::db::DatabaseError),
 }
 
 pub type Result<T> = std::result::Result<T, SurrealMLError>;
 
 pub struct SurrealMLStorage {
-    client: Arc<Surreal<Client>>,
+    db: Arc<DatabaseManager>,
+    timeout: Option<Duration>,
 }
 
 impl SurrealMLStorage {
-    pub fn new(client: Arc<Surreal<Client>>) -> Self {
-        Self { client }
+    pub fn new(db: Arc<DatabaseManager>) -> Self {
+        Self { db, timeout: None }
+    }
+
+    /// Use `timeout` for every operation instead of the configured default.
+    /// Large datasets and weights may need more than the default allows.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
     }
 
     pub async fn store_dataset(&self, id: String, dataset: Dataset, data: Vec<u8>) -> Result<()> {
-        // Store dataset data
-        self.client
-            .query("CREATE type::thing('dataset_data', $id) SET data = $data")
-            .bind(("id", id.clone()))
-            .bind(("data", data))
-            .await?;
-
-        // Store dataset metadata
-        self.client
-            .query("CREATE type::thing('dataset', $id) SET name = $name, description = $description, created_at = $created_at, data_pointer = $data_pointer")
-            .bind(("id", id.clone()))
-            .bind(("name", dataset.name))
-            .bind(("description", dataset.description))
-            .bind(("created_at", dataset.created_at))
-            .bind(("data_pointer", format!("dataset_data:{}", id)))
-            .await?;
-
-        Ok(())
+        // Data and metadata are written together so a failure never leaves orphaned data
+        self.db.transaction_with_timeout(self.timeout, |tx| async move {
+            // Store dataset data
+            tx.query("CREATE type::thing('dataset_data', $id) SET data = $data")
+                .bind(("id", id.clone()))
+                .bind(("data", data));
+
+            // Store dataset metadata
+            tx.query("CREATE type::thing('dataset', $id) SET name = $name, description = $description, created_at = $created_at, data_pointer = $data_pointer")
+                .bind(("id", id.clone()))
+                .bind(("name", dataset.name))
+                .bind(("description", dataset.description))
+                .bind(("created_at", sql::Datetime::from(dataset.created_at)))
+                .bind(("data_pointer", format!("dataset_data:{}", id)));
+
+            Ok(())
+        }).await
     }
 
     pub async fn get_dataset(&self, id: String) -> Result<Option<(Dataset, Vec<u8>)>> {
-        let dataset = self.client
-            .query("SELECT * FROM type::thing('dataset', $id)")
-            .bind(("id", id.clone()))
-            .await?
-            .take(0)?;
-
-        if let Some(dataset) = dataset {
-            // Get dataset data
-            let data = self.client
-                .query("SELECT data FROM type::thing('dataset_data', $id)")
-                .bind(("id", id))
+        self.db.with_timeout(self.timeout, async {
+            let conn = self.db.get_connection().await?;
+
+            let dataset = conn
+                .query("SELECT * FROM type::thing('dataset', $id)")
+                .bind(("id", id.clone()))
                 .await?
                 .take(0)?;
 
-            Ok(Some((dataset, data)))
-        } else {
-            Ok(None)
-        }
+            if let Some(dataset) = dataset {
+                // Get dataset data
+                let data = conn
+                    .query("SELECT data FROM type::thing('dataset_data', $id)")
+                    .bind(("id", id))
+                    .await?
+                    .take(0)?;
+
+                Ok(Some((dataset, data)))
+            } else {
+                Ok(None)
+            }
+        }).await
     }
 
     pub async fn store_model(&self, id: String, model: Model, weights: Vec<u8>) -> Result<()> {
-        // Store model weights
-        self.client
-            .query("CREATE type::thing('model_data', $id) SET weights = $weights")
-            .bind(("id", id.clone()))
-            .bind(("weights", weights))
-            .await?;
-
-        // Store model metadata
-        self.client
-            .query("CREATE type::thing('model', $id) SET name = $name, description = $description, created_at = $created_at, model_pointer = $model_pointer")
-            .bind(("id", id.clone()))
-            .bind(("name", model.name))
-            .bind(("description", model.description))
-            .bind(("created_at", model.created_at))
-            .bind(("model_pointer", format!("model_data:{}", id)))
-            .await?;
-
-        Ok(())
+        // Weights and metadata are written together so a failure never leaves orphaned weights
+        self.db.transaction_with_timeout(self.timeout, |tx| async move {
+            // Store model weights
+            tx.query("CREATE type::thing('model_data', $id) SET weights = $weights")
+                .bind(("id", id.clone()))
+                .bind(("weights", weights));
+
+            // Store model metadata
+            tx.query("CREATE type::thing('model', $id) SET name = $name, description = $description, created_at = $created_at, model_pointer = $model_pointer")
+                .bind(("id", id.clone()))
+                .bind(("name", model.name))
+                .bind(("description", model.description))
+                .bind(("created_at", sql::Datetime::from(model.created_at)))
+                .bind(("model_pointer", format!("model_data:{}", id)));
+
+            Ok(())
+        }).await
     }
 
     pub async fn get_model(&self, id: String) -> Result<Option<(Model, Vec<u8>)>> {
-        let model = self.client
-            .query("SELECT * FROM type::thing('model', $id)")
-            .bind(("id", id.clone()))
-            .await?
-            .take(0)?;
-
-        if let Some(model) = model {
-            // Get model weights
-            let weights = self.client
-                .query("SELECT weights FROM type::thing('model_data', $id)")
-                .bind(("id", id))
+        self.db.with_timeout(self.timeout, async {
+            let conn = self.db.get_connection().await?;
+
+            let model = conn
+                .query("SELECT * FROM type::thing('model', $id)")
+                .bind(("id", id.clone()))
                 .await?
                 .take(0)?;
 
-            Ok(Some((model, weights)))
-        } else {
-            Ok(None)
-        }
+            if let Some(model) = model {
+                // Get model weights
+                let weights = conn
+                    .query("SELECT weights FROM type::thing('model_data', $id)")
+                    .bind(("id", id))
+                    .await?
+                    .take(0)?;
+
+                Ok(Some((model, weights)))
+            } else {
+                Ok(None)
+            }
+        }).await
+    }
+
+    /// Dataset metadata, newest first.
+    pub async fn list_datasets(&self, page: &PageRequest) -> Result<Page<Dataset>> {
+        self.list("dataset", page).await
     }
 
-    pub async fn list_datasets(&self, limit: i64, offset: i64) -> Result<Vec<Dataset>> {
-        let datasets = self.client
-            .query("SELECT * FROM dataset ORDER BY created_at DESC LIMIT $limit OFFSET $offset")
-            .bind(("limit", limit))
-            .bind(("offset", offset))
-            .await?
-            .take(0)?;
+    /// Model metadata, newest first.
+    pub async fn list_models(&self, page: &PageRequest) -> Result<Page<Model>> {
+        self.list("model", page).await
+    }
 
-        Ok(datasets)
+    async fn list<T: Serialize + DeserializeOwned>(&self, table: &str, page: &PageRequest) -> Result<Page<T>> {
+        let key = format!("surrealml:{}:{}:{}", table, page.page_size(), page.page_token().unwrap_or_default());
+        let fetch = self.db.with_timeout(self.timeout, async {
+            let conn = self.db.get_connection().await?;
+            Ok(PageQuery::new(table, METADATA_FIELDS)
+                .fetch(&conn, self.db.cursor_signer(), page)
+                .await?)
+        });
+        self.db.cached(key, &[table], None, fetch).await
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_db;
+
+    #[tokio::test]
+    async fn test_failed_store_leaves_no_orphaned_data() {
+        let db = Arc::new(test_db("test", "surrealml").await);
+        let storage = SurrealMLStorage::new(db.clone());
+
+        // A metadata record already exists, so the second CREATE fails
+        db.execute_query("CREATE dataset:taken SET name = 'existing'", Default::default())
+            .await
+            .unwrap();
+
+        let dataset = Dataset {
+            id: "taken".to_string(),
+            name: "new".to_string(),
+            description: String::new(),
+            created_at: chrono::Utc::now(),
+        };
+        assert!(storage.store_dataset("taken".to_string(), dataset, vec![1, 2, 3]).await.is_err());
+
+        let orphans = db
+            .execute_query("SELECT * FROM dataset_data", Default::default())
+            .await
+            .unwrap();
+        assert_eq!(orphans, serde_json::json!([[]]));
     }
 
-    pub async fn list_models(&self, limit: i64, offset: i64) -> Result<Vec<Model>> {
-        let models = self.client
-            .query("SELECT * FROM model ORDER BY created_at DESC LIMIT $limit OFFSET $offset")
-            .bind(("limit", limit))
-            .bind(("offset", offset))
-            .await?
-            .take(0)?;
+    #[tokio::test]
+    async fn test_list_models() {
+        let db = Arc::new(test_db("test", "surrealml_list").await);
+        let storage = SurrealMLStorage::new(db);
+
+        let start = chrono::Utc::now();
+        for n in 0..3 {
+            let model = Model {
+                id: format!("m{}", n),
+                name: format!("model {}", n),
+                description: String::new(),
+                created_at: start + chrono::Duration::seconds(n),
+            };
+            storage.store_model(model.id.clone(), model, vec![n as u8]).await.unwrap();
+        }
+
+        let first = storage.list_models(&PageRequest::new(2)).await.unwrap();
+        let ids: Vec<_> = first.items.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, ["m2", "m1"]);
 
-        Ok(models)
+        let token = first.next_page_token.unwrap();
+        let rest = storage.list_models(&PageRequest::new(2).after(&token)).await.unwrap();
+        assert_eq!(rest.items.len(), 1);
+        assert_eq!(rest.items[0].id, "m0");
+        assert!(rest.next_page_token.is_none());
+
+        // Tokens are bound to the table they were issued for
+        assert!(storage.list_datasets(&PageRequest::new(2).after(&token)).await.is_err());
     }
-}
\ No newline at end of file
+}
@@ -1,7 +1,7 @@
 use opentelemetry::{ 
     metrics::Result as OtelResult,
     KeyValue,
-    trace::{TracerProvider, TraceError},
+    trace::TracerProvider,
 };
 use opentelemetry_sdk::{
     metrics::{
@@ -10,15 +10,34 @@ use opentelemetry_sdk::{
         InstrumentKind, Pipeline, Aggregation,
     },
     trace::TracerProvider as SdkTracerProvider,
-    export::trace::SpanData,
+    export::trace::{ExportResult, SpanData},
 };
+use futures::future::BoxFuture;
 
-use tracing_subscriber::{layer::SubscriberExt, EnvFilter};
+use serde::{Deserialize, Serialize};
+use tracing_subscriber::{layer::SubscriberExt, reload, EnvFilter, Registry};
 use opentelemetry::global;
 
+/// Telemetry settings loaded from the application config
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    /// `tracing_subscriber::EnvFilter` directives, e.g. `info,omnipro_db=debug`
+    pub log_filter: String,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            log_filter: "info".to_string(),
+        }
+    }
+}
+
 /// Manages telemetry for the application, including metrics and tracing
 pub struct TelemetryManager {
     tracer_provider: SdkTracerProvider,
+    log_filter: reload::Handle<EnvFilter, Registry>,
 }
 
 /// Console exporter for metrics and spans
@@ -26,8 +45,8 @@ pub struct TelemetryManager {
 struct ConsoleExporter;
 
 impl AggregationSelector for ConsoleExporter {
-    fn aggregation<'a>(&'a self, _kind: InstrumentKind) -> Box<dyn Iterator<Item = Aggregation> + 'a> {
-        Box::new(std::iter::once(Aggregation::Sum()))
+    fn aggregation(&self, _kind: InstrumentKind) -> Aggregation {
+        Aggregation::Sum
     }
 }
 
@@ -57,19 +76,28 @@ impl MetricReader for ConsoleExporter {
 }
 
 impl opentelemetry_sdk::export::trace::SpanExporter for ConsoleExporter {
-    fn export(&mut self, spans: Vec<SpanData>) -> Result<(), TraceError> {
+    fn export(&mut self, spans: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
         println!("Spans: {:?}", spans);
-        Ok(())
+        Box::pin(std::future::ready(Ok(())))
     }
 }
 
 impl TelemetryManager {
     /// Initialize the telemetry system with metrics and tracing
     pub async fn init() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Self::install(EnvFilter::from_default_env())
+    }
+
+    /// Initialize telemetry with the log filter from `config`
+    pub async fn with_config(config: &TelemetryConfig) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Self::install(EnvFilter::try_new(&config.log_filter)?)
+    }
+
+    fn install(filter: EnvFilter) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         // Set up metrics with manual reader
         let reader = ManualReader::builder()
-            .with_temporality_selector(ConsoleExporter::default())
-            .with_aggregation_selector(ConsoleExporter::default())
+            .with_temporality_selector(ConsoleExporter)
+            .with_aggregation_selector(ConsoleExporter)
             .build();
 
         let meter_provider = MeterProvider::builder()
@@ -80,7 +108,7 @@ impl TelemetryManager {
 
         // Set up tracing
         let tracer_provider = SdkTracerProvider::builder()
-            .with_simple_exporter(ConsoleExporter::default())
+            .with_simple_exporter(ConsoleExporter)
             .build();
 
         global::set_tracer_provider(tracer_provider.clone());
@@ -92,13 +120,22 @@ impl TelemetryManager {
         );
 
         let telemetry = tracing_opentelemetry::layer().with_tracer(tracer);
+        let (filter, log_filter) = reload::Layer::new(filter);
         let subscriber = tracing_subscriber::registry()
-            .with(EnvFilter::from_default_env())
+            .with(filter)
             .with(telemetry);
 
         tracing::subscriber::set_global_default(subscriber)?;
 
-        Ok(Self { tracer_provider })
+        Ok(Self { tracer_provider, log_filter })
+    }
+
+    /// Replace the active log filter, e.g. `info,omnipro_db=debug`. Invalid
+    /// directives are rejected and the current filter stays in place.
+    pub fn set_log_filter(&self, directives: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let filter = EnvFilter::try_new(directives)?;
+        self.log_filter.reload(filter)?;
+        Ok(())
     }
 
     /// Record a metric with the given name, value, and attributes
@@ -113,10 +150,37 @@ impl TelemetryManager {
 
         counter.add(value, &attrs);
     }
+
+    /// Record a sample into a histogram, e.g. a latency in milliseconds
+    pub fn record_histogram(&self, name: String, value: f64, attributes: Vec<(String, String)>) {
+        let meter = global::meter("omnipro_db");
+        let histogram = meter.f64_histogram(name).init();
+
+        let attrs: Vec<KeyValue> = attributes
+            .into_iter()
+            .map(|(k, v)| KeyValue::new(k, v))
+            .collect();
+
+        histogram.record(value, &attrs);
+    }
+
+    /// Adjust a value that can go up and down, e.g. the number of open connections
+    pub fn record_up_down(&self, name: String, delta: i64, attributes: Vec<(String, String)>) {
+        let meter = global::meter("omnipro_db");
+        let counter = meter.i64_up_down_counter(name).init();
+
+        let attrs: Vec<KeyValue> = attributes
+            .into_iter()
+            .map(|(k, v)| KeyValue::new(k, v))
+            .collect();
+
+        counter.add(delta, &attrs);
+    }
 }
 
 impl Drop for TelemetryManager {
     fn drop(&mut self) {
+        self.tracer_provider.force_flush();
         global::shutdown_tracer_provider();
     }
 }
@@ -1,37 +1,64 @@
 #[cfg(test)]
 mod tests {
-    use super::*;
-    use crate::db::DatabaseManager;
-    use crate::schema::TableDefinition;
-    use surrealdb::engine::remote::ws::Client;
-    use surrealdb::Surreal;
-
-    async fn setup_test_db() -> DatabaseManager {
-        let config = DatabaseConfig {
-            url: "ws://localhost:8000".to_string(),
-            namespace: "test".to_string(),
-            database: "test".to_string(),
-        };
-        DatabaseManager::new(config).await.unwrap()
-    }
+    use crate::db::{timeout_clause, DatabaseConfig, DatabaseError, DatabaseManager, QueryOptions};
+    use crate::schema::{self, TableDefinition};
+    use crate::tests::test_db;
+    use std::time::Duration;
 
     #[tokio::test]
     async fn test_database_connection() {
-        let db = setup_test_db().await;
-        assert!(db.connect().await.is_ok());
+        let db = test_db("test", "test").await;
+        assert!(db.health_check().await.is_ok());
     }
 
     #[tokio::test]
     async fn test_create_table() {
-        let db = setup_test_db().await;
-        db.connect().await.unwrap();
+        let db = test_db("test", "test").await;
 
         let table = TableDefinition {
             name: "test_table".to_string(),
             fields: vec![],
             indexes: vec![],
+            changefeed: None,
         };
 
-        assert!(db.create_table(&table).await.is_ok());
+        assert!(schema::create_table(&db, &table).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_query_timeout() {
+        let db = test_db("test", "test").await;
+
+        let result = db
+            .execute_query_with(
+                "SLEEP 500ms",
+                Default::default(),
+                QueryOptions {
+                    timeout: Some(Duration::from_millis(50)),
+                    ..Default::default()
+                },
+            )
+            .await;
+        assert!(matches!(result, Err(DatabaseError::Timeout(limit)) if limit == Duration::from_millis(50)));
+
+        // The configured default applies when no override is given
+        let config = DatabaseConfig {
+            query_timeout_ms: 50,
+            ..DatabaseConfig::in_memory("test", "test")
+        };
+        let db = DatabaseManager::new(config).await.unwrap();
+        assert!(matches!(
+            db.execute_query("SLEEP 500ms", Default::default()).await,
+            Err(DatabaseError::Timeout(_))
+        ));
+        assert!(db.execute_query("RETURN 1", Default::default()).await.is_ok());
+    }
+
+    #[test]
+    fn test_timeout_clause() {
+        assert_eq!(timeout_clause(Some(Duration::from_secs(2))), " TIMEOUT 2000ms");
+        assert_eq!(timeout_clause(Some(Duration::from_micros(10))), " TIMEOUT 1ms");
+        assert_eq!(timeout_clause(None), "");
+        assert_eq!(DatabaseConfig { query_timeout_ms: 0, ..Default::default() }.query_timeout(), None);
     }
-}
\ No newline at end of file
+}
@@ -1,20 +1,22 @@
 #[cfg(test)]
 mod tests {
-    use super::*;
-    use crate::migrations::MigrationManager;
-    use surrealdb::engine::remote::ws::Client;
-    use surrealdb::Surreal;
+    use crate::migrations::{Migration, MigrationManager};
+    use crate::tests::{test_db, test_telemetry};
+    use std::sync::Arc;
 
     async fn setup_test_migration() -> MigrationManager {
-        let client = // ... mock client setup
-        let mut manager = MigrationManager::new(client);
+        let db = Arc::new(test_db("test", "test").await);
+        let mut manager = MigrationManager::new(db, test_telemetry().await).await.unwrap();
         
         // Add test migration
         manager.add_migration(Migration {
             version: 1,
             name: "test_migration".to_string(),
-            up: "CREATE TABLE test_table".to_string(),
-            down: "DROP TABLE test_table".to_string(),
+            description: "Create the test table".to_string(),
+            up: "DEFINE TABLE test_table".to_string(),
+            down: "REMOVE TABLE test_table".to_string(),
+            applied_at: None,
+            transactional: true,
         });
         
         manager
@@ -29,7 +31,9 @@ mod tests {
     #[tokio::test]
     async fn test_migration_down() {
         let manager = setup_test_migration().await;
+        manager.run_pending_migrations().await.unwrap();
         assert!(manager.rollback(0).await.is_ok());
+        assert_eq!(manager.get_current_version().await.unwrap(), 0);
     }
 
     #[tokio::test]
@@ -43,4 +47,4 @@ mod tests {
         let version = manager.get_current_version().await.unwrap();
         assert_eq!(version, 1);
     }
-}
\ No newline at end of file
+}
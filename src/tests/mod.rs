@@ -0,0 +1,28 @@
+// Path: src/tests/mod.rs
+
+use crate::db::{DatabaseConfig, DatabaseManager};
+use crate::telemetry::TelemetryManager;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+
+mod integration_tests;
+mod migration_tests;
+mod performance_tests;
+mod unit_tests;
+
+static TELEMETRY: OnceCell<Arc<TelemetryManager>> = OnceCell::const_new();
+
+/// Telemetry installs a global subscriber, so all tests share one instance.
+pub(crate) async fn test_telemetry() -> Arc<TelemetryManager> {
+    TELEMETRY
+        .get_or_init(|| async { Arc::new(TelemetryManager::init().await.unwrap()) })
+        .await
+        .clone()
+}
+
+/// Every call gets its own in-memory datastore, so tests never share state.
+pub(crate) async fn test_db(namespace: &str, database: &str) -> DatabaseManager {
+    DatabaseManager::new(DatabaseConfig::in_memory(namespace, database))
+        .await
+        .unwrap()
+}
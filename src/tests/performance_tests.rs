@@ -1,25 +1,16 @@
 #[cfg(test)]
 mod tests {
-    use super::*;
-    use crate::db::DatabaseManager;
+    use crate::tests::test_db;
+    use std::sync::Arc;
     use std::time::Instant;
     use tokio::time::Duration;
 
     const CONCURRENT_CONNECTIONS: usize = 100;
     const OPERATIONS_PER_CONNECTION: usize = 1000;
 
-    async fn setup_performance_test() -> DatabaseManager {
-        let config = DatabaseConfig {
-            url: "ws://localhost:8000".to_string(),
-            namespace: "perf_test".to_string(),
-            database: "perf_test".to_string(),
-        };
-        DatabaseManager::new(config).await.unwrap()
-    }
-
     #[tokio::test]
     async fn test_concurrent_connections() {
-        let db = setup_performance_test().await;
+        let db = Arc::new(test_db("perf_test", "perf_test").await);
         let start = Instant::now();
         
         let handles: Vec<_> = (0..CONCURRENT_CONNECTIONS)
@@ -46,12 +37,13 @@ mod tests {
 
     #[tokio::test]
     async fn test_query_performance() {
-        let db = setup_performance_test().await;
+        let db = test_db("perf_test", "perf_test").await;
+        let client = db.get_connection().await.unwrap();
         let start = Instant::now();
         
         // Perform complex query operations
         for _ in 0..1000 {
-            db.client
+            client
                 .query("SELECT * FROM test_table WHERE field1 = $value")
                 .bind(("value", "test"))
                 .await
@@ -64,4 +56,4 @@ mod tests {
         // Assert query performance meets requirements
         assert!(duration < Duration::from_secs(5));
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,206 @@
+// Path: src/transaction.rs
+
+use crate::db::{DatabaseError, DatabaseResult};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+
+/// Statements collected inside [`DatabaseManager::transaction`].
+///
+/// SurrealDB only keeps a transaction open for the duration of a single
+/// request, so nothing is sent while the closure runs. Once it returns `Ok`
+/// the queued statements go to the server as one
+/// `BEGIN TRANSACTION; ...; COMMIT TRANSACTION;` request.
+///
+/// [`DatabaseManager::transaction`]: crate::db::DatabaseManager::transaction
+#[derive(Clone, Default)]
+pub struct Transaction {
+    statements: Arc<Mutex<Vec<Statement>>>,
+}
+
+struct Statement {
+    sql: String,
+    params: Vec<(String, surrealdb::Value)>,
+    error: Option<String>,
+}
+
+impl Transaction {
+    /// Queue a statement (or a script of several). Parameters bound to it
+    /// with [`StatementBuilder::bind`] are scoped to this statement, so
+    /// different statements may reuse names like `$id`.
+    pub fn query(&self, sql: impl Into<String>) -> StatementBuilder<'_> {
+        let mut statements = self.lock();
+        statements.push(Statement {
+            sql: sql.into(),
+            params: Vec::new(),
+            error: None,
+        });
+
+        StatementBuilder {
+            transaction: self,
+            index: statements.len() - 1,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Statement>> {
+        self.statements.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Render the queued statements as a single transaction script. Each
+    /// statement with parameters runs in its own block, where `LET` maps the
+    /// uniquely named request parameters back to the names it uses.
+    pub(crate) fn build(&self) -> DatabaseResult<(String, Vec<(String, surrealdb::Value)>)> {
+        let statements = std::mem::take(&mut *self.lock());
+
+        let mut script = String::from("BEGIN TRANSACTION;\n");
+        let mut bindings = Vec::new();
+        for (index, statement) in statements.into_iter().enumerate() {
+            if let Some(error) = statement.error {
+                return Err(DatabaseError::InvalidInput(error));
+            }
+
+            let sql = statement.sql.trim().trim_end_matches(';');
+            if statement.params.is_empty() {
+                script.push_str(&format!("{};\n", sql));
+                continue;
+            }
+
+            script.push_str("{\n");
+            for (name, value) in statement.params {
+                let scoped = format!("tx{}_{}", index, name);
+                script.push_str(&format!("LET ${} = ${};\n", name, scoped));
+                bindings.push((scoped, value));
+            }
+            script.push_str(&format!("{};\n}};\n", sql));
+        }
+        script.push_str("COMMIT TRANSACTION;");
+
+        Ok((script, bindings))
+    }
+}
+
+/// A queued statement that parameters can still be bound to.
+pub struct StatementBuilder<'a> {
+    transaction: &'a Transaction,
+    index: usize,
+}
+
+impl StatementBuilder<'_> {
+    /// Bind `$name` for this statement, e.g. `.bind(("id", id))`.
+    pub fn bind<V>(self, (name, value): (impl Into<String>, V)) -> Self
+    where
+        V: Serialize + 'static,
+    {
+        let name = name.into();
+        let mut statements = self.transaction.lock();
+        let statement = &mut statements[self.index];
+
+        if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') || name.is_empty() {
+            statement.error = Some(format!("invalid parameter name: {}", name));
+        } else {
+            match surrealdb::value::to_value(value) {
+                Ok(value) => statement.params.push((name, value)),
+                Err(e) => statement.error = Some(format!("cannot bind ${}: {}", name, e)),
+            }
+        }
+
+        drop(statements);
+        self
+    }
+}
+
+/// The error that made a transaction fail. Every other statement in the
+/// request only reports that it was not executed.
+pub(crate) fn transaction_error(mut response: surrealdb::Response) -> Option<DatabaseError> {
+    use surrealdb::error::{Api, Db};
+
+    let mut errors: Vec<_> = response.take_errors().into_iter().collect();
+    errors.sort_by_key(|(index, _)| *index);
+
+    let is_consequence = |error: &surrealdb::Error| match error {
+        surrealdb::Error::Db(Db::QueryNotExecuted | Db::QueryCancelled) => true,
+        surrealdb::Error::Api(Api::Query(message)) => {
+            message.contains("not executed") || message.contains("was cancelled")
+        }
+        _ => false,
+    };
+
+    if errors.is_empty() {
+        return None;
+    }
+    let position = errors.iter().position(|(_, e)| !is_consequence(e)).unwrap_or(0);
+    Some(DatabaseError::from(errors.swap_remove(position).1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_db;
+
+    #[test]
+    fn test_build_scopes_parameters() {
+        let tx = Transaction::default();
+        tx.query("DEFINE TABLE t;");
+        tx.query("CREATE type::thing('t', $id)").bind(("id", "a"));
+        tx.query("CREATE type::thing('t', $id)").bind(("id", "b"));
+
+        let (script, bindings) = tx.build().unwrap();
+        assert!(script.starts_with("BEGIN TRANSACTION;\nDEFINE TABLE t;\n{\nLET $id = $tx1_id;"));
+        assert!(script.ends_with("COMMIT TRANSACTION;"));
+        let names: Vec<_> = bindings.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["tx1_id", "tx2_id"]);
+        assert!(tx.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_parameter_name() {
+        let tx = Transaction::default();
+        tx.query("RETURN $x").bind(("x; REMOVE TABLE t", 1));
+        assert!(matches!(tx.build(), Err(DatabaseError::InvalidInput(_))));
+    }
+
+    #[tokio::test]
+    async fn test_commit_and_rollback() {
+        let db = test_db("test", "transactions").await;
+
+        db.transaction(|tx| async move {
+            tx.query("CREATE type::thing('item', $id) SET n = 1").bind(("id", "a"));
+            tx.query("CREATE type::thing('item', $id) SET n = 2").bind(("id", "b"));
+            Ok::<_, DatabaseError>(())
+        })
+        .await
+        .unwrap();
+
+        // The second statement conflicts with an existing record, so the first is undone too
+        let result = db
+            .transaction(|tx| async move {
+                tx.query("CREATE type::thing('item', $id)").bind(("id", "c"));
+                tx.query("CREATE type::thing('item', $id)").bind(("id", "a"));
+                Ok::<_, DatabaseError>(())
+            })
+            .await;
+        assert!(matches!(result, Err(DatabaseError::DatabaseError(_))));
+
+        // An error from the closure cancels before anything is sent
+        let result = db
+            .transaction(|tx| async move {
+                tx.query("CREATE type::thing('item', $id)").bind(("id", "d"));
+                Err::<(), _>(DatabaseError::InvalidInput("cancelled".to_string()))
+            })
+            .await;
+        assert!(matches!(result, Err(DatabaseError::InvalidInput(_))));
+
+        let ids = db
+            .execute_query("SELECT VALUE record::id(id) FROM item", Default::default())
+            .await
+            .unwrap();
+        assert_eq!(ids, serde_json::json!([["a", "b"]]));
+    }
+}
@@ -0,0 +1,458 @@
+// Path: src/user.rs
+
+use crate::db::{timeout_clause, DatabaseError, DatabaseManager};
+use crate::live::LiveStream;
+use crate::pagination::{Page, PageQuery, PageRequest};
+use crate::replica::ReadPreference;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::time::Duration;
+use surrealdb::sql::Thing;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Name of the UNIQUE index on `user.email` created by `schema::init_schema`
+const EMAIL_INDEX: &str = "user_email";
+
+/// Columns selected for every user query; the record key is returned as a plain string id
+const USER_FIELDS: &str = "record::id(id) AS id, email, name, password_hash, role, created_at, updated_at";
+
+#[derive(Debug, Error)]
+pub enum UserError {
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+
+    #[error("A user with email {0} already exists")]
+    DuplicateEmail(String),
+
+    #[error("User not found: {0}")]
+    NotFound(String),
+}
+
+impl From<surrealdb::Error> for UserError {
+    fn from(error: surrealdb::Error) -> Self {
+        UserError::Database(DatabaseError::from(error))
+    }
+}
+
+pub type UserResult<T> = Result<T, UserError>;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct User {
+    pub id: String,
+    pub email: String,
+    pub name: String,
+    pub password_hash: String,
+    pub role: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl User {
+    /// A new, not yet stored user with a random id. The password must
+    /// already be hashed, e.g. with `SecurityManager::hash_password`.
+    pub fn new(email: String, name: String, password_hash: String, role: String) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            email,
+            name,
+            password_hash,
+            role,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Apply a partial update; `None` keeps the current value. The stored
+    /// `updated_at` is set by [`UserRepository::update`].
+    pub fn update(mut self, name: Option<String>, email: Option<String>, role: Option<String>) -> Self {
+        if let Some(name) = name {
+            self.name = name;
+        }
+        if let Some(email) = email {
+            self.email = email;
+        }
+        if let Some(role) = role {
+            self.role = role;
+        }
+        self
+    }
+}
+
+/// A `user` row as live queries deliver it: they ignore projections, so
+/// the id arrives as a full record id.
+#[derive(Deserialize)]
+struct StoredUser {
+    id: Thing,
+    email: String,
+    name: String,
+    password_hash: String,
+    role: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl From<StoredUser> for User {
+    fn from(stored: StoredUser) -> Self {
+        Self {
+            id: stored.id.id.to_raw(),
+            email: stored.email,
+            name: stored.name,
+            password_hash: stored.password_hash,
+            role: stored.role,
+            created_at: stored.created_at,
+            updated_at: stored.updated_at,
+        }
+    }
+}
+
+/// Parameters bound for writes. The id is the record key and the
+/// timestamps are set by the database.
+#[derive(Serialize)]
+struct UserParams {
+    id: String,
+    email: String,
+    name: String,
+    password_hash: String,
+    role: String,
+}
+
+impl From<&User> for UserParams {
+    fn from(user: &User) -> Self {
+        Self {
+            id: user.id.clone(),
+            email: user.email.clone(),
+            name: user.name.clone(),
+            password_hash: user.password_hash.clone(),
+            role: user.role.clone(),
+        }
+    }
+}
+
+/// Assignments shared by create and update
+const USER_ASSIGNMENTS: &str = "email = $email, name = $name, password_hash = $password_hash, role = $role";
+
+/// Typed access to the `user` table.
+pub struct UserRepository<'a> {
+    db: &'a DatabaseManager,
+    timeout: Option<Duration>,
+    read_preference: ReadPreference,
+}
+
+impl<'a> UserRepository<'a> {
+    pub fn new(db: &'a DatabaseManager) -> Self {
+        Self {
+            db,
+            timeout: None,
+            read_preference: ReadPreference::default(),
+        }
+    }
+
+    /// Use `timeout` for every query instead of the configured default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Where the `find_*` lookups read from; replicas by default. Replica
+    /// reads may be answered from the result cache, primary reads never are.
+    pub fn with_read_preference(mut self, preference: ReadPreference) -> Self {
+        self.read_preference = preference;
+        self
+    }
+
+    /// Store a new user, returning it with the timestamps the database assigned.
+    pub async fn create(&self, user: &User) -> UserResult<User> {
+        let query = format!(
+            "CREATE type::thing('user', $id) SET {}, created_at = time::now(), updated_at = created_at RETURN {}{}",
+            USER_ASSIGNMENTS,
+            USER_FIELDS,
+            self.timeout_clause()
+        );
+
+        let created: UserResult<Option<User>> = self
+            .run(async {
+                let conn = self.db.get_connection().await?;
+                conn.query(query)
+                    .bind(UserParams::from(user))
+                    .await?
+                    .take(0)
+                    .map_err(|e| write_error(e, &user.email))
+            })
+            .await;
+        self.db.invalidate_cache(&["user"]);
+
+        created?.ok_or_else(|| UserError::NotFound(user.id.clone()))
+    }
+
+    pub async fn find_by_id(&self, id: &str) -> UserResult<Option<User>> {
+        let query = format!("SELECT {} FROM type::thing('user', $id){}", USER_FIELDS, self.timeout_clause());
+        self.cached(format!("user:id:{}", id), async {
+            let conn = self.db.get_read_connection(self.read_preference).await?;
+            Ok(conn.query(query).bind(("id", id.to_string())).await?.take(0)?)
+        })
+        .await
+    }
+
+    pub async fn find_by_email(&self, email: &str) -> UserResult<Option<User>> {
+        let query = format!(
+            "SELECT {} FROM user WHERE email = $email LIMIT 1{}",
+            USER_FIELDS,
+            self.timeout_clause()
+        );
+        self.cached(format!("user:email:{}", email), async {
+            let conn = self.db.get_read_connection(self.read_preference).await?;
+            Ok(conn.query(query).bind(("email", email.to_string())).await?.take(0)?)
+        })
+        .await
+    }
+
+    /// Overwrite the mutable fields of an existing user and bump `updated_at`.
+    pub async fn update(&self, user: &User) -> UserResult<User> {
+        let query = format!(
+            "UPDATE type::thing('user', $id) SET {}, updated_at = time::now() RETURN {}{}",
+            USER_ASSIGNMENTS,
+            USER_FIELDS,
+            self.timeout_clause()
+        );
+
+        let updated: UserResult<Option<User>> = self
+            .run(async {
+                let conn = self.db.get_connection().await?;
+                conn.query(query)
+                    .bind(UserParams::from(user))
+                    .await?
+                    .take(0)
+                    .map_err(|e| write_error(e, &user.email))
+            })
+            .await;
+        self.db.invalidate_cache(&["user"]);
+
+        updated?.ok_or_else(|| UserError::NotFound(user.id.clone()))
+    }
+
+    /// Users newest first, one page at a time.
+    pub async fn list(&self, page: &PageRequest) -> UserResult<Page<User>> {
+        self.run(async {
+            let conn = self.db.get_read_connection(self.read_preference).await?;
+            Ok(PageQuery::new("user", USER_FIELDS)
+                .timeout_clause(self.timeout_clause())
+                .fetch(&conn, self.db.cursor_signer(), page)
+                .await?)
+        })
+        .await
+    }
+
+    pub async fn delete(&self, id: &str) -> UserResult<()> {
+        let query = format!("DELETE type::thing('user', $id) RETURN BEFORE{}", self.timeout_clause());
+        let deleted: UserResult<Option<surrealdb::RecordId>> = self
+            .run(async {
+                let conn = self.db.get_connection().await?;
+                Ok(conn.query(query).bind(("id", id.to_string())).await?.take((0, "id"))?)
+            })
+            .await;
+        self.db.invalidate_cache(&["user"]);
+
+        deleted?.map(|_| ()).ok_or_else(|| UserError::NotFound(id.to_string()))
+    }
+
+    /// Stream changes to the user `id`, including its deletion.
+    pub async fn watch(&self, id: &str) -> UserResult<LiveStream<User>> {
+        let stream = self.db.live_record::<StoredUser>(&Thing::from(("user", id))).await?;
+        Ok(stream.map_data(|stored| Ok(User::from(stored))))
+    }
+
+    fn timeout_clause(&self) -> String {
+        timeout_clause(self.timeout.or(self.db.query_timeout()))
+    }
+
+    async fn run<T>(&self, operation: impl Future<Output = UserResult<T>>) -> UserResult<T> {
+        self.db.with_timeout(self.timeout, operation).await
+    }
+
+    /// Like [`UserRepository::run`], going through the result cache for replica reads.
+    async fn cached<T>(&self, key: String, operation: impl Future<Output = UserResult<T>>) -> UserResult<T>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        match self.read_preference {
+            ReadPreference::Replica => self.db.cached(key, &["user"], None, self.run(operation)).await,
+            ReadPreference::Primary => self.run(operation).await,
+        }
+    }
+}
+
+/// Translate a violation of the email index into [`UserError::DuplicateEmail`].
+/// Embedded engines report it as a typed error, remote ones only as a message.
+fn write_error(error: surrealdb::Error, email: &str) -> UserError {
+    use surrealdb::error::{Api, Db};
+
+    let duplicate = match &error {
+        surrealdb::Error::Db(Db::IndexExists { index, .. }) => index == EMAIL_INDEX,
+        surrealdb::Error::Api(Api::Query(message)) => {
+            message.contains(&format!("`{}` already contains", EMAIL_INDEX))
+        }
+        _ => false,
+    };
+
+    if duplicate {
+        UserError::DuplicateEmail(email.to_string())
+    } else {
+        UserError::from(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema;
+    use crate::tests::test_db;
+
+    async fn user_db() -> DatabaseManager {
+        let db = test_db("test", "users").await;
+        schema::init_schema(&db).await.unwrap();
+        db
+    }
+
+    fn user(email: &str) -> User {
+        User::new(email.to_string(), "Jane".to_string(), "hash".to_string(), "user".to_string())
+    }
+
+    #[tokio::test]
+    async fn test_create_and_find() {
+        let db = user_db().await;
+        let users = UserRepository::new(&db);
+
+        let created = users.create(&user("jane@example.com")).await.unwrap();
+        assert_eq!(created.email, "jane@example.com");
+        assert_eq!(created.created_at, created.updated_at);
+
+        let found = users.find_by_id(&created.id).await.unwrap().unwrap();
+        assert_eq!(found, created);
+        assert_eq!(users.find_by_email("jane@example.com").await.unwrap(), Some(created));
+        assert!(users.find_by_id("missing").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_email() {
+        let db = user_db().await;
+        let users = UserRepository::new(&db);
+
+        users.create(&user("dup@example.com")).await.unwrap();
+        let result = users.create(&user("dup@example.com")).await;
+        assert!(matches!(result, Err(UserError::DuplicateEmail(email)) if email == "dup@example.com"));
+
+        let other = users.create(&user("other@example.com")).await.unwrap();
+        let result = users.update(&other.update(None, Some("dup@example.com".to_string()), None)).await;
+        assert!(matches!(result, Err(UserError::DuplicateEmail(_))));
+    }
+
+    #[tokio::test]
+    async fn test_update_bumps_timestamp() {
+        let db = user_db().await;
+        let users = UserRepository::new(&db);
+
+        let created = users.create(&user("update@example.com")).await.unwrap();
+        let updated = users
+            .update(&created.clone().update(Some("Janet".to_string()), None, Some("admin".to_string())))
+            .await
+            .unwrap();
+
+        assert_eq!(updated.name, "Janet");
+        assert_eq!(updated.role, "admin");
+        assert_eq!(updated.created_at, created.created_at);
+        assert!(updated.updated_at > created.updated_at);
+    }
+
+    #[tokio::test]
+    async fn test_missing_user() {
+        let db = user_db().await;
+        let users = UserRepository::new(&db);
+
+        let ghost = user("ghost@example.com");
+        assert!(matches!(users.update(&ghost).await, Err(UserError::NotFound(_))));
+        assert!(matches!(users.delete(&ghost.id).await, Err(UserError::NotFound(_))));
+
+        let created = users.create(&user("gone@example.com")).await.unwrap();
+        users.delete(&created.id).await.unwrap();
+        assert!(users.find_by_id(&created.id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_list_pages() {
+        let db = user_db().await;
+        let users = UserRepository::new(&db);
+        let mut created = Vec::new();
+        for n in 0..5 {
+            created.push(users.create(&user(&format!("page{}@example.com", n))).await.unwrap());
+        }
+
+        let first = users.list(&PageRequest::new(2)).await.unwrap();
+        let token = first.next_page_token.clone().unwrap();
+        // A user added mid-way sorts before the cursor and does not shift later pages
+        users.create(&user("late@example.com")).await.unwrap();
+
+        let mut listed = first.items;
+        let mut page = PageRequest::new(2).after(&token);
+        loop {
+            let next = users.list(&page).await.unwrap();
+            listed.extend(next.items);
+            match next.next_page_token {
+                Some(token) => page = PageRequest::new(2).after(&token),
+                None => break,
+            }
+        }
+
+        created.reverse();
+        assert_eq!(listed, created);
+        assert!(matches!(
+            users.list(&PageRequest::new(2).after("forged.token")).await,
+            Err(UserError::Database(DatabaseError::InvalidInput(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_watch_user() {
+        use futures::StreamExt;
+
+        let db = user_db().await;
+        let users = UserRepository::new(&db);
+        let watched = user("watched@example.com");
+        let mut stream = users.watch(&watched.id).await.unwrap();
+
+        users.create(&user("other@example.com")).await.unwrap();
+        let created = users.create(&watched).await.unwrap();
+        users.delete(&created.id).await.unwrap();
+
+        let timeout = std::time::Duration::from_secs(5);
+        let notification = tokio::time::timeout(timeout, stream.next()).await.unwrap().unwrap().unwrap();
+        assert_eq!(notification.action, crate::live::LiveAction::Create);
+        assert_eq!(notification.data, created);
+
+        let notification = tokio::time::timeout(timeout, stream.next()).await.unwrap().unwrap().unwrap();
+        assert_eq!(notification.action, crate::live::LiveAction::Delete);
+    }
+
+    #[tokio::test]
+    async fn test_cached_lookups_see_writes() {
+        let mut config = crate::db::DatabaseConfig::in_memory("test", "users");
+        config.cache.enabled = true;
+        let db = DatabaseManager::new(config).await.unwrap();
+        schema::init_schema(&db).await.unwrap();
+        let users = UserRepository::new(&db);
+
+        let created = users.create(&user("cached@example.com")).await.unwrap();
+        users.find_by_id(&created.id).await.unwrap();
+        assert_eq!(users.find_by_id(&created.id).await.unwrap(), Some(created.clone()));
+        assert_eq!(db.cache_stats().unwrap().hits, 1);
+
+        let renamed = users.update(&created.clone().update(Some("Janet".to_string()), None, None)).await.unwrap();
+        assert_eq!(users.find_by_id(&created.id).await.unwrap(), Some(renamed));
+
+        users.delete(&created.id).await.unwrap();
+        assert_eq!(users.find_by_email("cached@example.com").await.unwrap(), None);
+    }
+}